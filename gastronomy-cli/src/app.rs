@@ -6,7 +6,9 @@ use std::{collections::BTreeMap, io};
 
 use crate::utils;
 use crossterm::event::{self, Event, KeyCode, KeyEventKind};
-use gastronomy::execution_trace::{ExBudget, RawFrame};
+use gastronomy::execution_trace::{ExBudget, ExecutionOutcome, RawFrame, TraceLog};
+use gastronomy::source_map::SourceLocation;
+use gastronomy::uplc::RedeemerInfo;
 use ratatui::{
     prelude::*,
     symbols::border,
@@ -22,6 +24,7 @@ pub enum Focus {
     Term,
     Context,
     Env,
+    Logs,
 }
 impl Default for Focus {
     fn default() -> Self {
@@ -34,6 +37,7 @@ impl Display for Focus {
             Self::Term => "Term",
             Self::Context => "Context",
             Self::Env => "Env",
+            Self::Logs => "Logs",
         };
         f.write_str(str)
     }
@@ -47,6 +51,8 @@ pub struct App<'a> {
     pub frames: Vec<RawFrame<'a>>,
     pub source_files: BTreeMap<String, String>,
     pub source_token_indices: Vec<usize>,
+    pub outcome: ExecutionOutcome,
+    pub redeemer: Option<RedeemerInfo>,
     pub view_source: bool,
     pub exit: bool,
     pub focus: Focus,
@@ -54,6 +60,8 @@ pub struct App<'a> {
     pub context_scroll: u16,
     pub env_scroll: u16,
     pub return_scroll: u16,
+    /// The selected line in the log panel
+    pub log_cursor: usize,
 
     pub env_filter: Option<String>,
     pub env_depth: usize,
@@ -190,9 +198,8 @@ impl App<'_> {
                                         curr_frame
                                             .location
                                             .map(|loc| {
-                                                let (file, _, _) = parse_location(loc);
                                                 self.source_files
-                                                    .get(file)
+                                                    .get(&loc.file)
                                                     .map(|c| c.as_str())
                                                     .unwrap_or("File not found")
                                                     .to_string()
@@ -211,6 +218,13 @@ impl App<'_> {
                                     &self.env_filter,
                                     None,
                                 ),
+                                Focus::Logs => self
+                                    .outcome
+                                    .logs
+                                    .iter()
+                                    .map(|log| format!("{}: {}", log.step, log.message))
+                                    .collect::<Vec<_>>()
+                                    .join("\n"),
                             };
                             if let Err(e) = terminal_clipboard::set_string(text) {
                                 eprintln!("Could not copy to clipboard: {e}");
@@ -219,6 +233,20 @@ impl App<'_> {
                         KeyCode::Char('v') => {
                             self.view_source = !self.view_source;
                         }
+                        KeyCode::Char('L') | KeyCode::Char('l') => {
+                            let logs = &self.outcome.logs;
+                            if let Some(next) = logs.iter().position(|log| log.step > self.cursor) {
+                                self.cursor = logs[next].step;
+                                self.log_cursor = next;
+                            }
+                        }
+                        KeyCode::Enter => {
+                            if self.focus == Focus::Logs
+                                && let Some(log) = self.outcome.logs.get(self.log_cursor)
+                            {
+                                self.cursor = log.step;
+                            }
+                        }
                         KeyCode::Char('i') => {
                             if self.focus == Focus::Env {
                                 self.env_filter = Some("i_".to_string());
@@ -227,7 +255,8 @@ impl App<'_> {
                         KeyCode::Tab => match self.focus {
                             Focus::Term => self.focus = Focus::Context,
                             Focus::Context => self.focus = Focus::Env,
-                            Focus::Env => self.focus = Focus::Term,
+                            Focus::Env if !self.outcome.logs.is_empty() => self.focus = Focus::Logs,
+                            Focus::Env | Focus::Logs => self.focus = Focus::Term,
                         },
                         KeyCode::Up => match self.focus {
                             Focus::Term => self.term_scroll = self.term_scroll.saturating_sub(1),
@@ -235,6 +264,7 @@ impl App<'_> {
                                 self.context_scroll = self.context_scroll.saturating_sub(1)
                             }
                             Focus::Env => self.env_scroll = self.env_scroll.saturating_sub(1),
+                            Focus::Logs => self.log_cursor = self.log_cursor.saturating_sub(1),
                         },
                         KeyCode::Down => match self.focus {
                             Focus::Term => self.term_scroll = self.term_scroll.saturating_add(1),
@@ -242,6 +272,11 @@ impl App<'_> {
                                 self.context_scroll = self.context_scroll.saturating_add(1)
                             }
                             Focus::Env => self.env_scroll = self.env_scroll.saturating_add(1),
+                            Focus::Logs => {
+                                if self.log_cursor + 1 < self.outcome.logs.len() {
+                                    self.log_cursor += 1;
+                                }
+                            }
                         },
                         _ => {}
                     }
@@ -266,6 +301,7 @@ impl Widget for &mut App<'_> {
         let layout = render_block_region(
             self.file_name.clone(),
             self.index,
+            &self.outcome,
             location,
             area,
             self.focus,
@@ -274,7 +310,16 @@ impl Widget for &mut App<'_> {
 
         let gauge_region = layout[0];
         let command_region = layout[1];
-        let main_region = layout[2];
+        let main_region = if let Some(redeemer) = &self.redeemer {
+            let layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(vec![Constraint::Length(1), Constraint::Percentage(100)])
+                .split(layout[2]);
+            render_redeemer_region(redeemer, &self.outcome, layout[0], buf);
+            layout[1]
+        } else {
+            layout[2]
+        };
 
         render_gauge_region(self.cursor, &self.frames, gauge_region, buf);
 
@@ -289,6 +334,15 @@ impl Widget for &mut App<'_> {
             .split(layout[1]);
         let context_region = layout[0];
         let env_region = layout[1];
+        let (context_region, logs_region) = if self.outcome.logs.is_empty() {
+            (context_region, None)
+        } else {
+            let layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(context_region);
+            (layout[0], Some(layout[1]))
+        };
 
         render_command_region(label, self.cursor, &self.frames, command_region, buf);
         render_term_region(
@@ -317,6 +371,16 @@ impl Widget for &mut App<'_> {
             env_region,
             buf,
         );
+        if let Some(logs_region) = logs_region {
+            render_logs_region(
+                self.focus,
+                &self.outcome.logs,
+                self.cursor,
+                self.log_cursor,
+                logs_region,
+                buf,
+            );
+        }
         render_clear_popup_region(area, ret_value, self.ret_depth, buf);
     }
 }
@@ -324,7 +388,8 @@ impl Widget for &mut App<'_> {
 fn render_block_region(
     file_name: PathBuf,
     index: Option<usize>,
-    location: Option<&String>,
+    outcome: &ExecutionOutcome,
+    location: Option<&SourceLocation>,
     area: Rect,
     focus: Focus,
     buf: &mut Buffer,
@@ -335,11 +400,26 @@ fn render_block_region(
         index.map(|i| format!(" #{i}")).unwrap_or_default().bold(),
         ")".bold(),
     ]);
+    let result = match &outcome.error {
+        Some(error) => Line::from(vec![
+            format!(" {} at step {}: ", error.kind, error.step)
+                .red()
+                .bold(),
+            format!("{} ", error.message.lines().next().unwrap_or_default()).red(),
+        ]),
+        None => Line::from(vec![
+            " Result: ".green().bold(),
+            format!("{} ", outcome.result.as_deref().unwrap_or_default()).green(),
+        ]),
+    };
     let mut instructions = if location.is_some() {
         vec![" View Source ".into(), "<V>".blue().bold()]
     } else {
         vec![]
     };
+    if !outcome.logs.is_empty() {
+        instructions.extend([" Next Log ".into(), "<L>".blue().bold()]);
+    }
     instructions.extend([
         format!(" Copy {focus} ").into(),
         "<C>".blue().bold(),
@@ -354,6 +434,7 @@ fn render_block_region(
 
     let block = Block::default()
         .title(title.centered())
+        .title(result.right_aligned())
         .title_bottom(instructions.centered())
         .borders(Borders::ALL)
         .border_set(border::THICK);
@@ -443,11 +524,54 @@ fn render_command_region(
     .render(command_region, buf);
 }
 
+fn render_redeemer_region(
+    redeemer: &RedeemerInfo,
+    outcome: &ExecutionOutcome,
+    redeemer_region: Rect,
+    buf: &mut Buffer,
+) {
+    let mut line = vec![
+        "Redeemer: ".into(),
+        redeemer
+            .to_string()
+            .fg(Color::Blue)
+            .add_modifier(Modifier::BOLD),
+    ];
+    if let Some(script_hash) = &redeemer.script_hash {
+        line.extend([" Script: ".into(), script_hash.clone().fg(Color::Blue)]);
+    }
+    Line::from(line).left_aligned().render(redeemer_region, buf);
+
+    let declared = redeemer.declared;
+    let (steps_headroom, mem_headroom) = declared.headroom(outcome.spent);
+    Line::from(vec![
+        "Declared: ".into(),
+        format!("{} steps ", declared.steps)
+            .fg(Color::Blue)
+            .add_modifier(Modifier::BOLD),
+        headroom_span(steps_headroom),
+        format!("{} mem ", declared.mem)
+            .fg(Color::Blue)
+            .add_modifier(Modifier::BOLD),
+        headroom_span(mem_headroom),
+    ])
+    .right_aligned()
+    .render(redeemer_region, buf);
+}
+
+fn headroom_span(headroom: Option<f64>) -> Span<'static> {
+    match headroom {
+        Some(headroom) if headroom >= 0.0 => format!("({:.1}% left) ", headroom).fg(Color::Green),
+        Some(headroom) => format!("({:.1}% over) ", -headroom).fg(Color::Red),
+        None => "".into(),
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn render_term_region(
     focus: Focus,
     term: &IndexedTerm<NamedDeBruijn>,
-    location: Option<&String>,
+    location: Option<&SourceLocation>,
     source_files: &BTreeMap<String, String>,
     mut term_scroll: u16,
     view_source: bool,
@@ -470,15 +594,13 @@ fn render_term_region(
         let mut term_text = String::new();
 
         let term_lines = if view_source {
-            let (file, line, column) = parse_location(location);
-
             let old_term_text = source_files
-                .get(file)
+                .get(&location.file)
                 .map(|c| c.as_str())
                 .unwrap_or("File not found");
             // to highlight lines properly, each line needs to take up the full width of its region
             term_text = pad_lines_with_spaces(old_term_text, term_region.width as usize);
-            highlight_text(&term_text, line, column)
+            highlight_text(&term_text, location)
         } else {
             term_text = term.to_pretty(100);
             split_text(&term_text)
@@ -517,13 +639,19 @@ fn render_term_region(
     }
 }
 
-fn render_source_region(location: &str, source_region: Rect, buf: &mut Buffer) {
+fn render_source_region(location: &SourceLocation, source_region: Rect, buf: &mut Buffer) {
     let source_block = Block::default()
         .title(" Source Location ".fg(Color::Reset))
         .borders(Borders::TOP | Borders::LEFT | Borders::BOTTOM)
         .border_set(border::PLAIN);
 
-    Paragraph::new(location)
+    // a breadcrumb of the function being evaluated, when the source map names it
+    let mut breadcrumb = vec![];
+    if let Some(function) = &location.function {
+        breadcrumb.extend([function.as_str().blue().bold(), " › ".into()]);
+    }
+    breadcrumb.push(location.to_string().into());
+    Paragraph::new(Line::from(breadcrumb))
         .block(source_block)
         .render(source_region, buf);
 }
@@ -560,6 +688,62 @@ fn render_context_region(
         .render(context_region, buf);
 }
 
+fn render_logs_region(
+    focus: Focus,
+    logs: &[TraceLog],
+    cursor: usize,
+    log_cursor: usize,
+    logs_region: Rect,
+    buf: &mut Buffer,
+) {
+    let collapsed_top_border_set = symbols::border::Set {
+        top_left: symbols::line::NORMAL.vertical_right,
+        top_right: symbols::line::NORMAL.vertical_left,
+        ..symbols::border::PLAIN
+    };
+    let logs_block = Block::default()
+        .title(
+            format!(" Logs ({}) ", logs.len()).fg(if focus == Focus::Logs {
+                Color::Blue
+            } else {
+                Color::Reset
+            }),
+        )
+        .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT)
+        .border_set(collapsed_top_border_set);
+
+    // logs from steps still to come are dimmed, and those from this step stand out
+    let lines: Vec<Line> = logs
+        .iter()
+        .enumerate()
+        .map(|(index, log)| {
+            let style = if log.step == cursor {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else if log.step > cursor {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            let style = if focus == Focus::Logs && index == log_cursor {
+                style.add_modifier(Modifier::REVERSED)
+            } else {
+                style
+            };
+            Line::styled(format!("{:>7} {}", log.step, log.message), style)
+        })
+        .collect();
+
+    // keep the selected log in view
+    let height = logs_region.height.saturating_sub(1) as usize;
+    let scroll = (log_cursor + 1).saturating_sub(height) as u16;
+    Paragraph::new(lines)
+        .block(logs_block)
+        .scroll((scroll, 0))
+        .render(logs_region, buf);
+}
+
 fn render_env_region(
     env: &Env,
     depth: usize,
@@ -652,31 +836,42 @@ fn pad_lines_with_spaces(text: &str, line_width: usize) -> String {
     result.join("\n")
 }
 
-fn highlight_text(text: &str, line: usize, column: usize) -> Vec<Line<'_>> {
+/// Highlights the lines of the span being evaluated, and underlines the span itself
+fn highlight_text<'a>(text: &'a str, location: &SourceLocation) -> Vec<Line<'a>> {
+    let (end_line, end_column) = location.end();
     text.split('\n')
         .enumerate()
         .map(|(line_number, line_text)| {
-            if line_number + 1 != line {
-                line_text.into()
-            } else {
-                let (before, at_after) = line_text.split_at(column - 1);
-                let (at, after) = at_after.split_at(1);
-
-                vec![
-                    before.bg(Color::DarkGray),
-                    at.bg(Color::Gray).underlined(),
-                    after.bg(Color::DarkGray),
-                ]
-                .into()
+            let line_number = line_number + 1;
+            if line_number < location.line || line_number > end_line {
+                return line_text.into();
             }
+            let start = if line_number == location.line {
+                byte_offset(line_text, location.column.saturating_sub(1))
+            } else {
+                0
+            };
+            let end = if line_number == end_line {
+                byte_offset(line_text, end_column).max(start)
+            } else {
+                line_text.trim_end().len().max(start)
+            };
+            let (before, at_after) = line_text.split_at(start);
+            let (at, after) = at_after.split_at(end - start);
+
+            vec![
+                before.bg(Color::DarkGray),
+                at.bg(Color::Gray).underlined(),
+                after.bg(Color::DarkGray),
+            ]
+            .into()
         })
         .collect()
 }
 
-fn parse_location(location: &str) -> (&str, usize, usize) {
-    let mut pieces = location.split(":");
-    let file = pieces.next().unwrap();
-    let line = pieces.next().unwrap().parse().unwrap();
-    let column = pieces.next().unwrap().parse().unwrap();
-    (file, line, column)
+/// The byte offset of a character in a line, or the line's end if it's shorter
+fn byte_offset(line: &str, chars: usize) -> usize {
+    line.char_indices()
+        .nth(chars)
+        .map_or(line.len(), |(offset, _)| offset)
 }
@@ -1,17 +1,20 @@
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
 
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
 use app::App;
 use clap::{Parser, Subcommand};
 use figment::providers::Env;
 use gastronomy::{
-    chain_query::ChainQuery,
+    chain_query::{self, Cache, default_cache_dir},
     compute_script_overrides,
-    config::{Config, load_base_config},
+    config::{Config, CustomNetworkConfig, load_base_config},
     parse_script_overrides,
+    protocol_parameters::ProtocolParameters,
+    uplc::{FileFormat, LoadOptions, StartingBudget},
 };
 
 mod app;
+mod summary;
 mod utils;
 
 #[derive(Parser, Debug)]
@@ -24,12 +27,20 @@ struct Args {
 #[derive(Subcommand, Debug)]
 enum Commands {
     Run {
+        /// The file to run, an aiken project directory, or - to read it from stdin
         file: PathBuf,
         parameters: Vec<String>,
         #[clap(long)]
         index: Option<usize>,
+        /// Run every redeemer in the transaction, and summarize how each went
+        #[clap(long, conflicts_with = "index")]
+        all: bool,
         #[clap(long)]
         source_root: Option<PathBuf>,
+        /// The title of the validator to run, when FILE is a blueprint (e.g. `pool.spend`),
+        /// or of a validator or function (e.g. `pool.can_swap`) when it's an aiken project
+        #[clap(long)]
+        validator: Option<String>,
         /// A Cardano blueprint JSON file containing the overriding scripts, if applicable (defaults to plutus.json)
         #[clap(long, value_name = "FILEPATH")]
         blueprint: Option<PathBuf>,
@@ -38,6 +49,60 @@ enum Commands {
         /// *Only supported by transaction ID and transaction files*
         #[clap(long("script-override"), value_name = "FROM:TO:VERSION", num_args(0..), verbatim_doc_comment)]
         script_overrides: Vec<String>,
+        /// Resolve transaction inputs from a local file instead of querying the chain
+        /// (CBOR input/output pairs, or the JSON written by `cardano-cli query utxo --out-file`).
+        /// Needs --network, and uses default protocol parameters unless --protocol-params is given
+        #[clap(long, value_name = "FILEPATH", verbatim_doc_comment)]
+        utxos: Option<PathBuf>,
+        /// Only serve chain data from the local cache, without querying the configured provider
+        #[clap(long)]
+        offline: bool,
+        /// The network to query: preview, preprod, mainnet,
+        /// or the name of a custom network described by the customNetwork settings
+        /// or the flags below
+        #[clap(long, verbatim_doc_comment)]
+        network: Option<String>,
+        /// The unix time (in milliseconds) of the custom network's first slot
+        #[clap(long, value_name = "MILLIS")]
+        zero_time: Option<u64>,
+        /// The number of the custom network's first slot
+        #[clap(long, value_name = "SLOT")]
+        zero_slot: Option<u64>,
+        /// The length of the custom network's slots, in milliseconds
+        #[clap(long, value_name = "MILLIS")]
+        slot_length: Option<u32>,
+        /// The provider endpoint for the custom network
+        #[clap(long, value_name = "URL")]
+        network_url: Option<String>,
+        /// Evaluate transactions with the protocol parameters in this file
+        /// (the JSON written by `cardano-cli query protocol-parameters`),
+        /// instead of fetching the current ones
+        #[clap(long("protocol-params"), value_name = "FILEPATH", verbatim_doc_comment)]
+        protocol_parameters: Option<PathBuf>,
+        /// Evaluate the transaction with the redeemers in this file, in place of its own
+        /// (a JSON list of {"purpose", "index", "data", "exUnits"}, or their CBOR)
+        #[clap(long, value_name = "FILEPATH", verbatim_doc_comment)]
+        redeemers: Option<PathBuf>,
+        /// Add the datums in this file to the transaction's witness set
+        /// (a JSON list of datums, or their CBOR)
+        #[clap(long, value_name = "FILEPATH", verbatim_doc_comment)]
+        datums: Option<PathBuf>,
+        /// Read FILE as this format (uplc, flat, tx or json), instead of working it out
+        #[clap(long)]
+        format: Option<FileFormat>,
+        /// Step through sources with this source map, for scripts from toolchains other than aiken
+        #[clap(long, value_name = "FILEPATH")]
+        source_map: Option<PathBuf>,
+        /// The budget to start the program with: max (the protocol's per-transaction limit),
+        /// declared (the ex-units its redeemer declares), or STEPS,MEM
+        #[clap(long, verbatim_doc_comment)]
+        budget: Option<String>,
+    },
+    /// Remove cached chain query responses
+    PruneCache {
+        /// Only remove entries older than this many days
+        #[clap(long, value_name = "DAYS")]
+        older_than: Option<u64>,
     },
 }
 
@@ -48,32 +113,116 @@ fn load_config() -> Result<Config> {
     Ok(config)
 }
 
+/// Describes the custom network with the flags given, on top of its customNetwork settings
+fn describe_custom_network(
+    config: &mut Config,
+    zero_time: Option<u64>,
+    zero_slot: Option<u64>,
+    slot_length: Option<u32>,
+    url: Option<String>,
+) -> Result<()> {
+    if zero_time.is_none() && zero_slot.is_none() && slot_length.is_none() && url.is_none() {
+        return Ok(());
+    }
+    let configured = config.custom_network.take();
+    let missing = |flag| {
+        anyhow!(
+            "a custom network needs {} too, unless the customNetwork settings give it",
+            flag
+        )
+    };
+    config.custom_network = Some(CustomNetworkConfig {
+        zero_time: zero_time
+            .or(configured.as_ref().map(|custom| custom.zero_time))
+            .ok_or_else(|| missing("--zero-time"))?,
+        zero_slot: zero_slot
+            .or(configured.as_ref().map(|custom| custom.zero_slot))
+            .ok_or_else(|| missing("--zero-slot"))?,
+        slot_length: slot_length
+            .or(configured.as_ref().map(|custom| custom.slot_length))
+            .ok_or_else(|| missing("--slot-length"))?,
+        url: url.or(configured.and_then(|custom| custom.url)),
+    });
+    Ok(())
+}
+
 async fn run() -> Result<(), anyhow::Error> {
     utils::install_hooks().unwrap();
 
     let args = Args::parse();
-    let config = load_config()?;
-
-    let query = if let Some(blockfrost) = &config.blockfrost {
-        ChainQuery::blockfrost(blockfrost)
-    } else {
-        ChainQuery::None
-    };
+    let mut config = load_config()?;
 
     match args.command {
         Some(Commands::Run {
             file,
             parameters,
             index,
+            all,
             source_root,
+            validator,
             blueprint,
             script_overrides,
+            utxos,
+            offline,
+            network,
+            zero_time,
+            zero_slot,
+            slot_length,
+            network_url,
+            protocol_parameters,
+            redeemers,
+            datums,
+            format,
+            source_map,
+            budget,
         }) => {
+            if network.is_some() {
+                config.network = network;
+            }
+            describe_custom_network(&mut config, zero_time, zero_slot, slot_length, network_url)?;
+            if utxos.is_some() {
+                config.utxos_file = utxos;
+            }
+            if offline {
+                config.offline = Some(true);
+            }
+            if protocol_parameters.is_some() {
+                config.protocol_parameters_file = protocol_parameters;
+            }
+            if budget.is_some() {
+                config.budget = budget;
+            }
+            let query = chain_query::from_config(&config)?;
+            let protocol_parameters = config
+                .protocol_parameters_file
+                .as_deref()
+                .map(ProtocolParameters::load)
+                .transpose()?;
+
             let overrides =
                 compute_script_overrides(parse_script_overrides(script_overrides)?, blueprint)?;
 
+            let options = LoadOptions {
+                script_overrides: overrides,
+                protocol_parameters,
+                validator,
+                redeemers,
+                datums,
+                format,
+                source_map,
+                budget: config
+                    .budget
+                    .as_deref()
+                    .map(str::parse::<StartingBudget>)
+                    .transpose()
+                    .map_err(anyhow::Error::msg)?
+                    .unwrap_or_default(),
+            };
             let mut raw_programs =
-                gastronomy::uplc::load_programs_from_file(&file, query, overrides).await?;
+                gastronomy::uplc::load_programs_from_file(&file, query.as_ref(), options).await?;
+            if all {
+                return summary::run_all(raw_programs, &parameters);
+            }
             let index = index.or(if raw_programs.len() == 1 {
                 None
             } else {
@@ -87,16 +236,24 @@ async fn run() -> Result<(), anyhow::Error> {
                 );
             }
             let raw_program = raw_programs.remove(index.unwrap_or_default());
-            let arguments = parameters
-                .iter()
-                .enumerate()
-                .map(|(index, param)| gastronomy::uplc::parse_parameter(index, param.clone()))
-                .collect::<Result<Vec<_>>>()?;
+            let arguments = gastronomy::uplc::parse_parameters(&raw_program, &parameters)?;
             let applied_program = gastronomy::uplc::apply_parameters(raw_program, arguments)?;
-            let states = gastronomy::uplc::execute_program(applied_program.program)?;
-            let frames =
-                gastronomy::execution_trace::parse_raw_frames(&states, &applied_program.source_map);
+            let (states, outcome) = gastronomy::uplc::execute_program(
+                applied_program.program,
+                applied_program.language,
+                applied_program.is_validator,
+                applied_program.cost_model,
+                applied_program.budget,
+            )?;
+            let frames = gastronomy::execution_trace::parse_raw_frames(
+                &states,
+                &applied_program.source_map,
+                applied_program.budget,
+                &outcome.logs,
+            );
 
+            // programs compiled from an aiken project know where their sources are
+            let source_root = source_root.or(applied_program.source_root);
             let source_files = if let Some(source_root) = source_root {
                 gastronomy::execution_trace::read_source_files(&source_root, &frames)
             } else {
@@ -113,6 +270,8 @@ async fn run() -> Result<(), anyhow::Error> {
                 frames,
                 source_files,
                 source_token_indices,
+                outcome,
+                redeemer: applied_program.redeemer,
                 exit: false,
                 env_depth: 10,
                 ret_depth: 10,
@@ -122,6 +281,19 @@ async fn run() -> Result<(), anyhow::Error> {
             utils::restore().and(app_result)?;
             Ok(())
         }
+        Some(Commands::PruneCache { older_than }) => {
+            let Some(cache_dir) = config.cache_dir.or_else(default_cache_dir) else {
+                bail!("Could not find a cache directory");
+            };
+            let max_age = older_than.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+            let removed = Cache::prune(&cache_dir, max_age)?;
+            println!(
+                "Removed {} cached entries from {}",
+                removed,
+                cache_dir.display()
+            );
+            Ok(())
+        }
         None => {
             println!("No command provided");
             Ok(())
@@ -0,0 +1,199 @@
+use anyhow::{Result, bail};
+use gastronomy::{
+    ExecutionOutcome,
+    execution_trace::ExecutionErrorKind,
+    uplc::{self, LoadedProgram, RedeemerInfo},
+};
+
+const HEADERS: [&str; 7] = [
+    "#", "Redeemer", "Script", "Result", "Steps", "Mem", "Message",
+];
+
+/// Runs every program, and prints a line for each with how it went.
+/// Fails if any of them did, so scripts can check a transaction with it.
+pub fn run_all(programs: Vec<LoadedProgram>, parameters: &[String]) -> Result<()> {
+    let results = evaluate(programs, parameters)?;
+    let rows: Vec<_> = results
+        .iter()
+        .enumerate()
+        .map(|(index, (redeemer, outcome))| row(index, redeemer.as_ref(), outcome))
+        .collect();
+    print_table(&rows);
+    check(&results)
+}
+
+fn evaluate(
+    programs: Vec<LoadedProgram>,
+    parameters: &[String],
+) -> Result<Vec<(Option<RedeemerInfo>, ExecutionOutcome)>> {
+    let mut results = vec![];
+    for raw_program in programs {
+        let arguments = uplc::parse_parameters(&raw_program, parameters)?;
+        let program = uplc::apply_parameters(raw_program, arguments)?;
+        let (_, outcome) = uplc::execute_program(
+            program.program,
+            program.language,
+            program.is_validator,
+            program.cost_model,
+            program.budget,
+        )?;
+        results.push((program.redeemer, outcome));
+    }
+    Ok(results)
+}
+
+fn check(results: &[(Option<RedeemerInfo>, ExecutionOutcome)]) -> Result<()> {
+    let failed: Vec<_> = results
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, outcome))| !outcome.succeeded())
+        .map(|(index, _)| index)
+        .collect();
+    if let Some(first) = failed.first() {
+        bail!(
+            "{} of {} failed, step through one with --index {}",
+            failed.len(),
+            results.len(),
+            first
+        );
+    }
+    Ok(())
+}
+
+fn row(index: usize, redeemer: Option<&RedeemerInfo>, outcome: &ExecutionOutcome) -> [String; 7] {
+    let spent = |spent: i64, declared: Option<i64>| match declared {
+        Some(declared) => format!("{}/{}", spent, declared),
+        None => spent.to_string(),
+    };
+    // traces usually say why a script failed better than the machine does,
+    // unless it finished, and only returned the wrong thing
+    let message = match (&outcome.error, outcome.logs.last()) {
+        (Some(error), Some(log)) if error.kind != ExecutionErrorKind::NotUnit => {
+            format!("{}: {}", error.kind, log.message)
+        }
+        (Some(error), _) => {
+            let message = error.message.lines().next().unwrap_or_default();
+            format!("{}: {}", error.kind, message)
+        }
+        (None, log) => log.map(|log| log.message.clone()).unwrap_or_default(),
+    };
+    [
+        index.to_string(),
+        redeemer.map(|r| r.to_string()).unwrap_or_default(),
+        redeemer
+            .and_then(|r| r.script_hash.clone())
+            .unwrap_or_default(),
+        if outcome.succeeded() { "pass" } else { "fail" }.to_string(),
+        spent(outcome.spent.steps, redeemer.map(|r| r.declared.steps)),
+        spent(outcome.spent.mem, redeemer.map(|r| r.declared.mem)),
+        message,
+    ]
+}
+
+fn print_table(rows: &[[String; 7]]) {
+    let mut widths = HEADERS.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let print_row = |cells: [&str; 7]| {
+        let line: Vec<_> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+    print_row(HEADERS);
+    for row in rows {
+        print_row(row.each_ref().map(String::as_str));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ::uplc::ast::{Name, NamedDeBruijn};
+    use gastronomy::{
+        execution_trace::ExUnits,
+        uplc::{CostModel, ExBudget, Language, Program},
+    };
+
+    use super::*;
+
+    /// A PlutusV3 validator run by a spend redeemer, as if loaded from a transaction
+    fn spend(index: u32, source: &str) -> LoadedProgram {
+        let program: Program<Name> = ::uplc::parser::program(source).unwrap();
+        let program: Program<NamedDeBruijn> = program.try_into().unwrap();
+        LoadedProgram {
+            filename: "tx.cbor".to_string(),
+            program,
+            source_map: BTreeMap::new(),
+            language: Language::PlutusV3,
+            is_validator: true,
+            cost_model: CostModel::default(),
+            budget: ExBudget::default(),
+            parameters: vec![],
+            source_root: None,
+            redeemer: Some(RedeemerInfo {
+                purpose: "spend".to_string(),
+                index,
+                target: None,
+                script_hash: None,
+                declared: ExUnits {
+                    steps: 1_000_000,
+                    mem: 10_000,
+                },
+            }),
+        }
+    }
+
+    #[test]
+    fn reports_each_redeemer_and_fails_if_any_did() {
+        let traces_and_fails = r#"(program 1.1.0
+            (force [(force (builtin trace)) (con string "nope") (delay (error))]))"#;
+        let programs = vec![
+            spend(0, "(program 1.1.0 (con unit ()))"),
+            spend(1, "(program 1.1.0 (con integer 42))"),
+            spend(2, traces_and_fails),
+        ];
+        let results = evaluate(programs, &[]).unwrap();
+        let rows: Vec<_> = results
+            .iter()
+            .enumerate()
+            .map(|(index, (redeemer, outcome))| row(index, redeemer.as_ref(), outcome))
+            .collect();
+
+        let column = |index: usize| {
+            rows.iter()
+                .map(|row| row[index].as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(column(1), ["spend #0", "spend #1", "spend #2"]);
+        assert_eq!(column(3), ["pass", "fail", "fail"]);
+        assert!(
+            rows[1][6].starts_with("Didn't return unit"),
+            "{}",
+            rows[1][6]
+        );
+        assert_eq!(rows[2][6], "Explicit error: nope");
+
+        let error = check(&results).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "2 of 3 failed, step through one with --index 1"
+        );
+    }
+
+    #[test]
+    fn passes_when_every_redeemer_does() {
+        let programs = vec![
+            spend(0, "(program 1.1.0 (con unit ()))"),
+            spend(1, "(program 1.1.0 [(lam x (con unit ())) (con integer 1)])"),
+        ];
+        let results = evaluate(programs, &[]).unwrap();
+        assert!(check(&results).is_ok());
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use gastronomy::Frame;
+use gastronomy::{ExecutionOutcome, Frame, execution_trace::TraceLog, uplc::RedeemerInfo};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -14,6 +14,8 @@ pub struct CreateTraceResponse {
 pub struct GetTraceSummaryResponse {
     pub frame_count: usize,
     pub source_token_indices: Vec<usize>,
+    pub outcome: ExecutionOutcome,
+    pub redeemer: Option<RedeemerInfo>,
 }
 
 #[derive(Serialize)]
@@ -22,6 +24,12 @@ pub struct GetFrameResponse {
     pub frame: Frame,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetLogsResponse {
+    pub logs: Vec<TraceLog>,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetSourceResponse {
@@ -5,12 +5,13 @@ use std::{
 };
 
 use gastronomy::{
-    Frame,
+    ExecutionOutcome, Frame,
     execution_trace::{
-        RawFrame, find_source_token_indices, parse_context, parse_env, parse_raw_frames,
+        RawFrame, TraceLog, find_source_token_indices, parse_context, parse_env, parse_raw_frames,
         parse_uplc_value, read_source_files,
     },
-    uplc::{self, LoadedProgram, Program},
+    source_map::SourceLocation,
+    uplc::{self, CostModel, ExBudget, Language, LoadedProgram, Program, RedeemerInfo},
 };
 use pallas_codec::flat::Flat;
 use tauri::ipc::InvokeError;
@@ -19,6 +20,7 @@ use uuid::Uuid;
 
 pub struct ExecutionTrace {
     pub identifier: String,
+    pub redeemer: Option<RedeemerInfo>,
     worker_channel: mpsc::Sender<WorkerRequest>,
 }
 
@@ -33,6 +35,10 @@ impl ExecutionTrace {
         let worker = ExecutionTraceWorker {
             raw_program: program.program.to_flat().map_err(to_invoke_error)?,
             source_map: program.source_map,
+            language: program.language,
+            is_validator: program.is_validator,
+            cost_model: program.cost_model,
+            budget: program.budget,
             requests,
         };
         std::thread::Builder::new()
@@ -43,10 +49,11 @@ impl ExecutionTrace {
 
         Ok(Self {
             identifier,
+            redeemer: program.redeemer,
             worker_channel,
         })
     }
-    pub async fn get_trace_summary(&self) -> Result<(usize, Vec<usize>), InvokeError> {
+    pub async fn get_trace_summary(&self) -> Result<TraceSummary, InvokeError> {
         let (summary_sink, summary_source) = oneshot::channel();
         let request = WorkerRequest::GetTraceSummary(summary_sink);
         self.worker_channel
@@ -64,6 +71,15 @@ impl ExecutionTrace {
             .map_err(to_invoke_error)?;
         frame_source.await.map_err(to_invoke_error)?
     }
+    pub async fn get_logs(&self) -> Result<Vec<TraceLog>, InvokeError> {
+        let (logs_sink, logs_source) = oneshot::channel();
+        let request = WorkerRequest::GetLogs(logs_sink);
+        self.worker_channel
+            .send(request)
+            .await
+            .map_err(to_invoke_error)?;
+        logs_source.await.map_err(to_invoke_error)?
+    }
     pub async fn read_source_files(
         &self,
         source_root: &Path,
@@ -82,35 +98,52 @@ fn to_invoke_error<T: Display>(err: T) -> InvokeError {
     InvokeError::from(err.to_string())
 }
 
+type TraceSummary = (usize, Vec<usize>, ExecutionOutcome);
+
 type ResponseChannel<T> = oneshot::Sender<Result<T, InvokeError>>;
 
 enum WorkerRequest {
-    GetTraceSummary(ResponseChannel<(usize, Vec<usize>)>),
+    GetTraceSummary(ResponseChannel<TraceSummary>),
     GetFrame(usize, ResponseChannel<Frame>),
+    GetLogs(ResponseChannel<Vec<TraceLog>>),
     ReadSourceFiles(PathBuf, ResponseChannel<BTreeMap<String, String>>),
 }
 
 struct ExecutionTraceWorker {
     raw_program: Vec<u8>,
-    source_map: BTreeMap<u64, String>,
+    source_map: BTreeMap<u64, SourceLocation>,
+    language: Language,
+    is_validator: bool,
+    cost_model: CostModel,
+    budget: ExBudget,
     requests: mpsc::Receiver<WorkerRequest>,
 }
 
 impl ExecutionTraceWorker {
     fn run(self) {
         let program = Program::unflat(&self.raw_program).unwrap();
-        let states = uplc::execute_program(program).unwrap();
-        let frames = parse_raw_frames(&states, &self.source_map);
+        let (states, outcome) = uplc::execute_program(
+            program,
+            self.language,
+            self.is_validator,
+            self.cost_model,
+            self.budget,
+        )
+        .unwrap();
+        let frames = parse_raw_frames(&states, &self.source_map, self.budget, &outcome.logs);
 
         let mut requests = self.requests;
         while let Some(request) = requests.blocking_recv() {
             match request {
                 WorkerRequest::GetTraceSummary(res) => {
-                    let _ = res.send(Self::get_trace_summary(&frames));
+                    let _ = res.send(Self::get_trace_summary(&frames, &outcome));
                 }
                 WorkerRequest::GetFrame(index, res) => {
                     let _ = res.send(Self::get_frame(index, &frames));
                 }
+                WorkerRequest::GetLogs(res) => {
+                    let _ = res.send(Ok(outcome.logs.clone()));
+                }
                 WorkerRequest::ReadSourceFiles(source_root, res) => {
                     let _ = res.send(Self::read_source_files(&source_root, &frames));
                 }
@@ -118,10 +151,13 @@ impl ExecutionTraceWorker {
         }
     }
 
-    fn get_trace_summary(frames: &[RawFrame<'_>]) -> Result<(usize, Vec<usize>), InvokeError> {
+    fn get_trace_summary(
+        frames: &[RawFrame<'_>],
+        outcome: &ExecutionOutcome,
+    ) -> Result<TraceSummary, InvokeError> {
         let frame_count = frames.len();
         let source_token_indices = find_source_token_indices(frames);
-        Ok((frame_count, source_token_indices))
+        Ok((frame_count, source_token_indices, outcome.clone()))
     }
 
     fn get_frame(index: usize, frames: &[RawFrame<'_>]) -> Result<Frame, InvokeError> {
@@ -136,6 +172,7 @@ impl ExecutionTraceWorker {
             ret_value: raw.ret_value.map(|v| parse_uplc_value(v.clone())),
             location: raw.location.cloned(),
             budget: raw.budget.clone(),
+            logs: raw.logs.clone(),
         };
         Ok(frame)
     }
@@ -3,14 +3,18 @@
 
 use std::{collections::HashMap, path::Path};
 
-use api::{CreateTraceResponse, GetFrameResponse, GetSourceResponse, GetTraceSummaryResponse};
+use api::{
+    CreateTraceResponse, GetFrameResponse, GetLogsResponse, GetSourceResponse,
+    GetTraceSummaryResponse,
+};
 use dashmap::DashMap;
 use execution_trace::ExecutionTrace;
 use figment::providers::{Env, Serialized};
 use gastronomy::{
-    chain_query::ChainQuery,
-    compute_script_overrides,
+    chain_query, compute_script_overrides,
     config::{Config, ScriptOverride, load_base_config},
+    protocol_parameters::ProtocolParameters,
+    uplc::{LoadOptions, StartingBudget},
 };
 use tauri::{State, ipc::InvokeError};
 use tauri_plugin_store::StoreExt;
@@ -41,6 +45,7 @@ fn load_config(app_handle: &tauri::AppHandle) -> Result<Config, InvokeError> {
 async fn create_traces(
     file: &Path,
     parameters: Vec<String>,
+    validator: Option<String>,
     state: State<'_, SessionState>,
     app_handle: tauri::AppHandle,
 ) -> Result<CreateTraceResponse, InvokeError> {
@@ -48,11 +53,13 @@ async fn create_traces(
 
     let config = load_config(&app_handle)?;
 
-    let query = if let Some(blockfrost) = &config.blockfrost {
-        ChainQuery::blockfrost(blockfrost)
-    } else {
-        ChainQuery::None
-    };
+    let query = chain_query::from_config(&config).map_err(InvokeError::from_anyhow)?;
+    let protocol_parameters = config
+        .protocol_parameters_file
+        .as_deref()
+        .map(ProtocolParameters::load)
+        .transpose()
+        .map_err(InvokeError::from_anyhow)?;
 
     let script_overrides = if let Some(script_overrides) = config.script_overrides {
         compute_script_overrides(
@@ -68,8 +75,23 @@ async fn create_traces(
         HashMap::new()
     };
 
+    let budget = config
+        .budget
+        .as_deref()
+        .map(str::parse::<StartingBudget>)
+        .transpose()
+        .map_err(InvokeError::from)?
+        .unwrap_or_default();
+
+    let options = LoadOptions {
+        script_overrides,
+        protocol_parameters,
+        validator,
+        budget,
+        ..Default::default()
+    };
     let mut programs =
-        gastronomy::execution_trace::load_file(file, &parameters, query, script_overrides)
+        gastronomy::execution_trace::load_file(file, &parameters, query.as_ref(), options)
             .await
             .map_err(InvokeError::from_anyhow)?;
     let mut identifiers = vec![];
@@ -91,10 +113,12 @@ async fn get_trace_summary(
     let Some(trace) = state.traces.get(identifier) else {
         return Err(InvokeError::from("Trace not found"));
     };
-    let (frame_count, source_token_indices) = trace.get_trace_summary().await?;
+    let (frame_count, source_token_indices, outcome) = trace.get_trace_summary().await?;
     Ok(GetTraceSummaryResponse {
         frame_count,
         source_token_indices,
+        outcome,
+        redeemer: trace.redeemer.clone(),
     })
 }
 
@@ -112,6 +136,18 @@ async fn get_frame(
     Ok(GetFrameResponse { frame })
 }
 
+#[tauri::command]
+async fn get_logs(
+    identifier: &str,
+    state: State<'_, SessionState>,
+) -> Result<GetLogsResponse, InvokeError> {
+    let Some(trace) = state.traces.get(identifier) else {
+        return Err(InvokeError::from("Trace not found"));
+    };
+    let logs = trace.get_logs().await?;
+    Ok(GetLogsResponse { logs })
+}
+
 #[tauri::command]
 async fn get_source(
     identifier: &str,
@@ -155,6 +191,7 @@ fn main() {
             create_traces,
             get_trace_summary,
             get_frame,
+            get_logs,
             get_source,
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,69 @@
+use std::{fs, path::Path};
+
+use aiken_lang::ast::Tracing;
+use aiken_project::{Project, error::Error, telemetry::EventListener};
+use anyhow::{Context, Result, anyhow};
+use serde_json::Value;
+
+use crate::blueprint::Blueprint;
+
+/// Aiken projects are directories with an `aiken.toml`
+pub fn is_project(dir: &Path) -> bool {
+    dir.join("aiken.toml").is_file()
+}
+
+/// A compiled Aiken project, ready to have its functions exported
+pub struct CompiledProject {
+    project: Project<Quiet>,
+}
+
+/// Compiles a project with traces kept in, the way `aiken build` would.
+/// Our aiken fork includes source maps in the blueprint it writes.
+pub fn compile(dir: &Path) -> Result<(CompiledProject, Blueprint)> {
+    let mut project = Project::new(dir.to_path_buf(), Quiet).map_err(|e| {
+        anyhow!(
+            "could not open the aiken project at {}: {}",
+            dir.display(),
+            e
+        )
+    })?;
+    project
+        .build(false, Tracing::verbose(), None)
+        .map_err(|errors| report(dir, errors))?;
+
+    let path = dir.join("plutus.json");
+    let text =
+        fs::read_to_string(&path).with_context(|| format!("could not read {}", path.display()))?;
+    let blueprint = serde_json::from_str(&text)
+        .with_context(|| format!("invalid blueprint {}", path.display()))?;
+    Ok((CompiledProject { project }, blueprint))
+}
+
+impl CompiledProject {
+    /// Compiles a single function, named like `module/path.name`.
+    /// Returns it in the same JSON form as `aiken export`.
+    pub fn export(&self, name: &str) -> Result<Value> {
+        let (module, name) = name
+            .rsplit_once('.')
+            .with_context(|| format!("expected a name like module.function, found {}", name))?;
+        let export = self
+            .project
+            .export(module, name, Tracing::verbose())
+            .map_err(|e| anyhow!("could not compile {}.{}: {}", module, name, e))?;
+        Ok(serde_json::to_value(export)?)
+    }
+}
+
+fn report(dir: &Path, errors: Vec<Error>) -> anyhow::Error {
+    let errors = errors
+        .iter()
+        .map(|error| error.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    anyhow!("could not build {}:\n{}", dir.display(), errors)
+}
+
+/// Compiler output would get in the way of the debugger, so drop it
+struct Quiet;
+
+impl EventListener for Quiet {}
@@ -0,0 +1,866 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, anyhow, bail};
+use pallas::ledger::primitives::conway::Language;
+use serde::Deserialize;
+use serde_json::Value;
+use uplc::PlutusData;
+
+use crate::plutus_data::{self, Data};
+
+/// A CIP-57 blueprint, as written to `plutus.json` by `aiken build`.
+///
+/// This is read separately from `aiken_project::blueprint::Blueprint` (which script overrides
+/// still use, since they only need compiled code). Aiken's `Schema` types model the schemas
+/// aiken generates, while these keep each schema as the JSON it was written as, so blueprints
+/// from other toolchains can be checked too, and errors name types and fields as written.
+#[derive(Deserialize, Debug)]
+pub struct Blueprint {
+    pub preamble: Preamble,
+    pub validators: Vec<Validator>,
+    #[serde(default)]
+    pub definitions: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Preamble {
+    /// `v1`, `v2` or `v3`
+    pub plutus_version: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Validator {
+    pub title: String,
+    pub compiled_code: String,
+    #[serde(default)]
+    pub parameters: Vec<Parameter>,
+    pub source_map: Option<BTreeMap<u64, String>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Parameter {
+    pub title: Option<String>,
+    pub schema: Value,
+}
+
+/// The schema a parameter must match, along with the definitions it refers to
+#[derive(Clone, Debug)]
+pub struct ParameterSchema {
+    pub title: Option<String>,
+    schema: Value,
+    definitions: BTreeMap<String, Value>,
+}
+
+impl Blueprint {
+    /// Blueprints are told apart from other JSON files by their preamble and validators
+    pub fn is_blueprint(json: &Value) -> bool {
+        json.get("preamble").is_some() && json.get("validators").is_some()
+    }
+
+    /// The plutus version every validator in the blueprint was compiled for
+    pub fn language(&self) -> Result<Language> {
+        match self.preamble.plutus_version.as_deref() {
+            Some("v1") => Ok(Language::PlutusV1),
+            Some("v2") => Ok(Language::PlutusV2),
+            Some("v3") | None => Ok(Language::PlutusV3),
+            Some(other) => bail!("unknown plutus version {} in the blueprint", other),
+        }
+    }
+
+    /// Finds a validator by its full title (e.g. `pool.pool.spend`),
+    /// or by a suffix of it (e.g. `pool.spend`) when only one validator matches.
+    /// Blueprints with a single validator don't need a title at all.
+    pub fn select(self, title: Option<&str>) -> Result<(Validator, Vec<ParameterSchema>)> {
+        let Blueprint {
+            mut validators,
+            definitions,
+            ..
+        } = self;
+        let titles = || {
+            validators
+                .iter()
+                .map(|v| v.title.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let index = match title {
+            None if validators.len() == 1 => 0,
+            None => bail!(
+                "the blueprint has several validators, pick one with --validator: {}",
+                titles()
+            ),
+            Some(title) => {
+                let suffix = format!(".{}", title);
+                let exact = validators.iter().position(|v| v.title == title);
+                let matches: Vec<usize> = (0..validators.len())
+                    .filter(|&i| validators[i].title.ends_with(&suffix))
+                    .collect();
+                match (exact, matches.as_slice()) {
+                    (Some(index), _) => index,
+                    (None, &[index]) => index,
+                    (None, []) => bail!(
+                        "no validator titled {} in the blueprint, expected one of: {}",
+                        title,
+                        titles()
+                    ),
+                    (None, _) => bail!(
+                        "several validators match {}, use the full title: {}",
+                        title,
+                        titles()
+                    ),
+                }
+            }
+        };
+        let validator = validators.swap_remove(index);
+        let schemas = validator
+            .parameters
+            .iter()
+            .map(|parameter| ParameterSchema {
+                title: parameter.title.clone(),
+                schema: parameter.schema.clone(),
+                definitions: definitions.clone(),
+            })
+            .collect();
+        Ok((validator, schemas))
+    }
+}
+
+impl ParameterSchema {
+    /// Checks a parameter against its schema, naming where in the value the mismatch is
+    pub fn check(&self, data: &PlutusData) -> Result<()> {
+        check_schema(&self.schema, &self.definitions, data, "")
+    }
+
+    /// Encodes a JSON value laid out like the schema: records as objects keyed by field name,
+    /// variants by constructor title (e.g. `"None"` or `{"Some": 42}`), bytes as hex strings
+    /// and maps as objects or lists of pairs. Every mismatching field is reported.
+    pub fn encode(&self, value: &Value) -> Result<PlutusData> {
+        let mut errors = vec![];
+        let data = encode_schema(&self.schema, &self.definitions, value, "", &mut errors);
+        match data {
+            Some(data) if errors.is_empty() => data.to_plutus_data(),
+            _ => bail!(
+                "the value doesn't match the blueprint:\n  {}",
+                errors.join("\n  ")
+            ),
+        }
+    }
+}
+
+fn check_schema(
+    schema: &Value,
+    definitions: &BTreeMap<String, Value>,
+    data: &PlutusData,
+    path: &str,
+) -> Result<()> {
+    let schema = resolve(schema, definitions)?;
+
+    if let Some(options) = schema.get("anyOf").and_then(Value::as_array) {
+        // check against the constructor the value claims to be, so errors point inside it
+        if let PlutusData::Constr(constr) = data
+            && let Some(index) = constructor_index(constr.tag, constr.any_constructor)
+            && let Some(option) = options.iter().find(|option| {
+                resolve(option, definitions)
+                    .ok()
+                    .and_then(|option| option.get("index"))
+                    .and_then(Value::as_u64)
+                    == Some(index)
+            })
+        {
+            return check_schema(option, definitions, data, path);
+        }
+        if options
+            .iter()
+            .any(|option| check_schema(option, definitions, data, path).is_ok())
+        {
+            return Ok(());
+        }
+        bail!(
+            "expected one of {} constructor(s){}{}, found {}",
+            options.len(),
+            title_suffix(schema),
+            at(path),
+            describe(data)
+        );
+    }
+
+    let data_type = schema.get("dataType").and_then(Value::as_str);
+    match (data_type, data) {
+        // no type means any data is fine (e.g. the `Data` type)
+        (None, _) => Ok(()),
+        (Some("integer" | "#integer"), PlutusData::BigInt(_)) => Ok(()),
+        (Some("bytes" | "#bytes"), PlutusData::BoundedBytes(bytes)) => {
+            check_bytes_length(schema, bytes.len(), path)
+        }
+        (Some("list"), PlutusData::Array(items)) => match schema.get("items") {
+            // tuples list the schema of each item
+            Some(Value::Array(schemas)) => {
+                if schemas.len() != items.len() {
+                    bail!(
+                        "expected a tuple of {} items{}, found {}",
+                        schemas.len(),
+                        at(path),
+                        items.len()
+                    );
+                }
+                for (index, (schema, item)) in schemas.iter().zip(items.iter()).enumerate() {
+                    check_schema(schema, definitions, item, &format!("{}[{}]", path, index))?;
+                }
+                Ok(())
+            }
+            Some(schema) => {
+                for (index, item) in items.iter().enumerate() {
+                    check_schema(schema, definitions, item, &format!("{}[{}]", path, index))?;
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        },
+        (Some("map"), PlutusData::Map(entries)) => {
+            for (index, (key, value)) in entries.iter().enumerate() {
+                if let Some(keys) = schema.get("keys") {
+                    let path = format!("{}{{{}}}.key", path, index);
+                    check_schema(keys, definitions, key, &path)?;
+                }
+                if let Some(values) = schema.get("values") {
+                    let path = format!("{}{{{}}}.value", path, index);
+                    check_schema(values, definitions, value, &path)?;
+                }
+            }
+            Ok(())
+        }
+        (Some("constructor"), PlutusData::Constr(constr)) => {
+            let expected = schema.get("index").and_then(Value::as_u64);
+            let index = constructor_index(constr.tag, constr.any_constructor);
+            if let Some(expected) = expected
+                && index != Some(expected)
+            {
+                bail!(
+                    "expected constructor {}{}{}, found constructor {}",
+                    expected,
+                    title_suffix(schema),
+                    at(path),
+                    index.map_or("?".to_string(), |i| i.to_string())
+                );
+            }
+            let Some(fields) = schema.get("fields").and_then(Value::as_array) else {
+                return Ok(());
+            };
+            if fields.len() != constr.fields.len() {
+                bail!(
+                    "expected {} field(s) for constructor {}{}{}, found {}",
+                    fields.len(),
+                    index.map_or("?".to_string(), |i| i.to_string()),
+                    title_suffix(schema),
+                    at(path),
+                    constr.fields.len()
+                );
+            }
+            for (index, (field, value)) in fields.iter().zip(constr.fields.iter()).enumerate() {
+                let name = field
+                    .get("title")
+                    .and_then(Value::as_str)
+                    .map_or(index.to_string(), str::to_string);
+                check_schema(field, definitions, value, &format!("{}.{}", path, name))?;
+            }
+            Ok(())
+        }
+        // other builtin types (#string, #boolean, ...) can't be told apart from their encoding
+        (Some(other), _) if other.starts_with('#') => Ok(()),
+        (Some(expected), data) => bail!(
+            "expected {}{}{}, found {}",
+            expected,
+            title_suffix(schema),
+            at(path),
+            describe(data)
+        ),
+    }
+}
+
+fn encode_schema(
+    schema: &Value,
+    definitions: &BTreeMap<String, Value>,
+    value: &Value,
+    path: &str,
+    errors: &mut Vec<String>,
+) -> Option<Data> {
+    let schema = match resolve(schema, definitions) {
+        Ok(schema) => schema,
+        Err(error) => {
+            errors.push(format!("{:#}", error));
+            return None;
+        }
+    };
+    let mismatch = |errors: &mut Vec<String>, expected: &str| -> Option<Data> {
+        errors.push(format!(
+            "expected {}{}{}, found {}",
+            expected,
+            title_suffix(schema),
+            at(path),
+            value
+        ));
+        None
+    };
+
+    if let Some(options) = schema.get("anyOf").and_then(Value::as_array) {
+        return encode_variant(schema, options, definitions, value, path, errors);
+    }
+
+    let data_type = schema.get("dataType").and_then(Value::as_str);
+    match (data_type, value) {
+        // untyped data (e.g. the `Data` type) has no layout to follow, so take it in detailed form
+        (None, value) => match plutus_data::json_to_data(value, path) {
+            Ok(data) => Some(data),
+            Err(error) => {
+                errors.push(format!("{:#}", error));
+                None
+            }
+        },
+        // large integers don't fit in a JSON number, so allow them as strings too
+        (Some("integer" | "#integer"), Value::Number(_) | Value::String(_)) => {
+            let int = match value {
+                Value::String(string) => string.clone(),
+                number => number.to_string(),
+            };
+            match int.parse() {
+                Ok(int) => Some(Data::Int(int)),
+                Err(_) => mismatch(errors, "an integer"),
+            }
+        }
+        (Some("bytes" | "#bytes"), Value::String(string)) => {
+            let Ok(bytes) = hex::decode(string) else {
+                return mismatch(errors, "a hex string");
+            };
+            if let Err(error) = check_bytes_length(schema, bytes.len(), path) {
+                errors.push(error.to_string());
+            }
+            Some(Data::Bytes(bytes))
+        }
+        (Some("list"), Value::Array(items)) => {
+            let items = match schema.get("items") {
+                // tuples list the schema of each item
+                Some(Value::Array(schemas)) => {
+                    if schemas.len() != items.len() {
+                        errors.push(format!(
+                            "expected a tuple of {} items{}, found {}",
+                            schemas.len(),
+                            at(path),
+                            items.len()
+                        ));
+                        return None;
+                    }
+                    schemas
+                        .iter()
+                        .zip(items)
+                        .enumerate()
+                        .map(|(index, (schema, item))| {
+                            let path = format!("{}[{}]", path, index);
+                            encode_schema(schema, definitions, item, &path, errors)
+                        })
+                        .collect::<Vec<_>>()
+                }
+                schema => {
+                    let any = Value::Null;
+                    let schema = schema.unwrap_or(&any);
+                    items
+                        .iter()
+                        .enumerate()
+                        .map(|(index, item)| {
+                            let path = format!("{}[{}]", path, index);
+                            encode_schema(schema, definitions, item, &path, errors)
+                        })
+                        .collect()
+                }
+            };
+            items.into_iter().collect::<Option<_>>().map(Data::List)
+        }
+        (Some("map"), Value::Object(object)) => {
+            let entries = object
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::String(key.clone()), value))
+                .collect();
+            encode_map(schema, definitions, entries, path, errors)
+        }
+        // objects can only have string keys, so other keys are written as [key, value] pairs
+        (Some("map"), Value::Array(pairs)) => {
+            let mut entries = vec![];
+            for (index, pair) in pairs.iter().enumerate() {
+                match pair.as_array().map(Vec::as_slice) {
+                    Some([key, value]) => entries.push((index.to_string(), key.clone(), value)),
+                    _ => errors.push(format!(
+                        "expected a [key, value] pair at {}{{{}}}, found {}",
+                        path, index, pair
+                    )),
+                }
+            }
+            encode_map(schema, definitions, entries, path, errors)
+        }
+        (Some("constructor"), value) => {
+            encode_constructor(schema, definitions, value, path, errors)
+        }
+        (Some("integer" | "#integer"), _) => mismatch(errors, "an integer"),
+        (Some("bytes" | "#bytes"), _) => mismatch(errors, "a hex string"),
+        (Some("list"), _) => mismatch(errors, "a list"),
+        (Some("map"), _) => mismatch(errors, "an object or a list of [key, value] pairs"),
+        (Some(other), _) => {
+            errors.push(format!("can't encode {}{} from JSON", other, at(path)));
+            None
+        }
+    }
+}
+
+/// Map entries are named by their key, or their position when given as pairs
+fn encode_map(
+    schema: &Value,
+    definitions: &BTreeMap<String, Value>,
+    entries: Vec<(String, Value, &Value)>,
+    path: &str,
+    errors: &mut Vec<String>,
+) -> Option<Data> {
+    let any = Value::Null;
+    let keys = schema.get("keys").unwrap_or(&any);
+    let values = schema.get("values").unwrap_or(&any);
+    let entries = entries
+        .iter()
+        .map(|(name, key, value)| {
+            let key_path = format!("{}{{{}}}.key", path, name);
+            let value_path = format!("{}{{{}}}.value", path, name);
+            let key = encode_schema(keys, definitions, key, &key_path, errors);
+            let value = encode_schema(values, definitions, value, &value_path, errors);
+            key.zip(value)
+        })
+        .collect::<Vec<_>>();
+    entries.into_iter().collect::<Option<_>>().map(Data::Map)
+}
+
+/// Picks the constructor a value names, and encodes the value as that constructor
+fn encode_variant(
+    schema: &Value,
+    options: &[Value],
+    definitions: &BTreeMap<String, Value>,
+    value: &Value,
+    path: &str,
+    errors: &mut Vec<String>,
+) -> Option<Data> {
+    // types with a single constructor (i.e. records) don't need to name it
+    if let [option] = options {
+        return encode_schema(option, definitions, value, path, errors);
+    }
+    let options = options
+        .iter()
+        .map(|option| resolve(option, definitions))
+        .collect::<Result<Vec<_>>>();
+    let options = match options {
+        Ok(options) => options,
+        Err(error) => {
+            errors.push(format!("{:#}", error));
+            return None;
+        }
+    };
+    let titled = |title: &str| {
+        options
+            .iter()
+            .find(|option| option.get("title").and_then(Value::as_str) == Some(title))
+            .copied()
+    };
+    let null = Value::Null;
+    let (option, value) = match value {
+        Value::Bool(true) => (titled("True"), &null),
+        Value::Bool(false) => (titled("False"), &null),
+        Value::Null => (titled("None"), &null),
+        Value::String(title) if titled(title).is_some() => (titled(title), &null),
+        Value::Object(object) => match object.iter().next() {
+            Some((title, inner)) if object.len() == 1 && titled(title).is_some() => {
+                (titled(title), inner)
+            }
+            _ => (titled("Some"), value),
+        },
+        // a bare value is taken to be the content of an `Option`
+        value => (titled("Some"), value),
+    };
+    let Some(option) = option else {
+        let titles = options
+            .iter()
+            .filter_map(|option| option.get("title").and_then(Value::as_str))
+            .collect::<Vec<_>>();
+        errors.push(format!(
+            "expected one of {}{}{}, found {}",
+            titles.join(", "),
+            title_suffix(schema),
+            at(path),
+            value
+        ));
+        return None;
+    };
+    encode_constructor(option, definitions, value, path, errors)
+}
+
+/// Constructor fields are given by title in an object, or in order in a list.
+/// A constructor with a single field can also be given just that field's value.
+fn encode_constructor(
+    schema: &Value,
+    definitions: &BTreeMap<String, Value>,
+    value: &Value,
+    path: &str,
+    errors: &mut Vec<String>,
+) -> Option<Data> {
+    let index = schema.get("index").and_then(Value::as_u64).unwrap_or(0);
+    let fields = schema
+        .get("fields")
+        .and_then(Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+    let name = |index: usize, field: &Value| {
+        field
+            .get("title")
+            .and_then(Value::as_str)
+            .map_or(index.to_string(), str::to_string)
+    };
+    let values: Vec<Option<&Value>> = match value {
+        Value::Null | Value::String(_) if fields.is_empty() => vec![],
+        Value::Object(object) if fields.len() != 1 || object.contains_key(&name(0, &fields[0])) => {
+            for key in object.keys() {
+                if !(0..fields.len()).any(|index| name(index, &fields[index]) == *key) {
+                    errors.push(format!(
+                        "unknown field {}{}{}",
+                        key,
+                        title_suffix(schema),
+                        at(path)
+                    ));
+                }
+            }
+            (0..fields.len())
+                .map(|index| object.get(&name(index, &fields[index])))
+                .collect()
+        }
+        Value::Array(items) if fields.len() != 1 || items.len() == 1 => {
+            if items.len() != fields.len() {
+                errors.push(format!(
+                    "expected {} field(s){}{}, found {}",
+                    fields.len(),
+                    title_suffix(schema),
+                    at(path),
+                    items.len()
+                ));
+                return None;
+            }
+            items.iter().map(Some).collect()
+        }
+        value if fields.len() == 1 => vec![Some(value)],
+        value => {
+            errors.push(format!(
+                "expected an object with field(s) {}{}{}, found {}",
+                (0..fields.len())
+                    .map(|index| name(index, &fields[index]))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                title_suffix(schema),
+                at(path),
+                value
+            ));
+            return None;
+        }
+    };
+    let fields = fields
+        .iter()
+        .zip(values)
+        .enumerate()
+        .map(|(index, (field, value))| {
+            let path = format!("{}.{}", path, name(index, field));
+            let Some(value) = value else {
+                errors.push(format!("missing field {}", path.trim_start_matches('.')));
+                return None;
+            };
+            encode_schema(field, definitions, value, &path, errors)
+        })
+        .collect::<Vec<_>>();
+    fields
+        .into_iter()
+        .collect::<Option<_>>()
+        .map(|fields| Data::Constr(index, fields))
+}
+
+/// Follows `$ref`s into the blueprint's definitions
+fn resolve<'a>(schema: &'a Value, definitions: &'a BTreeMap<String, Value>) -> Result<&'a Value> {
+    let mut schema = schema;
+    while let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference
+            .strip_prefix("#/definitions/")
+            .ok_or_else(|| anyhow!("unsupported schema reference {}", reference))?
+            .replace("~1", "/")
+            .replace("~0", "~");
+        schema = definitions
+            .get(&name)
+            .with_context(|| format!("the blueprint doesn't define {}", name))?;
+    }
+    Ok(schema)
+}
+
+fn check_bytes_length(schema: &Value, length: usize, path: &str) -> Result<()> {
+    let min = schema.get("minLength").and_then(Value::as_u64);
+    let max = schema.get("maxLength").and_then(Value::as_u64);
+    let length = length as u64;
+    if min.is_some_and(|min| length < min) || max.is_some_and(|max| length > max) {
+        bail!(
+            "expected {} bytes{}, found {}",
+            match (min, max) {
+                (Some(min), Some(max)) if min == max => min.to_string(),
+                (min, max) => format!(
+                    "{}..{}",
+                    min.map_or(String::new(), |m| m.to_string()),
+                    max.map_or(String::new(), |m| m.to_string())
+                ),
+            },
+            at(path),
+            length
+        );
+    }
+    Ok(())
+}
+
+/// Where in the parameter an error is, if not at its root
+fn at(path: &str) -> String {
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!(" at {}", path)
+    }
+}
+
+fn title_suffix(schema: &Value) -> String {
+    schema
+        .get("title")
+        .and_then(Value::as_str)
+        .map_or(String::new(), |title| format!(" ({})", title))
+}
+
+/// The constructor index encoded by a CBOR tag, as laid out in the Plutus data spec
+fn constructor_index(tag: u64, any_constructor: Option<u64>) -> Option<u64> {
+    match tag {
+        121..=127 => Some(tag - 121),
+        1280..=1400 => Some(tag - 1280 + 7),
+        102 => any_constructor,
+        _ => None,
+    }
+}
+
+fn describe(data: &PlutusData) -> &'static str {
+    match data {
+        PlutusData::Constr(_) => "a constructor",
+        PlutusData::Map(_) => "a map",
+        PlutusData::BigInt(_) => "an integer",
+        PlutusData::BoundedBytes(_) => "bytes",
+        PlutusData::Array(_) => "a list",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A parameter of the given schema, with definitions for the types aiken generates
+    fn parameter(schema: Value) -> ParameterSchema {
+        let int = json!({ "$ref": "#/definitions/Int" });
+        let bytes = json!({ "$ref": "#/definitions/ByteArray" });
+        let blueprint: Blueprint = serde_json::from_value(json!({
+            "preamble": { "plutusVersion": "v3" },
+            "validators": [{
+                "title": "pool.pool.spend",
+                "compiledCode": "",
+                "parameters": [{ "title": "parameter", "schema": schema }],
+            }],
+            "definitions": {
+                "Int": { "dataType": "integer" },
+                "ByteArray": { "dataType": "bytes" },
+                "aiken/crypto/Hash": { "dataType": "bytes", "minLength": 2, "maxLength": 2 },
+                "Bool": {
+                    "title": "Bool",
+                    "anyOf": [
+                        { "title": "False", "dataType": "constructor", "index": 0, "fields": [] },
+                        { "title": "True", "dataType": "constructor", "index": 1, "fields": [] },
+                    ],
+                },
+                "Option$Int": {
+                    "title": "Option",
+                    "anyOf": [
+                        {
+                            "title": "Some",
+                            "dataType": "constructor",
+                            "index": 0,
+                            "fields": [int.clone()],
+                        },
+                        { "title": "None", "dataType": "constructor", "index": 1, "fields": [] },
+                    ],
+                },
+                "Datum": {
+                    "title": "Datum",
+                    "anyOf": [{
+                        "title": "Datum",
+                        "dataType": "constructor",
+                        "index": 0,
+                        "fields": [
+                            { "title": "owner", "$ref": "#/definitions/ByteArray" },
+                            { "title": "fee", "$ref": "#/definitions/Int" },
+                            { "title": "limit", "$ref": "#/definitions/Option$Int" },
+                        ],
+                    }],
+                },
+                "List$Int": { "dataType": "list", "items": int.clone() },
+                "Tuple$Int_ByteArray": {
+                    "dataType": "list",
+                    "items": [int.clone(), bytes.clone()],
+                },
+                "Pairs$ByteArray_Int": { "dataType": "map", "keys": bytes, "values": int },
+                "Data": { "title": "Data", "description": "Any Plutus data." },
+            },
+        }))
+        .unwrap();
+        let (_, mut schemas) = blueprint.select(None).unwrap();
+        schemas.remove(0)
+    }
+
+    fn definition(name: &str) -> ParameterSchema {
+        parameter(json!({ "$ref": format!("#/definitions/{}", name) }))
+    }
+
+    fn encode(name: &str, value: Value) -> String {
+        let data = definition(name).encode(&value).unwrap();
+        hex::encode(minicbor::to_vec(&data).unwrap())
+    }
+
+    fn encode_error(name: &str, value: Value) -> String {
+        match definition(name).encode(&value) {
+            Ok(_) => panic!("expected {} not to encode as {}", value, name),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    fn check_error(name: &str, data: &str) -> String {
+        let data = plutus_data::parse(data).unwrap();
+        match definition(name).check(&data) {
+            Ok(()) => panic!(
+                "expected {} not to match {}",
+                name,
+                hex::encode(minicbor::to_vec(&data).unwrap())
+            ),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn encodes_records_by_field_name_or_position() {
+        let by_name = json!({ "owner": "cafe", "fee": 30, "limit": null });
+        assert_eq!(encode("Datum", by_name), "d8799f42cafe181ed87a80ff");
+        let by_position = json!(["cafe", 30, { "Some": 5 }]);
+        assert_eq!(encode("Datum", by_position), "d8799f42cafe181ed8799f05ffff");
+    }
+
+    #[test]
+    fn encodes_variants_by_title() {
+        assert_eq!(encode("Bool", json!(true)), "d87a80");
+        assert_eq!(encode("Bool", json!("False")), "d87980");
+        assert_eq!(encode("Option$Int", json!({ "Some": 5 })), "d8799f05ff");
+        // a bare value is the content of an Option
+        assert_eq!(encode("Option$Int", json!(5)), "d8799f05ff");
+        assert_eq!(encode("Option$Int", json!(null)), "d87a80");
+        assert_eq!(
+            encode_error("Bool", json!("Maybe")),
+            "the value doesn't match the blueprint:\n  \
+            expected one of False, True (Bool), found \"Maybe\""
+        );
+    }
+
+    #[test]
+    fn encodes_lists_tuples_and_maps() {
+        assert_eq!(encode("List$Int", json!([1, 2])), "9f0102ff");
+        assert_eq!(
+            encode("Tuple$Int_ByteArray", json!([1, "ab"])),
+            "9f0141abff"
+        );
+        assert_eq!(
+            encode("Pairs$ByteArray_Int", json!({ "cafe": 1 })),
+            "a142cafe01"
+        );
+        assert_eq!(
+            encode("Pairs$ByteArray_Int", json!([["cafe", 1]])),
+            "a142cafe01"
+        );
+        assert_eq!(
+            encode_error("Tuple$Int_ByteArray", json!([1])),
+            "the value doesn't match the blueprint:\n  expected a tuple of 2 items, found 1"
+        );
+        assert_eq!(
+            encode_error("Pairs$ByteArray_Int", json!([["cafe"]])),
+            "the value doesn't match the blueprint:\n  \
+            expected a [key, value] pair at {0}, found [\"cafe\"]"
+        );
+    }
+
+    #[test]
+    fn encodes_untyped_data_in_detailed_form() {
+        assert_eq!(encode("Data", json!({ "int": 7 })), "07");
+    }
+
+    #[test]
+    fn follows_escaped_references() {
+        assert_eq!(encode("aiken~1crypto~1Hash", json!("cafe")), "42cafe");
+        assert_eq!(
+            encode_error("aiken~1crypto~1Hash", json!("cafe01")),
+            "the value doesn't match the blueprint:\n  expected 2 bytes, found 3"
+        );
+        assert_eq!(
+            encode_error("Missing", json!(1)),
+            "the value doesn't match the blueprint:\n  the blueprint doesn't define Missing"
+        );
+    }
+
+    #[test]
+    fn reports_every_mismatching_field() {
+        let error = encode_error("Datum", json!({ "owner": "xyz", "fee": "abc", "extra": 1 }));
+        assert_eq!(
+            error,
+            [
+                "the value doesn't match the blueprint:",
+                "  unknown field extra (Datum)",
+                "  expected a hex string at .owner, found \"xyz\"",
+                "  expected an integer at .fee, found \"abc\"",
+                "  missing field limit",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn checks_data_against_the_schema() {
+        let datum = plutus_data::parse("121([h'cafe', 30, 122([])])").unwrap();
+        definition("Datum").check(&datum).unwrap();
+
+        assert_eq!(
+            check_error("Datum", "121([h'cafe', 30])"),
+            "expected 3 field(s) for constructor 0 (Datum), found 2"
+        );
+        assert_eq!(
+            check_error("Datum", "121([h'cafe', h'00', 122([])])"),
+            "expected integer at .fee, found bytes"
+        );
+        assert_eq!(
+            check_error("Datum", "121([h'cafe', 30, 123([])])"),
+            "expected one of 2 constructor(s) (Option) at .limit, found a constructor"
+        );
+        assert_eq!(
+            check_error("Tuple$Int_ByteArray", "[1, 2]"),
+            "expected bytes at [1], found an integer"
+        );
+        assert_eq!(
+            check_error("Pairs$ByteArray_Int", "{1: 2}"),
+            "expected bytes at {0}.key, found an integer"
+        );
+        assert_eq!(
+            check_error("aiken~1crypto~1Hash", "h'cafe01'"),
+            "expected 2 bytes, found 3"
+        );
+    }
+}
@@ -1,248 +1,389 @@
-use anyhow::{Result, bail};
-use blockfrost::{BlockfrostAPI, JsonValue};
-use blockfrost_openapi::models::script::Type;
+use std::{collections::BTreeMap, str::FromStr, time::Duration};
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
 use pallas::{
-    applying::utils::{AlonzoError, ValidationError, add_values},
-    codec::utils::{Bytes, CborWrap, NonEmptyKeyValuePairs, PositiveCoin},
-    ledger::{
-        addresses::Address,
-        primitives::conway::{
-            self, DatumOption, PostAlonzoTransactionOutput, PseudoScript, TransactionOutput,
-        },
-    },
+    codec::utils::{Bytes, NonEmptyKeyValuePairs, PositiveCoin},
+    ledger::primitives::conway::{self, NativeScript, PlutusScript, PseudoScript, ScriptRef},
 };
-use serde_json::from_str;
+use serde::de::DeserializeOwned;
 use uplc::{
-    Fragment, Hash, KeyValuePairs, PlutusData, TransactionInput, Value,
+    Hash, TransactionInput,
     tx::{ResolvedInput, SlotConfig},
 };
 
-use crate::config::BlockfrostConfig;
+use crate::{config::Config, protocol_parameters::ProtocolParameters};
 
-pub enum ChainQuery {
-    Blockfrost(Blockfrost),
-    None,
-}
+mod blockfrost;
+mod cache;
+mod error;
+mod koios;
+mod kupmios;
+mod mock;
+mod utxo_file;
 
-impl ChainQuery {
-    pub fn blockfrost(config: &BlockfrostConfig) -> Self {
-        Self::Blockfrost(Blockfrost::new(config))
-    }
+pub use blockfrost::Blockfrost;
+pub use cache::{Cache, default_cache_dir};
+pub use error::ChainQueryError;
+pub use koios::Koios;
+pub use kupmios::Kupmios;
+pub use mock::MockChainQuery;
+pub use utxo_file::UtxoFile;
 
-    pub async fn get_tx_bytes(&self, tx_id: Hash<32>) -> Result<Bytes> {
-        match self {
-            Self::Blockfrost(blockfrost) => blockfrost.get_tx_bytes(tx_id).await,
-            Self::None => {
-                bail!("No chain query provider configured, consider adding a blockfrost API key")
-            }
-        }
-    }
+pub type QueryResult<T> = std::result::Result<T, ChainQueryError>;
 
-    pub async fn get_utxos(&self, tx_ref: Vec<TransactionInput>) -> Result<Vec<ResolvedInput>> {
-        match self {
-            Self::Blockfrost(blockfrost) => blockfrost.get_utxos(tx_ref).await,
-            Self::None => {
-                bail!("No chain query provider configured, consider adding a blockfrost API key")
-            }
-        }
+/// How many requests a provider sends at once, unless configured otherwise
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 8;
+const MAX_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// A source of chain data, used to resolve everything a transaction refers to.
+///
+/// Implement this to load transactions through your own indexer,
+/// and pass it to `load_programs_from_file` or `execution_trace::load_file`.
+#[async_trait]
+pub trait ChainQuery: Send + Sync {
+    /// The name of the network this provider talks to, used to keep cached responses apart
+    fn network(&self) -> Option<&str> {
+        None
     }
 
-    pub fn get_slot_config(&self) -> Result<SlotConfig> {
-        match self {
-            Self::Blockfrost(blockfrost) => blockfrost.get_slot_config(),
-            Self::None => {
-                bail!("No chain query provider configured, consider adding a blockfrost API key")
-            }
-        }
+    /// Fails if `get_tx_bytes` can't return the transaction,
+    /// so loading one by id stops before anything else is fetched
+    fn check_tx_lookup(&self, _tx_id: Hash<32>) -> QueryResult<()> {
+        Ok(())
     }
+
+    async fn get_tx_bytes(&self, tx_id: Hash<32>) -> QueryResult<Bytes>;
+
+    async fn get_utxos(&self, tx_ref: Vec<TransactionInput>) -> QueryResult<Vec<ResolvedInput>>;
+
+    /// Looks up a datum by its hash, returning its original CBOR encoding
+    async fn get_datum(&self, datum_hash: Hash<32>) -> QueryResult<Option<Bytes>>;
+
+    async fn get_slot_config(&self) -> QueryResult<SlotConfig>;
+
+    async fn get_protocol_parameters(&self) -> QueryResult<ProtocolParameters>;
 }
 
-impl Default for ChainQuery {
-    fn default() -> Self {
-        Self::None
+/// Builds the provider described by the config, wrapped in an on-disk cache when one is available
+pub fn from_config(config: &Config) -> Result<Box<dyn ChainQuery>> {
+    let network = Network::from_config(config)?;
+    if let Some(utxos_file) = &config.utxos_file {
+        // the file can't say which network its inputs are from
+        let network = network.ok_or_else(|| {
+            anyhow!(
+                "a utxo file needs a network (see --network), \
+                to turn the transaction's slots into times"
+            )
+        })?;
+        // already local, so there's nothing to cache
+        return Ok(Box::new(UtxoFile::load(utxos_file, network)?));
+    }
+    // offline, there may be no provider to say which network it's on
+    let network_name = network.as_ref().map(|network| network.name().to_string());
+    let max_concurrent_requests = match config.max_concurrent_requests {
+        Some(0) => bail!("maxConcurrentRequests must be at least 1"),
+        Some(limit) => limit,
+        None => DEFAULT_MAX_CONCURRENT_REQUESTS,
+    };
+    let query: Box<dyn ChainQuery> = if let Some(blockfrost) = &config.blockfrost {
+        Box::new(Blockfrost::new(
+            blockfrost,
+            network,
+            max_concurrent_requests,
+        )?)
+    } else if let Some(koios) = &config.koios {
+        Box::new(Koios::new(koios, network, max_concurrent_requests)?)
+    } else if let (Some(ogmios), Some(kupo)) = (&config.ogmios, &config.kupo) {
+        Box::new(Kupmios::new(ogmios, kupo, network, max_concurrent_requests))
+    } else {
+        Box::new(Unconfigured)
+    };
+    let offline = config.offline.unwrap_or_default();
+    if query.network().is_none() && network_name.is_none() && !offline {
+        return Ok(query);
+    }
+    match config.cache_dir.clone().or_else(default_cache_dir) {
+        Some(cache_dir) => Ok(Box::new(Cache::new(
+            query,
+            &cache_dir,
+            network_name.as_deref(),
+            offline,
+        )?)),
+        None if offline => bail!("offline mode needs a cache directory"),
+        None => Ok(query),
     }
 }
 
-trait ChainQueryImpl {
-    async fn get_tx_bytes(&self, tx_id: Hash<32>) -> Result<Bytes>;
-    async fn get_utxos(&self, tx_ref: Vec<TransactionInput>) -> Result<Vec<ResolvedInput>>;
-    fn get_slot_config(&self) -> Result<SlotConfig>;
-}
+/// Used when no provider is configured, so anything which needs chain data fails clearly
+pub struct Unconfigured;
 
-pub struct Blockfrost {
-    api_key: String,
-    environment: Environment,
-    api: BlockfrostAPI,
+#[async_trait]
+impl ChainQuery for Unconfigured {
+    async fn get_tx_bytes(&self, _tx_id: Hash<32>) -> QueryResult<Bytes> {
+        Err(ChainQueryError::NotConfigured)
+    }
+
+    async fn get_utxos(&self, _tx_ref: Vec<TransactionInput>) -> QueryResult<Vec<ResolvedInput>> {
+        Err(ChainQueryError::NotConfigured)
+    }
+
+    async fn get_datum(&self, _datum_hash: Hash<32>) -> QueryResult<Option<Bytes>> {
+        Err(ChainQueryError::NotConfigured)
+    }
+
+    async fn get_slot_config(&self) -> QueryResult<SlotConfig> {
+        Err(ChainQueryError::NotConfigured)
+    }
+
+    async fn get_protocol_parameters(&self) -> QueryResult<ProtocolParameters> {
+        Err(ChainQueryError::NotConfigured)
+    }
 }
 
-enum Environment {
+/// The network a provider talks to.
+/// Besides the public networks, any other name refers to a custom network,
+/// such as SanchoNet, a yaci-devkit devnet or a private testnet.
+#[derive(Clone, Debug)]
+pub enum Network {
     Preview,
     Preprod,
     Mainnet,
+    Custom(CustomNetwork),
+}
+
+#[derive(Clone, Debug)]
+pub struct CustomNetwork {
+    pub name: String,
+    pub zero_time: u64,
+    pub zero_slot: u64,
+    pub slot_length: u32,
+    pub url: Option<String>,
+}
+
+impl FromStr for Network {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "preview" => Ok(Self::Preview),
+            "preprod" => Ok(Self::Preprod),
+            "mainnet" => Ok(Self::Mainnet),
+            other => Err(anyhow!(
+                "unknown network \"{}\", expected preview, preprod or mainnet",
+                other
+            )),
+        }
+    }
 }
 
-impl Environment {
-    fn as_str(&self) -> &'static str {
+impl Network {
+    /// The network selected in the config, if any
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(name) = &config.network else {
+            return Ok(None);
+        };
+        let network = match (name.parse(), &config.custom_network) {
+            (Ok(network), _) => network,
+            (Err(_), Some(custom)) => Self::Custom(CustomNetwork {
+                name: name.to_string(),
+                zero_time: custom.zero_time,
+                zero_slot: custom.zero_slot,
+                slot_length: custom.slot_length,
+                url: custom.url.clone(),
+            }),
+            (Err(_), None) => bail!(
+                "unknown network \"{}\", expected preview, preprod or mainnet, \
+                or customNetwork settings describing it",
+                name
+            ),
+        };
+        Ok(Some(network))
+    }
+
+    pub fn name(&self) -> &str {
         match self {
             Self::Preview => "preview",
             Self::Preprod => "preprod",
             Self::Mainnet => "mainnet",
+            Self::Custom(custom) => &custom.name,
+        }
+    }
+
+    fn blockfrost_url(&self) -> Option<String> {
+        match self {
+            Self::Custom(custom) => custom.url.clone(),
+            public => Some(format!(
+                "https://cardano-{}.blockfrost.io/api/v0",
+                public.name()
+            )),
         }
     }
+
+    fn koios_url(&self) -> Option<String> {
+        match self {
+            Self::Preview => Some("https://preview.koios.rest/api/v1".to_string()),
+            Self::Preprod => Some("https://preprod.koios.rest/api/v1".to_string()),
+            Self::Mainnet => Some("https://api.koios.rest/api/v1".to_string()),
+            Self::Custom(custom) => custom.url.clone(),
+        }
+    }
+
     fn slot_config(&self) -> SlotConfig {
-        let (zero_time, zero_slot) = match self {
-            Self::Preview => (1666656000000, 0),
-            Self::Preprod => (1655683200000, 0),
-            Self::Mainnet => (1591566291000, 0),
+        let (zero_time, zero_slot, slot_length) = match self {
+            Self::Preview => (1666656000000, 0, 1000),
+            Self::Preprod => (1655683200000, 0, 1000),
+            Self::Mainnet => (1591566291000, 0, 1000),
+            Self::Custom(custom) => (custom.zero_time, custom.zero_slot, custom.slot_length),
         };
         SlotConfig {
-            slot_length: 1000,
+            slot_length,
             zero_slot,
             zero_time,
         }
     }
 }
 
-impl Blockfrost {
-    pub fn new(config: &BlockfrostConfig) -> Self {
-        let environment = if config.key.starts_with("preview") {
-            Environment::Preview
-        } else if config.key.starts_with("preprod") {
-            Environment::Preprod
-        } else {
-            Environment::Mainnet
+enum ScriptLanguage {
+    Native,
+    PlutusV1,
+    PlutusV2,
+    PlutusV3,
+}
+
+fn build_script_ref(language: ScriptLanguage, bytes: Vec<u8>) -> Result<ScriptRef> {
+    let script = match language {
+        ScriptLanguage::Native => PseudoScript::NativeScript(
+            minicbor::decode(&bytes).map_err(|e| anyhow!("invalid native script: {}", e))?,
+        ),
+        ScriptLanguage::PlutusV1 => PseudoScript::PlutusV1Script(PlutusScript(bytes.into())),
+        ScriptLanguage::PlutusV2 => PseudoScript::PlutusV2Script(PlutusScript(bytes.into())),
+        ScriptLanguage::PlutusV3 => PseudoScript::PlutusV3Script(PlutusScript(bytes.into())),
+    };
+    Ok(script)
+}
+
+/// Parses a native script from the JSON format used by cardano-cli and db-sync
+fn native_script_from_json(json: &serde_json::Value) -> Result<NativeScript> {
+    let scripts = || -> Result<Vec<NativeScript>> {
+        let Some(scripts) = json["scripts"].as_array() else {
+            bail!("native script is missing its scripts");
         };
-        Blockfrost {
-            api_key: config.key.to_string(),
-            environment,
-            api: BlockfrostAPI::new(&config.key, Default::default()),
+        scripts.iter().map(native_script_from_json).collect()
+    };
+    let slot = || {
+        json["slot"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("native script is missing its slot"))
+    };
+    let script = match json["type"].as_str() {
+        Some("sig") => {
+            let Some(key_hash) = json["keyHash"].as_str() else {
+                bail!("native script is missing its key hash");
+            };
+            NativeScript::ScriptPubkey(hex::decode(key_hash)?[..].into())
         }
-    }
+        Some("all") => NativeScript::ScriptAll(scripts()?),
+        Some("any") => NativeScript::ScriptAny(scripts()?),
+        Some("atLeast") => {
+            let Some(required) = json["required"].as_u64() else {
+                bail!("native script is missing its required count");
+            };
+            NativeScript::ScriptNOfK(required.try_into()?, scripts()?)
+        }
+        Some("after") => NativeScript::InvalidBefore(slot()?),
+        Some("before") => NativeScript::InvalidHereafter(slot()?),
+        other => bail!("unknown native script type {:?}", other),
+    };
+    Ok(script)
 }
 
-impl ChainQueryImpl for Blockfrost {
-    async fn get_tx_bytes(&self, tx_id: Hash<32>) -> Result<Bytes> {
-        let client = reqwest::Client::new();
-        let tx_id = hex::encode(tx_id);
-        let response = client
-            .get(format!(
-                "https://cardano-{}.blockfrost.io/api/v0/txs/{}/cbor",
-                self.environment.as_str(),
-                tx_id
-            ))
-            .header("project_id", self.api_key.as_str())
-            .send()
-            .await?;
-        let value = from_str::<JsonValue>(&response.text().await?)?;
-        let tx_bytes = hex::decode(value["cbor"].as_str().unwrap())?;
-        Ok(tx_bytes.into())
-    }
-    async fn get_utxos(&self, inputs: Vec<TransactionInput>) -> Result<Vec<ResolvedInput>> {
-        let mut resolved_inputs = vec![];
-        for input in inputs {
-            let tx = self
-                .api
-                .transactions_utxos(hex::encode(input.transaction_id).as_str())
-                .await?;
-            let output = tx.outputs[input.index as usize].clone();
-            let datum = match (output.inline_datum, output.data_hash) {
-                (Some(datum), _) => Some(DatumOption::Data(CborWrap(
-                    hex::decode(datum)
-                        .ok()
-                        .and_then(|d| PlutusData::decode_fragment(&d).ok())
-                        .unwrap(),
-                ))),
-                (None, Some(hash)) => {
-                    Some(DatumOption::Hash(hex::decode(hash).unwrap()[..].into()))
-                }
-                (None, None) => None,
-            };
-            let mut value: Value = pallas::applying::utils::empty_value();
-            for asset in output.amount.iter() {
-                if asset.unit == "lovelace" {
-                    value = add_values(
-                        &value,
-                        &Value::Coin(asset.quantity.parse().unwrap()),
-                        &ValidationError::Alonzo(AlonzoError::NegativeValue),
-                    )
-                    .unwrap();
-                } else {
-                    let policy: Hash<28> = hex::decode(&asset.unit[0..56]).unwrap()[..].into();
-                    let asset_name: Bytes = hex::decode(&asset.unit[56..]).unwrap().into();
-                    let amount: u64 = asset.quantity.parse().unwrap();
-                    let asset_amt = KeyValuePairs::Def(vec![(asset_name, amount)]);
-                    let multiasset = KeyValuePairs::Def(vec![(policy, asset_amt)]);
-                    value = add_values(
-                        &value,
-                        &Value::Multiasset(0u64, multiasset),
-                        &ValidationError::Alonzo(AlonzoError::NegativeValue),
-                    )
-                    .unwrap();
-                }
+/// Sends a request to a provider, and returns the body of its response,
+/// or nothing if the provider doesn't know about the thing we asked for.
+///
+/// Requests which were rate limited or hit a server error are retried with exponential backoff,
+/// waiting as long as the provider asks through `Retry-After` when it says so.
+async fn send_request(
+    provider: &'static str,
+    mut request: reqwest::RequestBuilder,
+) -> QueryResult<Option<String>> {
+    let http = |source| ChainQueryError::Http { provider, source };
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+    let response = loop {
+        // keep a copy around in case it needs to be sent again
+        let retry = request.try_clone();
+        let response = request.send().await.map_err(http)?;
+        let status = response.status();
+        let retryable =
+            status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+        match retry {
+            Some(retry) if retryable && attempt < MAX_RETRIES => {
+                tokio::time::sleep(retry_after(&response).unwrap_or(backoff)).await;
+                request = retry;
+                backoff *= 2;
+                attempt += 1;
             }
-            let value = match value {
-                Value::Coin(coin) => conway::Value::Coin(coin),
-                Value::Multiasset(coin, multiasset) => conway::Value::Multiasset(
-                    coin,
-                    NonEmptyKeyValuePairs::Def(
-                        multiasset
-                            .iter()
-                            .map(|(k, v)| {
-                                (
-                                    *k,
-                                    NonEmptyKeyValuePairs::Def(
-                                        v.iter()
-                                            .map(|(k, v)| {
-                                                (k.clone(), PositiveCoin::try_from(*v).unwrap())
-                                            })
-                                            .collect(),
-                                    ),
-                                )
-                            })
-                            .collect(),
-                    ),
-                ),
-            };
+            _ => break response,
+        }
+    };
+    match response.status() {
+        reqwest::StatusCode::NOT_FOUND => Ok(None),
+        reqwest::StatusCode::TOO_MANY_REQUESTS => Err(ChainQueryError::RateLimited { provider }),
+        _ => {
+            let response = response.error_for_status().map_err(http)?;
+            Ok(Some(response.text().await.map_err(http)?))
+        }
+    }
+}
 
-            let script_ref = if let Some(script_hash) = output.reference_script_hash {
-                let script_version = self.api.scripts_by_id(script_hash.as_str()).await?.r#type;
-                let script = self
-                    .api
-                    .scripts_datum_hash_cbor(script_hash.as_str())
-                    .await?;
-                let bytes = hex::decode(script["cbor"].as_str().unwrap()).unwrap();
-                let script = match script_version {
-                    Type::PlutusV1 => {
-                        PseudoScript::PlutusV1Script(conway::PlutusScript(bytes.into()))
-                    }
-                    Type::PlutusV2 => {
-                        PseudoScript::PlutusV2Script(conway::PlutusScript(bytes.into()))
-                    }
-                    Type::PlutusV3 => {
-                        PseudoScript::PlutusV3Script(conway::PlutusScript(bytes.into()))
-                    }
-                    Type::Timelock => {
-                        unreachable!("native script cannot be a used referenced in a script_ref")
-                    }
-                };
-
-                Some(CborWrap(script))
-            } else {
-                None
-            };
+/// How long the provider asked us to wait before retrying, if it said so in seconds
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let seconds = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
 
-            let output: TransactionOutput =
-                TransactionOutput::PostAlonzo(PostAlonzoTransactionOutput {
-                    address: Address::from_bech32(&output.address)?.to_vec().into(),
-                    datum_option: datum,
-                    script_ref,
-                    value,
-                });
-            resolved_inputs.push(ResolvedInput { input, output });
-        }
-        Ok(resolved_inputs)
+fn parse_response<T: DeserializeOwned>(provider: &'static str, body: &str) -> QueryResult<T> {
+    serde_json::from_str(body).map_err(|e| ChainQueryError::malformed(provider, e))
+}
+
+/// Builds a ledger value out of a lovelace amount and a flat list of native assets,
+/// merging duplicate entries and keeping policies and asset names in canonical order.
+fn build_value(coin: u64, assets: Vec<(Hash<28>, Bytes, u64)>) -> Result<conway::Value> {
+    let mut multiasset: BTreeMap<Hash<28>, BTreeMap<Bytes, u64>> = BTreeMap::new();
+    for (policy, asset_name, amount) in assets {
+        *multiasset
+            .entry(policy)
+            .or_default()
+            .entry(asset_name)
+            .or_default() += amount;
     }
-    fn get_slot_config(&self) -> Result<SlotConfig> {
-        Ok(self.environment.slot_config())
+    if multiasset.is_empty() {
+        return Ok(conway::Value::Coin(coin));
     }
+    let multiasset = multiasset
+        .into_iter()
+        .map(|(policy, assets)| {
+            let assets = assets
+                .into_iter()
+                .map(|(asset_name, amount)| {
+                    let amount = PositiveCoin::try_from(amount)
+                        .map_err(|_| anyhow!("asset quantity must be positive"))?;
+                    Ok((asset_name, amount))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok((policy, NonEmptyKeyValuePairs::Def(assets)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(conway::Value::Multiasset(
+        coin,
+        NonEmptyKeyValuePairs::Def(multiasset),
+    ))
 }
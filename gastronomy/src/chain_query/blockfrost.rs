@@ -0,0 +1,331 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt, stream};
+use pallas::{
+    codec::utils::{Bytes, CborWrap},
+    ledger::{
+        addresses::Address,
+        primitives::conway::{
+            DatumOption, Language, PostAlonzoTransactionOutput, PseudoScript, ScriptRef,
+            TransactionOutput,
+        },
+    },
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use uplc::{
+    Fragment, Hash, PlutusData, TransactionInput,
+    machine::cost_model::ExBudget,
+    tx::{ResolvedInput, SlotConfig},
+};
+
+use super::{
+    ChainQuery, ChainQueryError, Network, QueryResult, ScriptLanguage, build_script_ref,
+    build_value, native_script_from_json, parse_response, send_request,
+};
+use crate::{
+    config::BlockfrostConfig,
+    protocol_parameters::{ProtocolParameters, parse_cost_model, parse_i64},
+};
+
+const PROVIDER: &str = "blockfrost";
+
+/// Queries the Blockfrost API, or any other service which implements it
+/// (such as the one bundled with yaci-devkit).
+pub struct Blockfrost {
+    api_key: String,
+    base_url: String,
+    network: Network,
+    max_concurrent_requests: usize,
+    client: reqwest::Client,
+}
+
+impl Blockfrost {
+    pub fn new(
+        config: &BlockfrostConfig,
+        network: Option<Network>,
+        max_concurrent_requests: usize,
+    ) -> Result<Self> {
+        // Blockfrost keys start with the name of the network they're for
+        let network = network.unwrap_or_else(|| {
+            if config.key.starts_with("preview") {
+                Network::Preview
+            } else if config.key.starts_with("preprod") {
+                Network::Preprod
+            } else {
+                Network::Mainnet
+            }
+        });
+        let Some(base_url) = network.blockfrost_url() else {
+            bail!(
+                "no url configured for network {}, blockfrost doesn't know where to connect",
+                network.name()
+            );
+        };
+        Ok(Blockfrost {
+            api_key: config.key.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            network,
+            max_concurrent_requests,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> QueryResult<T> {
+        self.try_get(path)
+            .await?
+            .ok_or_else(|| ChainQueryError::not_found(PROVIDER, path))
+    }
+
+    /// Like `get`, but treats a 404 as a missing result instead of an error
+    async fn try_get<T: DeserializeOwned>(&self, path: &str) -> QueryResult<Option<T>> {
+        let request = self
+            .client
+            .get(format!("{}/{}", self.base_url, path))
+            .header("project_id", self.api_key.as_str());
+        match send_request(PROVIDER, request).await? {
+            Some(body) => Ok(Some(parse_response(PROVIDER, &body)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BlockfrostCbor {
+    cbor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BlockfrostJson {
+    json: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct BlockfrostTxUtxos {
+    outputs: Vec<BlockfrostOutput>,
+}
+
+#[derive(Deserialize)]
+struct BlockfrostOutput {
+    address: String,
+    amount: Vec<BlockfrostAmount>,
+    output_index: u64,
+    data_hash: Option<String>,
+    inline_datum: Option<String>,
+    reference_script_hash: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BlockfrostAmount {
+    unit: String,
+    quantity: String,
+}
+
+#[derive(Deserialize)]
+struct BlockfrostScript {
+    #[serde(rename = "type")]
+    script_type: String,
+}
+
+#[derive(Deserialize)]
+struct BlockfrostProtocolParameters {
+    cost_models_raw: Option<serde_json::Value>,
+    max_tx_ex_mem: Option<serde_json::Value>,
+    max_tx_ex_steps: Option<serde_json::Value>,
+}
+
+#[async_trait]
+impl ChainQuery for Blockfrost {
+    fn network(&self) -> Option<&str> {
+        Some(self.network.name())
+    }
+
+    async fn get_tx_bytes(&self, tx_id: Hash<32>) -> QueryResult<Bytes> {
+        let Some(tx) = self
+            .try_get::<BlockfrostCbor>(&format!("txs/{}/cbor", tx_id))
+            .await?
+        else {
+            return Err(ChainQueryError::not_found(
+                PROVIDER,
+                format!("transaction {}", tx_id),
+            ));
+        };
+        decode_cbor(tx)
+    }
+
+    async fn get_utxos(&self, inputs: Vec<TransactionInput>) -> QueryResult<Vec<ResolvedInput>> {
+        // Inputs spending the same transaction share one request
+        let mut tx_ids = HashSet::new();
+        let tx_inputs: Vec<&TransactionInput> = inputs
+            .iter()
+            .filter(|input| tx_ids.insert(input.transaction_id))
+            .collect();
+        let txs: HashMap<Hash<32>, Vec<BlockfrostOutput>> = stream::iter(tx_inputs)
+            .map(|input| async move {
+                let tx: Option<BlockfrostTxUtxos> = self
+                    .try_get(&format!("txs/{}/utxos", input.transaction_id))
+                    .await
+                    .map_err(|e| e.for_input(input))?;
+                let outputs = tx.map(|tx| tx.outputs).unwrap_or_default();
+                Ok::<_, ChainQueryError>((input.transaction_id, outputs))
+            })
+            .buffer_unordered(self.max_concurrent_requests)
+            .try_collect()
+            .await?;
+
+        let mut outputs = vec![];
+        for input in &inputs {
+            let Some(output) = txs
+                .get(&input.transaction_id)
+                .into_iter()
+                .flatten()
+                .find(|output| output.output_index == input.index)
+            else {
+                return Err(ChainQueryError::not_found(
+                    PROVIDER,
+                    format!("utxo {}#{}", input.transaction_id, input.index),
+                ));
+            };
+            outputs.push(output);
+        }
+
+        // Many inputs usually share a handful of reference scripts, so only fetch each one once
+        let script_hashes: HashSet<&String> = outputs
+            .iter()
+            .filter_map(|output| output.reference_script_hash.as_ref())
+            .collect();
+        let scripts: HashMap<&String, ScriptRef> =
+            stream::iter(script_hashes)
+                .map(|hash| async move {
+                    Ok::<_, ChainQueryError>((hash, self.get_script(hash).await?))
+                })
+                .buffer_unordered(self.max_concurrent_requests)
+                .try_collect()
+                .await?;
+
+        inputs
+            .into_iter()
+            .zip(outputs)
+            .map(|(input, output)| {
+                let output = to_transaction_output(output, &scripts)
+                    .map_err(|e| ChainQueryError::malformed(PROVIDER, e).for_input(&input))?;
+                Ok(ResolvedInput { input, output })
+            })
+            .collect()
+    }
+
+    async fn get_datum(&self, datum_hash: Hash<32>) -> QueryResult<Option<Bytes>> {
+        let datum: Option<BlockfrostCbor> = self
+            .try_get(&format!("scripts/datum/{}/cbor", datum_hash))
+            .await?;
+        datum.map(decode_cbor).transpose()
+    }
+
+    async fn get_slot_config(&self) -> QueryResult<SlotConfig> {
+        Ok(self.network.slot_config())
+    }
+
+    async fn get_protocol_parameters(&self) -> QueryResult<ProtocolParameters> {
+        let parameters: BlockfrostProtocolParameters = self.get("epochs/latest/parameters").await?;
+        let cost_models = parameters.cost_models_raw.unwrap_or_default();
+        let cost_model = |name: &str, language| parse_cost_model(language, &cost_models[name]);
+        let parse = || -> Result<ProtocolParameters> {
+            let max_tx_ex_units = match (parameters.max_tx_ex_mem, parameters.max_tx_ex_steps) {
+                (Some(mem), Some(steps)) => Some(ExBudget {
+                    mem: parse_i64(&mem)?,
+                    cpu: parse_i64(&steps)?,
+                }),
+                _ => None,
+            };
+            Ok(ProtocolParameters {
+                plutus_v1_cost_model: cost_model("PlutusV1", Language::PlutusV1)?,
+                plutus_v2_cost_model: cost_model("PlutusV2", Language::PlutusV2)?,
+                plutus_v3_cost_model: cost_model("PlutusV3", Language::PlutusV3)?,
+                max_tx_ex_units,
+            })
+        };
+        parse().map_err(|e| ChainQueryError::malformed(PROVIDER, e))
+    }
+}
+
+fn decode_cbor(response: BlockfrostCbor) -> QueryResult<Bytes> {
+    let Some(cbor) = response.cbor else {
+        return Err(ChainQueryError::malformed(
+            PROVIDER,
+            anyhow!("missing cbor"),
+        ));
+    };
+    let bytes = hex::decode(cbor).map_err(|e| ChainQueryError::malformed(PROVIDER, e))?;
+    Ok(bytes.into())
+}
+
+impl Blockfrost {
+    async fn get_script(&self, hash: &str) -> QueryResult<ScriptRef> {
+        let script: BlockfrostScript = self.get(&format!("scripts/{}", hash)).await?;
+        let language = match script.script_type.as_str() {
+            "timelock" => {
+                // blockfrost only serves native scripts as JSON
+                let script: BlockfrostJson = self.get(&format!("scripts/{}/json", hash)).await?;
+                let script = native_script_from_json(&script.json.unwrap_or_default())
+                    .map_err(|e| ChainQueryError::malformed(PROVIDER, e))?;
+                return Ok(PseudoScript::NativeScript(script));
+            }
+            "plutusV1" => ScriptLanguage::PlutusV1,
+            "plutusV2" => ScriptLanguage::PlutusV2,
+            "plutusV3" => ScriptLanguage::PlutusV3,
+            other => return Err(ChainQueryError::UnsupportedScript(other.to_string())),
+        };
+        let cbor: BlockfrostCbor = self.get(&format!("scripts/{}/cbor", hash)).await?;
+        build_script_ref(language, decode_cbor(cbor)?.to_vec())
+            .map_err(|e| ChainQueryError::malformed(PROVIDER, e))
+    }
+}
+
+fn to_transaction_output(
+    output: &BlockfrostOutput,
+    scripts: &HashMap<&String, ScriptRef>,
+) -> Result<TransactionOutput> {
+    let datum_option = match (&output.inline_datum, &output.data_hash) {
+        (Some(datum), _) => {
+            let bytes = hex::decode(datum)?;
+            let data = PlutusData::decode_fragment(&bytes)
+                .map_err(|e| anyhow!("could not decode inline datum: {}", e))?;
+            Some(DatumOption::Data(CborWrap(data)))
+        }
+        (None, Some(hash)) => Some(DatumOption::Hash(hex::decode(hash)?[..].into())),
+        (None, None) => None,
+    };
+
+    let mut coin = 0;
+    let mut assets = vec![];
+    for asset in &output.amount {
+        if asset.unit == "lovelace" {
+            coin += asset.quantity.parse::<u64>()?;
+            continue;
+        }
+        if asset.unit.len() < 56 {
+            bail!("invalid asset unit {}", asset.unit);
+        }
+        let (policy, asset_name) = asset.unit.split_at(56);
+        let policy: Hash<28> = hex::decode(policy)?[..].into();
+        let asset_name: Bytes = hex::decode(asset_name)?.into();
+        assets.push((policy, asset_name, asset.quantity.parse()?));
+    }
+    let value = build_value(coin, assets)?;
+
+    let script_ref = match &output.reference_script_hash {
+        Some(hash) => match scripts.get(hash) {
+            Some(script) => Some(CborWrap(script.clone())),
+            None => bail!("reference script {} was not fetched", hash),
+        },
+        None => None,
+    };
+
+    Ok(TransactionOutput::PostAlonzo(PostAlonzoTransactionOutput {
+        address: Address::from_bech32(&output.address)?.to_vec().into(),
+        datum_option,
+        script_ref,
+        value,
+    }))
+}
@@ -0,0 +1,411 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use pallas::{
+    codec::utils::{Bytes, CborWrap},
+    crypto::hash::Hasher,
+    ledger::primitives::conway::{PseudoScript, ScriptRef, TransactionOutput},
+};
+use serde_json::json;
+use uplc::{
+    Hash, TransactionInput,
+    tx::{ResolvedInput, SlotConfig},
+};
+
+use super::{ChainQuery, ChainQueryError, QueryResult};
+use crate::protocol_parameters::ProtocolParameters;
+
+/// Wraps another provider, and keeps every response it gives on disk.
+///
+/// Entries are stored under `<dir>/<network>/`, keyed by transaction or script hash:
+/// - `tx/<tx hash>.cbor` holds transaction bytes
+/// - `utxo/<tx hash>-<index>.cbor` holds a resolved output, minus its reference script
+/// - `script/<script hash>.cbor` holds reference scripts, shared between outputs
+/// - `datum/<datum hash>.cbor` holds datums looked up by hash
+///
+/// The slot config and protocol parameters can change between epochs, so they are refreshed
+/// whenever we're online. The last ones seen are only served when running offline,
+/// and only for `LEDGER_STATE_MAX_AGE` after they were fetched.
+pub struct Cache {
+    inner: Box<dyn ChainQuery>,
+    network: String,
+    dir: PathBuf,
+    offline: bool,
+}
+
+/// About an epoch on mainnet
+const LEDGER_STATE_MAX_AGE: Duration = Duration::from_secs(5 * 24 * 60 * 60);
+
+pub fn default_cache_dir() -> Option<PathBuf> {
+    home::home_dir().map(|home| home.join(".cache").join("gastronomy"))
+}
+
+impl Cache {
+    /// Entries are kept apart by the provider's network,
+    /// or the configured one for providers which can't tell (or when there's no provider at all)
+    pub fn new(
+        inner: Box<dyn ChainQuery>,
+        dir: &Path,
+        network: Option<&str>,
+        offline: bool,
+    ) -> Result<Self> {
+        let Some(network) = inner.network().or(network).map(str::to_string) else {
+            bail!("cannot tell which network to cache, pick one with --network");
+        };
+        Ok(Cache {
+            dir: dir.join(&network),
+            network,
+            inner,
+            offline,
+        })
+    }
+
+    /// Removes entries written more than `max_age` ago (or every entry, if no age is given),
+    /// and returns the number of files removed.
+    pub fn prune(dir: &Path, max_age: Option<Duration>) -> Result<usize> {
+        if !dir.exists() {
+            return Ok(0);
+        }
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                removed += Self::prune(&path, max_age)?;
+                if fs::read_dir(&path)?.next().is_none() {
+                    fs::remove_dir(&path)?;
+                }
+                continue;
+            }
+            let expired = match max_age {
+                Some(max_age) => {
+                    let modified = fs::metadata(&path)?.modified()?;
+                    now.duration_since(modified).unwrap_or_default() > max_age
+                }
+                None => true,
+            };
+            if expired {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn read(&self, entry: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.dir.join(entry);
+        match fs::read(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("could not read {}", path.display())),
+        }
+    }
+
+    /// Reads an entry describing ledger state, as long as it's recent enough to trust
+    fn read_ledger_state(&self, entry: &str, description: &str) -> QueryResult<Vec<u8>> {
+        let path = self.dir.join(entry);
+        let expired = match fs::metadata(&path).and_then(|m| m.modified()) {
+            Ok(modified) => {
+                let age = SystemTime::now()
+                    .duration_since(modified)
+                    .unwrap_or_default();
+                age > LEDGER_STATE_MAX_AGE
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ChainQueryError::NotCached(description.to_string()));
+            }
+            Err(e) => {
+                return Err(anyhow::Error::from(e)
+                    .context(format!("could not read {}", path.display()))
+                    .into());
+            }
+        };
+        if expired {
+            return Err(ChainQueryError::NotCached(format!(
+                "{} from the last {} days",
+                description,
+                LEDGER_STATE_MAX_AGE.as_secs() / (24 * 60 * 60)
+            )));
+        }
+        match self.read(entry)? {
+            Some(bytes) => Ok(bytes),
+            None => Err(ChainQueryError::NotCached(description.to_string())),
+        }
+    }
+
+    fn write(&self, entry: &str, bytes: &[u8]) -> Result<()> {
+        let path = self.dir.join(entry);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("could not create cache dir {}", dir.display()))?;
+        }
+        fs::write(&path, bytes).with_context(|| format!("could not write {}", path.display()))
+    }
+
+    fn read_output(&self, input: &TransactionInput) -> Result<Option<TransactionOutput>> {
+        let Some(bytes) = self.read(&utxo_entry(input))? else {
+            return Ok(None);
+        };
+        let (mut output, script_hash): (TransactionOutput, Option<Hash<28>>) =
+            minicbor::decode(&bytes).map_err(|e| anyhow!("corrupt cache entry: {}", e))?;
+        if let Some(script_hash) = script_hash {
+            let Some(bytes) = self.read(&format!("script/{}.cbor", script_hash))? else {
+                return Ok(None);
+            };
+            let script: ScriptRef =
+                minicbor::decode(&bytes).map_err(|e| anyhow!("corrupt cache entry: {}", e))?;
+            if let TransactionOutput::PostAlonzo(output) = &mut output {
+                output.script_ref = Some(CborWrap(script));
+            }
+        }
+        Ok(Some(output))
+    }
+
+    fn write_output(&self, input: &TransactionInput, output: &TransactionOutput) -> Result<()> {
+        let mut output = output.clone();
+        let mut script_hash = None;
+        if let TransactionOutput::PostAlonzo(output) = &mut output
+            && let Some(CborWrap(script)) = output.script_ref.take()
+        {
+            let hash = compute_script_hash(&script)?;
+            self.write(
+                &format!("script/{}.cbor", hash),
+                &minicbor::to_vec(&script)?,
+            )?;
+            script_hash = Some(hash);
+        }
+        self.write(
+            &utxo_entry(input),
+            &minicbor::to_vec((&output, script_hash))?,
+        )
+    }
+}
+
+#[async_trait]
+impl ChainQuery for Cache {
+    fn network(&self) -> Option<&str> {
+        Some(&self.network)
+    }
+
+    fn check_tx_lookup(&self, tx_id: Hash<32>) -> QueryResult<()> {
+        if self.dir.join(tx_entry(tx_id)).exists() {
+            return Ok(());
+        }
+        self.inner.check_tx_lookup(tx_id)
+    }
+
+    async fn get_tx_bytes(&self, tx_id: Hash<32>) -> QueryResult<Bytes> {
+        let entry = tx_entry(tx_id);
+        if let Some(bytes) = self.read(&entry)? {
+            return Ok(bytes.into());
+        }
+        if self.offline {
+            return Err(ChainQueryError::NotCached(format!("transaction {}", tx_id)));
+        }
+        let bytes = self.inner.get_tx_bytes(tx_id).await?;
+        self.write(&entry, &bytes)?;
+        Ok(bytes)
+    }
+
+    async fn get_utxos(&self, inputs: Vec<TransactionInput>) -> QueryResult<Vec<ResolvedInput>> {
+        let mut outputs = HashMap::new();
+        let mut missing = vec![];
+        for input in &inputs {
+            match self.read_output(input)? {
+                Some(output) => {
+                    outputs.insert(utxo_entry(input), output);
+                }
+                None => missing.push(input.clone()),
+            }
+        }
+
+        if let Some(input) = missing.first() {
+            if self.offline {
+                return Err(ChainQueryError::NotCached(format!(
+                    "utxo {}#{}",
+                    input.transaction_id, input.index
+                )));
+            }
+            for resolved in self.inner.get_utxos(missing).await? {
+                self.write_output(&resolved.input, &resolved.output)?;
+                outputs.insert(utxo_entry(&resolved.input), resolved.output);
+            }
+        }
+
+        inputs
+            .into_iter()
+            .map(|input| {
+                let Some(output) = outputs.get(&utxo_entry(&input)).cloned() else {
+                    return Err(anyhow!(
+                        "utxo {}#{} was not resolved",
+                        input.transaction_id,
+                        input.index
+                    )
+                    .into());
+                };
+                Ok(ResolvedInput { input, output })
+            })
+            .collect()
+    }
+
+    async fn get_datum(&self, datum_hash: Hash<32>) -> QueryResult<Option<Bytes>> {
+        let entry = format!("datum/{}.cbor", datum_hash);
+        if let Some(bytes) = self.read(&entry)? {
+            return Ok(Some(bytes.into()));
+        }
+        if self.offline {
+            return Err(ChainQueryError::NotCached(format!("datum {}", datum_hash)));
+        }
+        let datum = self.inner.get_datum(datum_hash).await?;
+        if let Some(bytes) = &datum {
+            self.write(&entry, bytes)?;
+        }
+        Ok(datum)
+    }
+
+    async fn get_slot_config(&self) -> QueryResult<SlotConfig> {
+        if self.offline {
+            let bytes = self.read_ledger_state("slot_config.json", "the slot config")?;
+            return Ok(read_slot_config(&bytes).context("corrupt cache entry")?);
+        }
+        let slot_config = self.inner.get_slot_config().await?;
+        let value = json!({
+            "zeroTime": slot_config.zero_time,
+            "zeroSlot": slot_config.zero_slot,
+            "slotLength": slot_config.slot_length,
+        });
+        self.write("slot_config.json", value.to_string().as_bytes())?;
+        Ok(slot_config)
+    }
+
+    async fn get_protocol_parameters(&self) -> QueryResult<ProtocolParameters> {
+        if self.offline {
+            let bytes =
+                self.read_ledger_state("protocol_parameters.json", "the protocol parameters")?;
+            let value: serde_json::Value =
+                serde_json::from_slice(&bytes).context("corrupt cache entry")?;
+            return Ok(ProtocolParameters::from_cli_json(&value).context("corrupt cache entry")?);
+        }
+        let parameters = self.inner.get_protocol_parameters().await?;
+        self.write(
+            "protocol_parameters.json",
+            parameters.to_cli_json().to_string().as_bytes(),
+        )?;
+        Ok(parameters)
+    }
+}
+
+fn read_slot_config(bytes: &[u8]) -> Result<SlotConfig> {
+    let value: serde_json::Value = serde_json::from_slice(bytes)?;
+    let field = |name: &str| {
+        value[name]
+            .as_u64()
+            .ok_or_else(|| anyhow!("missing {}", name))
+    };
+    Ok(SlotConfig {
+        zero_time: field("zeroTime")?,
+        zero_slot: field("zeroSlot")?,
+        slot_length: field("slotLength")?.try_into()?,
+    })
+}
+
+fn tx_entry(tx_id: Hash<32>) -> String {
+    format!("tx/{}.cbor", tx_id)
+}
+
+fn utxo_entry(input: &TransactionInput) -> String {
+    format!("utxo/{}-{}.cbor", input.transaction_id, input.index)
+}
+
+fn compute_script_hash(script: &ScriptRef) -> Result<Hash<28>> {
+    let hash = match script {
+        PseudoScript::NativeScript(script) => {
+            Hasher::<224>::hash_tagged(&minicbor::to_vec(script)?, 0)
+        }
+        PseudoScript::PlutusV1Script(script) => Hasher::<224>::hash_tagged(&script.0, 1),
+        PseudoScript::PlutusV2Script(script) => Hasher::<224>::hash_tagged(&script.0, 2),
+        PseudoScript::PlutusV3Script(script) => Hasher::<224>::hash_tagged(&script.0, 3),
+    };
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::{
+        chain_query::{MockChainQuery, from_config},
+        config::Config,
+    };
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gastronomy-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn cache(dir: &Path, offline: bool) -> Result<Cache> {
+        Cache::new(
+            Box::new(MockChainQuery::new().with_network("mainnet")),
+            dir,
+            None,
+            offline,
+        )
+    }
+
+    #[tokio::test]
+    async fn serves_recent_ledger_state_offline() -> Result<()> {
+        let dir = cache_dir("recent");
+        let online = cache(&dir, false)?.get_slot_config().await?;
+        let offline = cache(&dir, true)?.get_slot_config().await?;
+        assert_eq!(offline.zero_time, online.zero_time);
+        assert_eq!(offline.zero_slot, online.zero_slot);
+        assert_eq!(offline.slot_length, online.slot_length);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expires_ledger_state_offline() -> Result<()> {
+        let dir = cache_dir("expired");
+        cache(&dir, false)?.get_protocol_parameters().await?;
+        let entry = dir.join("mainnet").join("protocol_parameters.json");
+        let fetched = SystemTime::now() - LEDGER_STATE_MAX_AGE - Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(&entry)?
+            .set_modified(fetched)?;
+
+        let result = cache(&dir, true)?.get_protocol_parameters().await;
+        assert!(matches!(result, Err(ChainQueryError::NotCached(_))));
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn works_offline_without_a_provider() -> Result<()> {
+        let dir = cache_dir("no-provider");
+        // filled while a provider was configured
+        cache(&dir, false)?.get_slot_config().await?;
+
+        let config: Config = serde_json::from_value(json!({
+            "network": "mainnet",
+            "offline": true,
+            "cacheDir": dir,
+        }))?;
+        let query = from_config(&config)?;
+        assert_eq!(query.network(), Some("mainnet"));
+        query.get_slot_config().await?;
+        let missing = query.get_tx_bytes(Hash::new([0; 32])).await;
+        assert!(matches!(missing, Err(ChainQueryError::NotCached(_))));
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
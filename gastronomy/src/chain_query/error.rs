@@ -0,0 +1,76 @@
+use std::fmt::Display;
+
+use thiserror::Error;
+use uplc::TransactionInput;
+
+#[derive(Debug, Error)]
+pub enum ChainQueryError {
+    #[error(
+        "no chain query provider configured, \
+        consider adding a blockfrost API key, a koios network or ogmios and kupo urls"
+    )]
+    NotConfigured,
+    #[error("{provider} has no {what}")]
+    NotFound {
+        provider: &'static str,
+        what: String,
+    },
+    #[error("{provider} is rate limiting requests, try again later")]
+    RateLimited { provider: &'static str },
+    #[error("malformed response from {provider}: {reason}")]
+    MalformedResponse {
+        provider: &'static str,
+        reason: String,
+    },
+    #[error("unsupported reference script type {0}")]
+    UnsupportedScript(String),
+    #[error(
+        "{0} cannot look up transactions by id, \
+        save the transaction to a file (e.g. from a block explorer) and load that instead"
+    )]
+    NoTxLookup(&'static str),
+    #[error("{0} is not cached, and gastronomy is running offline")]
+    NotCached(String),
+    #[error("request to {provider} failed")]
+    Http {
+        provider: &'static str,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("could not resolve input {input}")]
+    Input {
+        input: String,
+        #[source]
+        source: Box<ChainQueryError>,
+    },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl ChainQueryError {
+    pub(crate) fn not_found(provider: &'static str, what: impl Display) -> Self {
+        Self::NotFound {
+            provider,
+            what: what.to_string(),
+        }
+    }
+
+    /// Keeps the whole chain of causes, since they usually point at the offending field
+    pub(crate) fn malformed(provider: &'static str, reason: impl Into<anyhow::Error>) -> Self {
+        match reason.into().downcast::<Self>() {
+            Ok(error) => error,
+            Err(reason) => Self::MalformedResponse {
+                provider,
+                reason: format!("{:#}", reason),
+            },
+        }
+    }
+
+    /// Records which input was being resolved when this error happened
+    pub(crate) fn for_input(self, input: &TransactionInput) -> Self {
+        Self::Input {
+            input: format!("{}#{}", input.transaction_id, input.index),
+            source: Box::new(self),
+        }
+    }
+}
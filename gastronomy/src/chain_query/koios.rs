@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt, stream};
+use pallas::{
+    codec::utils::{Bytes, CborWrap},
+    ledger::{
+        addresses::Address,
+        primitives::conway::{DatumOption, PostAlonzoTransactionOutput, TransactionOutput},
+    },
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::json;
+use uplc::{
+    Fragment, Hash, PlutusData, TransactionInput,
+    tx::{ResolvedInput, SlotConfig},
+};
+
+use super::{
+    ChainQuery, ChainQueryError, Network, QueryResult, ScriptLanguage, build_script_ref,
+    build_value, parse_response, send_request,
+};
+use crate::{config::KoiosConfig, protocol_parameters::ProtocolParameters};
+
+const PROVIDER: &str = "koios";
+/// Koios pages responses past 1000 rows, and each input is one row
+const UTXO_REFS_PER_REQUEST: usize = 100;
+
+pub struct Koios {
+    base_url: String,
+    token: Option<String>,
+    network: Network,
+    max_concurrent_requests: usize,
+    client: reqwest::Client,
+}
+
+impl Koios {
+    /// Queries mainnet unless another network is configured
+    pub fn new(
+        config: &KoiosConfig,
+        network: Option<Network>,
+        max_concurrent_requests: usize,
+    ) -> Result<Self> {
+        let network = network.unwrap_or(Network::Mainnet);
+        let Some(base_url) = config.url.clone().or_else(|| network.koios_url()) else {
+            bail!(
+                "no url configured for network {}, koios doesn't know where to connect",
+                network.name()
+            );
+        };
+        Ok(Koios {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: config.token.clone(),
+            network,
+            max_concurrent_requests,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, endpoint: &str) -> QueryResult<T> {
+        let request = self.client.get(format!("{}/{}", self.base_url, endpoint));
+        self.send(request).await
+    }
+
+    async fn post<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        body: serde_json::Value,
+    ) -> QueryResult<T> {
+        let request = self
+            .client
+            .post(format!("{}/{}", self.base_url, endpoint))
+            .header("content-type", "application/json")
+            .body(body.to_string());
+        self.send(request).await
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> QueryResult<T> {
+        let mut request = request.header("accept", "application/json");
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        // koios answers lookups with an empty list rather than a 404,
+        // so a 404 means the endpoint itself is missing
+        let Some(body) = send_request(PROVIDER, request).await? else {
+            return Err(ChainQueryError::malformed(
+                PROVIDER,
+                anyhow!("endpoint not found, is the url right?"),
+            ));
+        };
+        parse_response(PROVIDER, &body)
+    }
+}
+
+#[derive(Deserialize)]
+struct KoiosTxCbor {
+    tx_hash: String,
+    cbor: String,
+}
+
+#[derive(Deserialize)]
+struct KoiosUtxo {
+    tx_hash: String,
+    tx_index: u64,
+    address: String,
+    value: String,
+    datum_hash: Option<String>,
+    inline_datum: Option<KoiosInlineDatum>,
+    reference_script: Option<KoiosScript>,
+    asset_list: Option<Vec<KoiosAsset>>,
+}
+
+#[derive(Deserialize)]
+struct KoiosInlineDatum {
+    bytes: String,
+}
+
+#[derive(Deserialize)]
+struct KoiosScript {
+    #[serde(rename = "type")]
+    script_type: String,
+    bytes: String,
+}
+
+#[derive(Deserialize)]
+struct KoiosDatum {
+    datum_hash: String,
+    bytes: String,
+}
+
+#[derive(Deserialize)]
+struct KoiosAsset {
+    policy_id: String,
+    asset_name: Option<String>,
+    quantity: String,
+}
+
+#[async_trait]
+impl ChainQuery for Koios {
+    fn network(&self) -> Option<&str> {
+        Some(self.network.name())
+    }
+
+    async fn get_tx_bytes(&self, tx_id: Hash<32>) -> QueryResult<Bytes> {
+        let tx_id = hex::encode(tx_id);
+        let txs: Vec<KoiosTxCbor> = self
+            .post("tx_cbor", json!({ "_tx_hashes": [tx_id] }))
+            .await?;
+        let Some(tx) = txs.into_iter().find(|tx| tx.tx_hash == tx_id) else {
+            return Err(ChainQueryError::not_found(
+                PROVIDER,
+                format!("transaction {}", tx_id),
+            ));
+        };
+        let bytes = hex::decode(tx.cbor).map_err(|e| ChainQueryError::malformed(PROVIDER, e))?;
+        Ok(bytes.into())
+    }
+
+    async fn get_utxos(&self, inputs: Vec<TransactionInput>) -> QueryResult<Vec<ResolvedInput>> {
+        let refs: Vec<String> = inputs
+            .iter()
+            .map(|input| format!("{}#{}", hex::encode(input.transaction_id), input.index))
+            .collect();
+        let pages: Vec<Vec<KoiosUtxo>> = stream::iter(refs.chunks(UTXO_REFS_PER_REQUEST))
+            .map(|refs| {
+                self.post::<Vec<KoiosUtxo>>(
+                    "utxo_info",
+                    json!({ "_utxo_refs": refs, "_extended": true }),
+                )
+            })
+            .buffer_unordered(self.max_concurrent_requests)
+            .try_collect()
+            .await?;
+        let mut utxos: HashMap<(String, u64), KoiosUtxo> = pages
+            .into_iter()
+            .flatten()
+            .map(|utxo| ((utxo.tx_hash.clone(), utxo.tx_index), utxo))
+            .collect();
+
+        let mut resolved_inputs = vec![];
+        for input in inputs {
+            let key = (hex::encode(input.transaction_id), input.index);
+            let Some(utxo) = utxos.remove(&key) else {
+                return Err(ChainQueryError::not_found(
+                    PROVIDER,
+                    format!("utxo {}#{}", key.0, key.1),
+                ));
+            };
+            let output = to_transaction_output(utxo)
+                .map_err(|e| ChainQueryError::malformed(PROVIDER, e).for_input(&input))?;
+            resolved_inputs.push(ResolvedInput { input, output });
+        }
+        Ok(resolved_inputs)
+    }
+
+    async fn get_datum(&self, datum_hash: Hash<32>) -> QueryResult<Option<Bytes>> {
+        let datum_hash = hex::encode(datum_hash);
+        let datums: Vec<KoiosDatum> = self
+            .post("datum_info", json!({ "_datum_hashes": [datum_hash] }))
+            .await?;
+        datums
+            .into_iter()
+            .find(|datum| datum.datum_hash == datum_hash)
+            .map(|datum| match hex::decode(datum.bytes) {
+                Ok(bytes) => Ok(bytes.into()),
+                Err(e) => Err(ChainQueryError::malformed(PROVIDER, e)),
+            })
+            .transpose()
+    }
+
+    async fn get_slot_config(&self) -> QueryResult<SlotConfig> {
+        Ok(self.network.slot_config())
+    }
+
+    async fn get_protocol_parameters(&self) -> QueryResult<ProtocolParameters> {
+        // koios serves these in the same shape as cardano-cli
+        let parameters: serde_json::Value = self.get("cli_protocol_params").await?;
+        ProtocolParameters::from_cli_json(&parameters)
+            .map_err(|e| ChainQueryError::malformed(PROVIDER, e))
+    }
+}
+
+fn to_transaction_output(utxo: KoiosUtxo) -> Result<TransactionOutput> {
+    let datum_option = match (utxo.inline_datum, utxo.datum_hash) {
+        (Some(datum), _) => {
+            let bytes = hex::decode(datum.bytes)?;
+            let data = PlutusData::decode_fragment(&bytes)
+                .map_err(|e| anyhow!("could not decode inline datum: {}", e))?;
+            Some(DatumOption::Data(CborWrap(data)))
+        }
+        (None, Some(hash)) => Some(DatumOption::Hash(hex::decode(hash)?[..].into())),
+        (None, None) => None,
+    };
+
+    let mut assets = vec![];
+    for asset in utxo.asset_list.unwrap_or_default() {
+        let policy: Hash<28> = hex::decode(&asset.policy_id)?[..].into();
+        let asset_name: Bytes = hex::decode(asset.asset_name.unwrap_or_default())?.into();
+        assets.push((policy, asset_name, asset.quantity.parse()?));
+    }
+    let value = build_value(utxo.value.parse()?, assets)?;
+
+    let script_ref = match utxo.reference_script {
+        Some(script) => {
+            let bytes = hex::decode(script.bytes)?;
+            let language = match script.script_type.as_str() {
+                "timelock" | "multisig" => ScriptLanguage::Native,
+                "plutusV1" => ScriptLanguage::PlutusV1,
+                "plutusV2" => ScriptLanguage::PlutusV2,
+                "plutusV3" => ScriptLanguage::PlutusV3,
+                other => return Err(ChainQueryError::UnsupportedScript(other.to_string()).into()),
+            };
+            Some(CborWrap(build_script_ref(language, bytes)?))
+        }
+        None => None,
+    };
+
+    Ok(TransactionOutput::PostAlonzo(PostAlonzoTransactionOutput {
+        address: Address::from_bech32(&utxo.address)?.to_vec().into(),
+        datum_option,
+        script_ref,
+        value,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use mockito::{Matcher, Server};
+    use pallas::ledger::primitives::conway::Value;
+
+    use super::*;
+    use crate::{
+        chain_query::{MAX_RETRIES, UtxoFile},
+        config::KoiosConfig,
+    };
+
+    const TX_HASH: &str = "d39dffddd163b357e1cc234541f6325167d66e03d0671928e1c31f2c04a2b22e";
+
+    fn koios(server: &Server) -> Koios {
+        let config = KoiosConfig {
+            url: Some(server.url()),
+            token: None,
+        };
+        Koios::new(&config, Some(Network::Mainnet), 2).unwrap()
+    }
+
+    fn test_data(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../test_data")
+            .join(name)
+    }
+
+    /// Koios's answer for the inputs of `sample.tx`,
+    /// the same outputs `sample.utxos.json` has in cardano-cli's format
+    fn utxo_info() -> Vec<serde_json::Value> {
+        let json = fs::read_to_string(test_data("koios/utxo_info.json")).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn utxo() -> serde_json::Value {
+        utxo_info()
+            .into_iter()
+            .filter(|utxo| utxo["tx_hash"] == TX_HASH)
+            .collect()
+    }
+
+    fn input_ref(utxo: &serde_json::Value) -> TransactionInput {
+        TransactionInput {
+            transaction_id: hex::decode(utxo["tx_hash"].as_str().unwrap()).unwrap()[..].into(),
+            index: utxo["tx_index"].as_u64().unwrap(),
+        }
+    }
+
+    fn input() -> TransactionInput {
+        TransactionInput {
+            transaction_id: hex::decode(TX_HASH).unwrap()[..].into(),
+            index: 0,
+        }
+    }
+
+    fn utxo() -> serde_json::Value {
+        json!([{
+            "tx_hash": TX_HASH,
+            "tx_index": 0,
+            "address": "addr1vqp4mmnx647vyutfwugav0yvxhl6pdkyg69x4xqzfl4vwwcdlws2f",
+            "value": "4500000",
+            "datum_hash": null,
+            "inline_datum": { "bytes": "d87980" },
+            "reference_script": null,
+            "asset_list": [{
+                "policy_id": "99b071ce8580d6a3a11b4902145adb8bfd0d2a03935af8cf66403e15",
+                "asset_name": "524245525259",
+                "quantity": "22182"
+            }]
+        }])
+    }
+
+    #[tokio::test]
+    async fn resolves_inputs() {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/utxo_info")
+            .match_body(Matcher::PartialJson(json!({
+                "_utxo_refs": [format!("{}#0", TX_HASH)],
+            })))
+            .with_body(utxo().to_string())
+            .create_async()
+            .await;
+
+        let resolved = koios(&server).get_utxos(vec![input()]).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].input, input());
+        let TransactionOutput::PostAlonzo(output) = &resolved[0].output else {
+            panic!("expected a post-alonzo output");
+        };
+        assert!(matches!(output.value, Value::Multiasset(4500000, _)));
+        assert!(matches!(output.datum_option, Some(DatumOption::Data(_))));
+    }
+
+    #[tokio::test]
+    async fn resolves_inputs_like_cardano_cli_describes_them() {
+        let mut server = Server::new_async().await;
+        let utxo_info = utxo_info();
+        server
+            .mock("POST", "/utxo_info")
+            .with_body(serde_json::Value::from(utxo_info.clone()).to_string())
+            .create_async()
+            .await;
+        let inputs: Vec<_> = utxo_info.iter().map(input_ref).collect();
+
+        let resolved = koios(&server).get_utxos(inputs.clone()).await.unwrap();
+
+        let utxo_file = UtxoFile::load(&test_data("sample.utxos.json"), Network::Mainnet).unwrap();
+        let expected = utxo_file.get_utxos(inputs).await.unwrap();
+        assert_eq!(resolved.len(), 6);
+        for (resolved, expected) in resolved.iter().zip(&expected) {
+            assert_eq!(resolved.input, expected.input);
+            assert_eq!(
+                minicbor::to_vec(&resolved.output).unwrap(),
+                minicbor::to_vec(&expected.output).unwrap(),
+                "{}#{}",
+                resolved.input.transaction_id,
+                resolved.input.index
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn splits_many_inputs_across_requests() {
+        let mut server = Server::new_async().await;
+        let template = utxo_info()
+            .into_iter()
+            .find(|utxo| utxo["tx_hash"] == TX_HASH)
+            .unwrap();
+        let inputs: Vec<_> = (0..UTXO_REFS_PER_REQUEST as u64 + 50)
+            .map(|index| TransactionInput { index, ..input() })
+            .collect();
+        let mut mocks = vec![];
+        for chunk in inputs.chunks(UTXO_REFS_PER_REQUEST) {
+            let refs: Vec<_> = chunk
+                .iter()
+                .map(|input| format!("{}#{}", TX_HASH, input.index))
+                .collect();
+            let utxos: Vec<_> = chunk
+                .iter()
+                .map(|input| {
+                    let mut utxo = template.clone();
+                    utxo["tx_index"] = input.index.into();
+                    utxo
+                })
+                .collect();
+            let mock = server
+                .mock("POST", "/utxo_info")
+                .match_body(Matcher::PartialJson(json!({ "_utxo_refs": refs })))
+                .with_body(serde_json::Value::from(utxos).to_string())
+                .expect(1)
+                .create_async()
+                .await;
+            mocks.push(mock);
+        }
+
+        let resolved = koios(&server).get_utxos(inputs.clone()).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        let resolved_inputs: Vec<_> = resolved.into_iter().map(|r| r.input).collect();
+        assert_eq!(resolved_inputs, inputs);
+    }
+
+    #[tokio::test]
+    async fn fetches_datums() {
+        let mut server = Server::new_async().await;
+        let datum_info = fs::read_to_string(test_data("koios/datum_info.json")).unwrap();
+        server
+            .mock("POST", "/datum_info")
+            .with_body(datum_info)
+            .create_async()
+            .await;
+        let datum_hash = Hash::<32>::from(
+            &hex::decode("923918e403bf43c34b4ef6b48eb2ee04babed17320d8d1b9ff9ad086e86f44ec")
+                .unwrap()[..],
+        );
+
+        let datum = koios(&server).get_datum(datum_hash).await.unwrap();
+
+        assert_eq!(
+            datum.map(|datum| datum.to_vec()),
+            Some(vec![0xd8, 0x79, 0x80])
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_missing_inputs() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/utxo_info")
+            .with_body("[]")
+            .create_async()
+            .await;
+
+        let error = koios(&server).get_utxos(vec![input()]).await.unwrap_err();
+
+        assert!(
+            matches!(error, ChainQueryError::NotFound { .. }),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_a_missing_endpoint() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("POST", "/utxo_info")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let error = koios(&server).get_utxos(vec![input()]).await.unwrap_err();
+
+        assert!(
+            matches!(error, ChainQueryError::MalformedResponse { .. }),
+            "{}",
+            error
+        );
+        assert!(
+            error.to_string().contains("endpoint not found"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limited_requests() {
+        let mut server = Server::new_async().await;
+        // mocks answer in the order they were made, until they've been hit as often as expected
+        let rate_limited = server
+            .mock("POST", "/utxo_info")
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .expect(2)
+            .create_async()
+            .await;
+        let answered = server
+            .mock("POST", "/utxo_info")
+            .with_body(utxo().to_string())
+            .create_async()
+            .await;
+
+        let resolved = koios(&server).get_utxos(vec![input()]).await.unwrap();
+
+        rate_limited.assert_async().await;
+        answered.assert_async().await;
+        assert_eq!(resolved.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_retrying() {
+        let mut server = Server::new_async().await;
+        let rate_limited = server
+            .mock("POST", "/utxo_info")
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .expect(MAX_RETRIES as usize + 1)
+            .create_async()
+            .await;
+
+        let error = koios(&server).get_utxos(vec![input()]).await.unwrap_err();
+
+        rate_limited.assert_async().await;
+        assert!(
+            matches!(error, ChainQueryError::RateLimited { .. }),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,637 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use chrono::DateTime;
+use futures::{StreamExt, TryStreamExt, stream};
+use pallas::{
+    codec::utils::{Bytes, CborWrap},
+    crypto::hash::Hasher,
+    ledger::{
+        addresses::Address,
+        primitives::conway::{
+            DatumOption, Language, PostAlonzoTransactionOutput, TransactionOutput,
+        },
+    },
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::json;
+use uplc::{
+    Fragment, Hash, PlutusData, TransactionInput,
+    machine::cost_model::ExBudget,
+    tx::{ResolvedInput, SlotConfig},
+};
+
+use super::{
+    ChainQuery, ChainQueryError, Network, QueryResult, ScriptLanguage, build_script_ref,
+    build_value, parse_response, send_request,
+};
+use crate::{
+    config::{KupoConfig, OgmiosConfig},
+    protocol_parameters::{ProtocolParameters, parse_cost_model},
+};
+
+const KUPO: &str = "kupo";
+const OGMIOS: &str = "ogmios";
+
+/// Resolves inputs through a Kupo indexer, and reads ledger state from an Ogmios server.
+pub struct Kupmios {
+    ogmios_url: String,
+    kupo_url: String,
+    /// What the cache files responses under, which is the network if we know it
+    cache_name: String,
+    max_concurrent_requests: usize,
+    client: reqwest::Client,
+}
+
+impl Kupmios {
+    pub fn new(
+        ogmios: &OgmiosConfig,
+        kupo: &KupoConfig,
+        network: Option<Network>,
+        max_concurrent_requests: usize,
+    ) -> Self {
+        let ogmios_url = ogmios.url.trim_end_matches('/').to_string();
+        let kupo_url = kupo.url.trim_end_matches('/').to_string();
+        // a self-hosted node can be on any network, so without one
+        // keep each pair of servers apart instead of sharing a cache dir
+        let cache_name = match network {
+            Some(network) => network.name().to_string(),
+            None => {
+                let servers = format!("{} {}", ogmios_url, kupo_url);
+                let hash = Hasher::<224>::hash(servers.as_bytes()).to_string();
+                format!("custom-{}", &hash[..16])
+            }
+        };
+        Kupmios {
+            ogmios_url,
+            kupo_url,
+            cache_name,
+            max_concurrent_requests,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn kupo<T: DeserializeOwned>(&self, path: &str) -> QueryResult<T> {
+        let request = self
+            .client
+            .get(format!("{}/{}", self.kupo_url, path))
+            .header("accept", "application/json");
+        let Some(body) = send_request(KUPO, request).await? else {
+            return Err(ChainQueryError::not_found(KUPO, path));
+        };
+        parse_response(KUPO, &body)
+    }
+
+    async fn ogmios<T: DeserializeOwned>(&self, method: &str) -> QueryResult<T> {
+        let request = self
+            .client
+            .post(&self.ogmios_url)
+            .header("content-type", "application/json")
+            .body(json!({ "jsonrpc": "2.0", "method": method }).to_string());
+        let Some(body) = send_request(OGMIOS, request).await? else {
+            return Err(ChainQueryError::not_found(OGMIOS, method));
+        };
+        let response: OgmiosResponse<T> = parse_response(OGMIOS, &body)?;
+        match (response.result, response.error) {
+            (Some(result), _) => Ok(result),
+            (None, Some(error)) => {
+                Err(anyhow!("ogmios {} failed: {}", method, error.message).into())
+            }
+            (None, None) => Err(ChainQueryError::malformed(
+                OGMIOS,
+                anyhow!("{} returned an empty response", method),
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OgmiosResponse<T> {
+    result: Option<T>,
+    error: Option<OgmiosError>,
+}
+
+#[derive(Deserialize)]
+struct OgmiosError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct EraSummary {
+    start: EraBound,
+    parameters: EraParameters,
+}
+
+#[derive(Deserialize)]
+struct EraBound {
+    time: Seconds,
+    slot: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EraParameters {
+    slot_length: Milliseconds,
+}
+
+#[derive(Deserialize)]
+struct Seconds {
+    seconds: u64,
+}
+
+#[derive(Deserialize)]
+struct Milliseconds {
+    milliseconds: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OgmiosProtocolParameters {
+    #[serde(default)]
+    plutus_cost_models: BTreeMap<String, serde_json::Value>,
+    max_execution_units_per_transaction: Option<OgmiosExUnits>,
+}
+
+#[derive(Deserialize)]
+struct OgmiosExUnits {
+    memory: i64,
+    cpu: i64,
+}
+
+#[derive(Deserialize)]
+struct KupoMatch {
+    output_index: u64,
+    address: String,
+    value: KupoValue,
+    datum_hash: Option<String>,
+    datum_type: Option<String>,
+    script_hash: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct KupoValue {
+    coins: u64,
+    #[serde(default)]
+    assets: BTreeMap<String, u64>,
+}
+
+#[derive(Deserialize)]
+struct KupoDatum {
+    datum: String,
+}
+
+#[derive(Deserialize)]
+struct KupoScript {
+    language: String,
+    script: String,
+}
+
+#[async_trait]
+impl ChainQuery for Kupmios {
+    fn network(&self) -> Option<&str> {
+        Some(&self.cache_name)
+    }
+
+    /// Ogmios only follows the chain and queries the ledger state, which doesn't keep
+    /// transactions, and Kupo only indexes their outputs, so neither can look one up by id
+    fn check_tx_lookup(&self, _tx_id: Hash<32>) -> QueryResult<()> {
+        Err(ChainQueryError::NoTxLookup("ogmios and kupo"))
+    }
+
+    async fn get_tx_bytes(&self, _tx_id: Hash<32>) -> QueryResult<Bytes> {
+        Err(ChainQueryError::NoTxLookup("ogmios and kupo"))
+    }
+
+    async fn get_utxos(&self, inputs: Vec<TransactionInput>) -> QueryResult<Vec<ResolvedInput>> {
+        // Kupo matches every output of a transaction at once,
+        // so inputs spending the same transaction share one request
+        let mut tx_ids = HashSet::new();
+        let tx_inputs: Vec<&TransactionInput> = inputs
+            .iter()
+            .filter(|input| tx_ids.insert(input.transaction_id))
+            .collect();
+        let txs: HashMap<Hash<32>, Vec<KupoMatch>> = stream::iter(tx_inputs)
+            .map(|input| async move {
+                let matches: Vec<KupoMatch> = self
+                    .kupo(&format!("matches/*@{}", input.transaction_id))
+                    .await
+                    .map_err(|e| e.for_input(input))?;
+                Ok::<_, ChainQueryError>((input.transaction_id, matches))
+            })
+            .buffer_unordered(self.max_concurrent_requests)
+            .try_collect()
+            .await?;
+
+        let mut utxos = vec![];
+        for input in &inputs {
+            let Some(utxo) = txs
+                .get(&input.transaction_id)
+                .into_iter()
+                .flatten()
+                .find(|utxo| utxo.output_index == input.index)
+            else {
+                return Err(ChainQueryError::not_found(
+                    KUPO,
+                    format!("utxo {}#{}", input.transaction_id, input.index),
+                ));
+            };
+            utxos.push(utxo);
+        }
+
+        // Inline datums and reference scripts are indexed separately,
+        // and are often shared between outputs, so only fetch each one once
+        let datum_hashes: HashSet<&String> = utxos
+            .iter()
+            .filter(|utxo| utxo.datum_type.as_deref() == Some("inline"))
+            .filter_map(|utxo| utxo.datum_hash.as_ref())
+            .collect();
+        let datums: HashMap<&String, Option<KupoDatum>> = stream::iter(datum_hashes)
+            .map(|hash| async move {
+                let datum = self.kupo(&format!("datums/{}", hash)).await?;
+                Ok::<_, ChainQueryError>((hash, datum))
+            })
+            .buffer_unordered(self.max_concurrent_requests)
+            .try_collect()
+            .await?;
+        let script_hashes: HashSet<&String> = utxos
+            .iter()
+            .filter_map(|utxo| utxo.script_hash.as_ref())
+            .collect();
+        let scripts: HashMap<&String, Option<KupoScript>> = stream::iter(script_hashes)
+            .map(|hash| async move {
+                let script = self.kupo(&format!("scripts/{}", hash)).await?;
+                Ok::<_, ChainQueryError>((hash, script))
+            })
+            .buffer_unordered(self.max_concurrent_requests)
+            .try_collect()
+            .await?;
+
+        inputs
+            .into_iter()
+            .zip(utxos)
+            .map(|(input, utxo)| {
+                let output = to_transaction_output(utxo, &datums, &scripts)
+                    .map_err(|e| ChainQueryError::malformed(KUPO, e).for_input(&input))?;
+                Ok(ResolvedInput { input, output })
+            })
+            .collect()
+    }
+
+    async fn get_datum(&self, datum_hash: Hash<32>) -> QueryResult<Option<Bytes>> {
+        let datum: Option<KupoDatum> = self.kupo(&format!("datums/{}", datum_hash)).await?;
+        datum
+            .map(|datum| match hex::decode(datum.datum) {
+                Ok(bytes) => Ok(bytes.into()),
+                Err(e) => Err(ChainQueryError::malformed(KUPO, e)),
+            })
+            .transpose()
+    }
+
+    async fn get_slot_config(&self) -> QueryResult<SlotConfig> {
+        let start_time: String = self.ogmios("queryNetwork/startTime").await?;
+        let era_summaries: Vec<EraSummary> = self.ogmios("queryLedgerState/eraSummaries").await?;
+        let slot_config = || -> Result<SlotConfig> {
+            let Some(current_era) = era_summaries.last() else {
+                bail!("no era summaries");
+            };
+            let start_time = DateTime::parse_from_rfc3339(&start_time)
+                .map_err(|e| anyhow!("invalid start time {}: {}", start_time, e))?;
+            // Slots only have a fixed length within an era, so anchor the config at the current one
+            let zero_time = u64::try_from(start_time.timestamp_millis())?
+                + current_era.start.time.seconds * 1000;
+            Ok(SlotConfig {
+                slot_length: current_era.parameters.slot_length.milliseconds.try_into()?,
+                zero_slot: current_era.start.slot,
+                zero_time,
+            })
+        };
+        slot_config().map_err(|e| ChainQueryError::malformed(OGMIOS, e))
+    }
+
+    async fn get_protocol_parameters(&self) -> QueryResult<ProtocolParameters> {
+        let parameters: OgmiosProtocolParameters =
+            self.ogmios("queryLedgerState/protocolParameters").await?;
+        let cost_model = |name: &str, language| match parameters.plutus_cost_models.get(name) {
+            Some(costs) => {
+                parse_cost_model(language, costs).map_err(|e| ChainQueryError::malformed(OGMIOS, e))
+            }
+            None => Ok(None),
+        };
+        Ok(ProtocolParameters {
+            plutus_v1_cost_model: cost_model("plutus:v1", Language::PlutusV1)?,
+            plutus_v2_cost_model: cost_model("plutus:v2", Language::PlutusV2)?,
+            plutus_v3_cost_model: cost_model("plutus:v3", Language::PlutusV3)?,
+            max_tx_ex_units: parameters
+                .max_execution_units_per_transaction
+                .map(|units| ExBudget {
+                    mem: units.memory,
+                    cpu: units.cpu,
+                }),
+        })
+    }
+}
+
+fn to_transaction_output(
+    utxo: &KupoMatch,
+    datums: &HashMap<&String, Option<KupoDatum>>,
+    scripts: &HashMap<&String, Option<KupoScript>>,
+) -> Result<TransactionOutput> {
+    let datum_option = match (utxo.datum_type.as_deref(), &utxo.datum_hash) {
+        (Some("inline"), Some(hash)) => {
+            let Some(Some(datum)) = datums.get(hash) else {
+                return Err(
+                    ChainQueryError::not_found(KUPO, format!("inline datum {}", hash)).into(),
+                );
+            };
+            let bytes = hex::decode(&datum.datum)?;
+            let data = PlutusData::decode_fragment(&bytes)
+                .map_err(|e| anyhow!("could not decode inline datum: {}", e))?;
+            Some(DatumOption::Data(CborWrap(data)))
+        }
+        (_, Some(hash)) => Some(DatumOption::Hash(hex::decode(hash)?[..].into())),
+        (_, None) => None,
+    };
+
+    let mut assets = vec![];
+    for (unit, amount) in &utxo.value.assets {
+        let (policy, asset_name) = unit.split_once('.').unwrap_or((unit, ""));
+        let policy: Hash<28> = hex::decode(policy)?[..].into();
+        let asset_name: Bytes = hex::decode(asset_name)?.into();
+        assets.push((policy, asset_name, *amount));
+    }
+    let value = build_value(utxo.value.coins, assets)?;
+
+    let script_ref = match &utxo.script_hash {
+        Some(hash) => {
+            let Some(Some(script)) = scripts.get(hash) else {
+                return Err(
+                    ChainQueryError::not_found(KUPO, format!("reference script {}", hash)).into(),
+                );
+            };
+            let language = match script.language.as_str() {
+                "native" => ScriptLanguage::Native,
+                "plutus:v1" => ScriptLanguage::PlutusV1,
+                "plutus:v2" => ScriptLanguage::PlutusV2,
+                "plutus:v3" => ScriptLanguage::PlutusV3,
+                other => {
+                    return Err(ChainQueryError::UnsupportedScript(other.to_string()).into());
+                }
+            };
+            Some(CborWrap(build_script_ref(
+                language,
+                hex::decode(&script.script)?,
+            )?))
+        }
+        None => None,
+    };
+
+    Ok(TransactionOutput::PostAlonzo(PostAlonzoTransactionOutput {
+        address: Address::from_bech32(&utxo.address)?.to_vec().into(),
+        datum_option,
+        script_ref,
+        value,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use mockito::{Matcher, Server};
+
+    use super::*;
+    use crate::chain_query::UtxoFile;
+
+    fn kupmios(server: &Server) -> Kupmios {
+        let ogmios = OgmiosConfig { url: server.url() };
+        let kupo = KupoConfig { url: server.url() };
+        Kupmios::new(&ogmios, &kupo, Some(Network::Mainnet), 2)
+    }
+
+    fn test_data(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../test_data")
+            .join(name)
+    }
+
+    fn fixture(name: &str) -> serde_json::Value {
+        let json = fs::read_to_string(test_data(name)).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    /// Serves Kupo's matches for the inputs of `sample.tx`,
+    /// along with the inline datum and reference script they hold
+    async fn serve_kupo(server: &mut Server) -> Vec<TransactionInput> {
+        let serde_json::Value::Array(matches) = fixture("kupo/matches.json") else {
+            panic!("expected a list of matches");
+        };
+        let mut inputs = vec![];
+        for utxo in matches {
+            let tx_id = utxo["transaction_id"].as_str().unwrap();
+            server
+                .mock("GET", format!("/matches/*@{}", tx_id).as_str())
+                .with_body(json!([utxo]).to_string())
+                .create_async()
+                .await;
+            inputs.push(TransactionInput {
+                transaction_id: hex::decode(tx_id).unwrap()[..].into(),
+                index: utxo["output_index"].as_u64().unwrap(),
+            });
+        }
+        server
+            .mock("GET", Matcher::Regex("^/datums/".to_string()))
+            .with_body(fixture("kupo/datum.json").to_string())
+            .create_async()
+            .await;
+        server
+            .mock("GET", Matcher::Regex("^/scripts/".to_string()))
+            .with_body(fixture("kupo/script.json").to_string())
+            .create_async()
+            .await;
+        inputs
+    }
+
+    #[tokio::test]
+    async fn resolves_inputs_like_cardano_cli_describes_them() {
+        let mut server = Server::new_async().await;
+        let inputs = serve_kupo(&mut server).await;
+
+        let resolved = kupmios(&server).get_utxos(inputs.clone()).await.unwrap();
+
+        let utxo_file = UtxoFile::load(&test_data("sample.utxos.json"), Network::Mainnet).unwrap();
+        let expected = utxo_file.get_utxos(inputs).await.unwrap();
+        assert_eq!(resolved.len(), 6);
+        for (resolved, expected) in resolved.iter().zip(&expected) {
+            assert_eq!(resolved.input, expected.input);
+            assert_eq!(
+                minicbor::to_vec(&resolved.output).unwrap(),
+                minicbor::to_vec(&expected.output).unwrap(),
+                "{}#{}",
+                resolved.input.transaction_id,
+                resolved.input.index
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fetches_datums() {
+        let mut server = Server::new_async().await;
+        let datum_hash = "923918e403bf43c34b4ef6b48eb2ee04babed17320d8d1b9ff9ad086e86f44ec";
+        server
+            .mock("GET", format!("/datums/{}", datum_hash).as_str())
+            .with_body(fixture("kupo/datum.json").to_string())
+            .create_async()
+            .await;
+        server
+            .mock("GET", Matcher::Regex("^/datums/".to_string()))
+            .with_body("null")
+            .create_async()
+            .await;
+        let kupmios = kupmios(&server);
+
+        let datum_hash = Hash::<32>::from(&hex::decode(datum_hash).unwrap()[..]);
+        let datum = kupmios.get_datum(datum_hash).await.unwrap();
+        assert_eq!(
+            datum.map(|datum| datum.to_vec()),
+            Some(vec![0xd8, 0x79, 0x80])
+        );
+
+        let unknown = kupmios.get_datum(Hash::new([0; 32])).await.unwrap();
+        assert_eq!(unknown, None);
+    }
+
+    #[tokio::test]
+    async fn reports_missing_inputs() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", Matcher::Regex("^/matches/".to_string()))
+            .with_body("[]")
+            .create_async()
+            .await;
+        let input = TransactionInput {
+            transaction_id: Hash::new([0; 32]),
+            index: 0,
+        };
+
+        let error = kupmios(&server).get_utxos(vec![input]).await.unwrap_err();
+
+        assert!(
+            matches!(error, ChainQueryError::NotFound { .. }),
+            "{}",
+            error
+        );
+    }
+
+    async fn serve_ogmios(server: &mut Server, method: &str, response: serde_json::Value) {
+        server
+            .mock("POST", "/")
+            .match_body(Matcher::PartialJson(json!({ "method": method })))
+            .with_body(response.to_string())
+            .create_async()
+            .await;
+    }
+
+    #[tokio::test]
+    async fn anchors_the_slot_config_at_the_current_era() {
+        let mut server = Server::new_async().await;
+        serve_ogmios(
+            &mut server,
+            "queryNetwork/startTime",
+            fixture("ogmios/startTime.json"),
+        )
+        .await;
+        serve_ogmios(
+            &mut server,
+            "queryLedgerState/eraSummaries",
+            fixture("ogmios/eraSummaries.json"),
+        )
+        .await;
+
+        let slot_config = kupmios(&server).get_slot_config().await.unwrap();
+
+        // Conway started at slot 133660800, 219024000 seconds after the system start
+        assert_eq!(slot_config.zero_slot, 133660800);
+        assert_eq!(slot_config.zero_time, 1506203091000 + 219024000 * 1000);
+        assert_eq!(slot_config.slot_length, 1000);
+        // which lines up with the mainnet config, anchored at slot 0
+        let mainnet = Network::Mainnet.slot_config();
+        assert_eq!(
+            slot_config.zero_time - slot_config.zero_slot * 1000,
+            mainnet.zero_time
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_start_times() {
+        let mut server = Server::new_async().await;
+        let mut start_time = fixture("ogmios/startTime.json");
+        start_time["result"] = json!("23/09/2017 21:44:51");
+        serve_ogmios(&mut server, "queryNetwork/startTime", start_time).await;
+        serve_ogmios(
+            &mut server,
+            "queryLedgerState/eraSummaries",
+            fixture("ogmios/eraSummaries.json"),
+        )
+        .await;
+
+        let error = kupmios(&server).get_slot_config().await.unwrap_err();
+
+        assert!(
+            matches!(error, ChainQueryError::MalformedResponse { .. }),
+            "{}",
+            error
+        );
+        assert!(
+            error.to_string().contains("invalid start time"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_protocol_parameters() {
+        let mut server = Server::new_async().await;
+        let response = json!({
+            "jsonrpc": "2.0",
+            "method": "queryLedgerState/protocolParameters",
+            "result": {
+                "plutusCostModels": { "plutus:v1": [205665, 812, 1], "plutus:v3": [100788, 420] },
+                "maxExecutionUnitsPerTransaction": { "memory": 14000000, "cpu": 10000000000u64 },
+            },
+        });
+        serve_ogmios(&mut server, "queryLedgerState/protocolParameters", response).await;
+
+        let parameters = kupmios(&server).get_protocol_parameters().await.unwrap();
+
+        assert_eq!(parameters.plutus_v1_cost_model, Some(vec![205665, 812, 1]));
+        assert_eq!(parameters.plutus_v2_cost_model, None);
+        assert_eq!(parameters.plutus_v3_cost_model, Some(vec![100788, 420]));
+        let max = parameters.max_tx_budget();
+        assert_eq!((max.mem, max.cpu), (14000000, 10000000000));
+    }
+
+    #[tokio::test]
+    async fn reports_ogmios_errors() {
+        let mut server = Server::new_async().await;
+        let response = json!({
+            "jsonrpc": "2.0",
+            "method": "queryLedgerState/protocolParameters",
+            "error": { "code": 2001, "message": "the node is still syncing" },
+        });
+        serve_ogmios(&mut server, "queryLedgerState/protocolParameters", response).await;
+
+        let error = kupmios(&server)
+            .get_protocol_parameters()
+            .await
+            .unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .contains("ogmios queryLedgerState/protocolParameters failed"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use pallas::{
+    codec::utils::Bytes, crypto::hash::Hasher, ledger::primitives::conway::TransactionOutput,
+};
+use uplc::{
+    Hash, TransactionInput,
+    tx::{ResolvedInput, SlotConfig},
+};
+
+use super::{ChainQuery, ChainQueryError, Network, QueryResult};
+use crate::protocol_parameters::ProtocolParameters;
+
+const PROVIDER: &str = "the mock provider";
+
+/// Serves chain data from memory, so tests (or tools embedding gastronomy)
+/// can load transactions without talking to a real provider.
+///
+/// ```ignore
+/// let query = MockChainQuery::new()
+///     .with_utxo(input, output)
+///     .with_datum(datum_bytes);
+/// ```
+#[derive(Default)]
+pub struct MockChainQuery {
+    network: Option<String>,
+    txs: HashMap<Hash<32>, Bytes>,
+    utxos: HashMap<(Hash<32>, u64), TransactionOutput>,
+    datums: HashMap<Hash<32>, Bytes>,
+    slot_config: Option<SlotConfig>,
+    protocol_parameters: ProtocolParameters,
+}
+
+impl MockChainQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports a network name, so the mock can sit behind a `Cache`
+    pub fn with_network(mut self, network: impl Into<String>) -> Self {
+        self.network = Some(network.into());
+        self
+    }
+
+    pub fn with_tx(mut self, tx_id: Hash<32>, tx_bytes: impl Into<Bytes>) -> Self {
+        self.txs.insert(tx_id, tx_bytes.into());
+        self
+    }
+
+    pub fn with_utxo(mut self, input: TransactionInput, output: TransactionOutput) -> Self {
+        self.utxos
+            .insert((input.transaction_id, input.index), output);
+        self
+    }
+
+    /// Adds a datum, keyed by the hash of its CBOR encoding
+    pub fn with_datum(mut self, datum: impl Into<Bytes>) -> Self {
+        let datum = datum.into();
+        self.datums.insert(Hasher::<256>::hash(&datum), datum);
+        self
+    }
+
+    /// Defaults to mainnet's slot config
+    pub fn with_slot_config(mut self, slot_config: SlotConfig) -> Self {
+        self.slot_config = Some(slot_config);
+        self
+    }
+
+    /// Defaults to the machine's own cost models and budget
+    pub fn with_protocol_parameters(mut self, protocol_parameters: ProtocolParameters) -> Self {
+        self.protocol_parameters = protocol_parameters;
+        self
+    }
+}
+
+#[async_trait]
+impl ChainQuery for MockChainQuery {
+    fn network(&self) -> Option<&str> {
+        self.network.as_deref()
+    }
+
+    async fn get_tx_bytes(&self, tx_id: Hash<32>) -> QueryResult<Bytes> {
+        self.txs
+            .get(&tx_id)
+            .cloned()
+            .ok_or_else(|| ChainQueryError::not_found(PROVIDER, format!("transaction {}", tx_id)))
+    }
+
+    async fn get_utxos(&self, inputs: Vec<TransactionInput>) -> QueryResult<Vec<ResolvedInput>> {
+        inputs
+            .into_iter()
+            .map(|input| {
+                let Some(output) = self.utxos.get(&(input.transaction_id, input.index)) else {
+                    return Err(ChainQueryError::not_found(
+                        PROVIDER,
+                        format!("utxo {}#{}", input.transaction_id, input.index),
+                    ));
+                };
+                Ok(ResolvedInput {
+                    output: output.clone(),
+                    input,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_datum(&self, datum_hash: Hash<32>) -> QueryResult<Option<Bytes>> {
+        Ok(self.datums.get(&datum_hash).cloned())
+    }
+
+    async fn get_slot_config(&self) -> QueryResult<SlotConfig> {
+        Ok(self
+            .slot_config
+            .clone()
+            .unwrap_or_else(|| Network::Mainnet.slot_config()))
+    }
+
+    async fn get_protocol_parameters(&self) -> QueryResult<ProtocolParameters> {
+        Ok(self.protocol_parameters.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anyhow::Result;
+    use minicbor::{Encoder, data::Tag};
+    use pallas::{
+        codec::utils::CborWrap,
+        ledger::primitives::conway::{DatumOption, PlutusData, PostAlonzoTransactionOutput, Value},
+    };
+    use uplc::ast::{DeBruijn, Program};
+
+    use super::*;
+    use crate::uplc::{self as gastronomy_uplc, Language, LoadOptions};
+
+    /// A plutus V3 script which accepts anything
+    fn always_succeeds() -> Result<Vec<u8>> {
+        let program = uplc::parser::program("(program 1.1.0 (lam context (con unit ())))")?;
+        let program: Program<DeBruijn> = program.try_into()?;
+        Ok(program.to_cbor()?)
+    }
+
+    /// An output locked by the script, with an inline datum
+    fn script_output(script_hash: Hash<28>) -> Result<TransactionOutput> {
+        // the unit constructor, 121([])
+        let datum: PlutusData = minicbor::decode(&[0xd8, 0x79, 0x80])?;
+        Ok(TransactionOutput::PostAlonzo(PostAlonzoTransactionOutput {
+            // an enterprise address on mainnet, paying to a script
+            address: [&[0x71][..], &script_hash[..]].concat().into(),
+            value: Value::Coin(2_000_000),
+            datum_option: Some(DatumOption::Data(CborWrap(datum))),
+            script_ref: None,
+        }))
+    }
+
+    /// A transaction which spends the input with the script, carried in its witness set
+    fn spending_tx(input: &TransactionInput, script: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = Encoder::new(vec![]);
+        encoder.array(4)?;
+        encoder.map(3)?;
+        encoder.u8(0)?.array(1)?.encode(input)?;
+        encoder.u8(1)?.array(0)?;
+        encoder.u8(2)?.u64(200_000)?;
+        encoder.map(2)?;
+        encoder.u8(5)?.array(1)?.array(4)?.u8(0)?.u8(0)?;
+        encoder.tag(Tag::new(121))?.array(0)?;
+        encoder.array(2)?.u64(1_000_000)?.u64(500_000_000)?;
+        encoder.u8(7)?.array(1)?.bytes(script)?;
+        encoder.bool(true)?.null()?;
+        Ok(encoder.into_writer())
+    }
+
+    #[tokio::test]
+    async fn loads_a_transaction_through_the_mock() -> Result<()> {
+        let script = always_succeeds()?;
+        let script_hash = Hasher::<224>::hash_tagged(&script, 3);
+        let input = TransactionInput {
+            transaction_id: Hash::new([1; 32]),
+            index: 0,
+        };
+        let tx_bytes = spending_tx(&input, &script)?;
+        let tx_id = Hash::new([2; 32]);
+        let query = MockChainQuery::new()
+            .with_tx(tx_id, tx_bytes)
+            .with_utxo(input.clone(), script_output(script_hash)?);
+
+        let programs = gastronomy_uplc::load_programs_from_file(
+            Path::new(&tx_id.to_string()),
+            &query,
+            LoadOptions::default(),
+        )
+        .await?;
+
+        assert_eq!(programs.len(), 1);
+        let program = programs.into_iter().next().unwrap();
+        assert_eq!(program.language, Language::PlutusV3);
+        let redeemer = program.redeemer.as_ref().unwrap();
+        assert_eq!(redeemer.purpose, "spend");
+        assert_eq!(
+            redeemer.target.as_deref(),
+            Some(format!("{}#0", input.transaction_id).as_str())
+        );
+        assert_eq!(redeemer.script_hash, Some(script_hash.to_string()));
+        assert_eq!(redeemer.declared.steps, 500_000_000);
+
+        let (_, outcome) = gastronomy_uplc::execute_program(
+            program.program,
+            program.language,
+            program.is_validator,
+            program.cost_model,
+            program.budget,
+        )?;
+        assert!(outcome.succeeded());
+        assert_eq!(outcome.result.as_deref(), Some("(con unit ())"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_inputs_the_mock_doesnt_have() -> Result<()> {
+        let script = always_succeeds()?;
+        let input = TransactionInput {
+            transaction_id: Hash::new([1; 32]),
+            index: 0,
+        };
+        let tx_id = Hash::new([2; 32]);
+        let query = MockChainQuery::new().with_tx(tx_id, spending_tx(&input, &script)?);
+
+        let Err(error) = gastronomy_uplc::load_programs_from_file(
+            Path::new(&tx_id.to_string()),
+            &query,
+            LoadOptions::default(),
+        )
+        .await
+        else {
+            panic!("expected the missing input to be an error");
+        };
+
+        let error = error.downcast::<ChainQueryError>()?;
+        assert!(
+            matches!(error, ChainQueryError::NotFound { .. }),
+            "{}",
+            error
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use minicbor::bytes::ByteVec;
+use pallas::{
+    codec::utils::{Bytes, CborWrap, KeyValuePairs},
+    ledger::{
+        addresses::Address,
+        primitives::conway::{DatumOption, PostAlonzoTransactionOutput, TransactionOutput},
+    },
+};
+use serde::Deserialize;
+use uplc::{
+    Fragment, Hash, PlutusData, TransactionInput,
+    tx::{ResolvedInput, SlotConfig},
+};
+
+use super::{
+    ChainQuery, ChainQueryError, Network, QueryResult, ScriptLanguage, build_script_ref,
+    build_value,
+};
+use crate::{plutus_data, protocol_parameters::ProtocolParameters};
+
+const PROVIDER: &str = "the utxo file";
+
+/// Serves resolved inputs from a local file, so transactions can be debugged offline.
+///
+/// The file holds either CBOR (raw or hex-encoded) `[input, output]` pairs, an input to output
+/// map, or the JSON written by `cardano-cli query utxo --out-file`.
+///
+/// Only the inputs come from the file, so the rest is filled in like this:
+/// - slots are turned into times with the network's slot config, so the network must be given
+/// - protocol parameters are the machine's defaults, unless given with `--protocol-params`
+/// - datums are never looked up, so ones which aren't in the transaction or inline in
+///   its inputs must be given with `--datums`
+pub struct UtxoFile {
+    utxos: HashMap<(Hash<32>, u64), TransactionOutput>,
+    network: Network,
+}
+
+impl UtxoFile {
+    pub fn load(path: &Path, network: Network) -> Result<Self> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("could not read utxo file {}", path.display()))?;
+        let utxos = parse_utxos(&bytes)
+            .with_context(|| format!("could not parse utxo file {}", path.display()))?;
+        Ok(UtxoFile {
+            utxos: utxos
+                .into_iter()
+                .map(|(input, output)| ((input.transaction_id, input.index), output))
+                .collect(),
+            network,
+        })
+    }
+}
+
+#[async_trait]
+impl ChainQuery for UtxoFile {
+    fn check_tx_lookup(&self, _tx_id: Hash<32>) -> QueryResult<()> {
+        Err(ChainQueryError::NoTxLookup("a utxo file"))
+    }
+
+    async fn get_tx_bytes(&self, _tx_id: Hash<32>) -> QueryResult<Bytes> {
+        Err(ChainQueryError::NoTxLookup("a utxo file"))
+    }
+
+    async fn get_utxos(&self, inputs: Vec<TransactionInput>) -> QueryResult<Vec<ResolvedInput>> {
+        inputs
+            .into_iter()
+            .map(|input| {
+                let Some(output) = self.utxos.get(&(input.transaction_id, input.index)) else {
+                    return Err(ChainQueryError::not_found(
+                        PROVIDER,
+                        format!("utxo {}#{}", input.transaction_id, input.index),
+                    ));
+                };
+                Ok(ResolvedInput {
+                    output: output.clone(),
+                    input,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_datum(&self, _datum_hash: Hash<32>) -> QueryResult<Option<Bytes>> {
+        Ok(None)
+    }
+
+    async fn get_slot_config(&self) -> QueryResult<SlotConfig> {
+        Ok(self.network.slot_config())
+    }
+
+    async fn get_protocol_parameters(&self) -> QueryResult<ProtocolParameters> {
+        // nothing to read them from, so fall back to the machine's defaults
+        Ok(ProtocolParameters::default())
+    }
+}
+
+fn parse_utxos(bytes: &[u8]) -> Result<Vec<(TransactionInput, TransactionOutput)>> {
+    let text = std::str::from_utf8(bytes).map(str::trim).ok();
+    if let Some(json) = text.filter(|t| t.starts_with('{')) {
+        let utxos: BTreeMap<String, CliUtxo> = serde_json::from_str(json)?;
+        return utxos
+            .into_iter()
+            .map(|(input, output)| {
+                let parsed = (parse_input(&input)?, output.try_into()?);
+                Ok(parsed)
+            })
+            .collect::<Result<Vec<_>>>();
+    }
+
+    let bytes = match text.map(hex::decode) {
+        Some(Ok(decoded)) => decoded,
+        _ => bytes.to_vec(),
+    };
+    if let Ok(pairs) = minicbor::decode::<Vec<(TransactionInput, TransactionOutput)>>(&bytes) {
+        return Ok(pairs);
+    }
+    let map: KeyValuePairs<TransactionInput, TransactionOutput> = minicbor::decode(&bytes)
+        .map_err(|e| {
+            anyhow!(
+                "expected a list of [input, output] pairs or a utxo map: {}",
+                e
+            )
+        })?;
+    Ok(map.to_vec())
+}
+
+fn parse_input(input: &str) -> Result<TransactionInput> {
+    let (tx_id, index) = input
+        .split_once('#')
+        .ok_or_else(|| anyhow!("invalid utxo reference {}, expected txhash#index", input))?;
+    Ok(TransactionInput {
+        transaction_id: hex::decode(tx_id)?[..].into(),
+        index: index.parse()?,
+    })
+}
+
+/// A single entry of `cardano-cli query utxo --out-file`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CliUtxo {
+    address: String,
+    value: BTreeMap<String, serde_json::Value>,
+    datumhash: Option<String>,
+    inline_datum: Option<serde_json::Value>,
+    inline_datum_raw: Option<String>,
+    reference_script: Option<CliReferenceScript>,
+}
+
+#[derive(Deserialize)]
+struct CliReferenceScript {
+    script: CliScript,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CliScript {
+    #[serde(rename = "type")]
+    script_type: String,
+    cbor_hex: String,
+}
+
+impl TryFrom<CliUtxo> for TransactionOutput {
+    type Error = anyhow::Error;
+
+    fn try_from(utxo: CliUtxo) -> Result<Self> {
+        let datum_option = match (utxo.inline_datum_raw, utxo.inline_datum, utxo.datumhash) {
+            (Some(raw), _, _) => {
+                let bytes = hex::decode(raw)?;
+                let data = PlutusData::decode_fragment(&bytes)
+                    .map_err(|e| anyhow!("could not decode inline datum: {}", e))?;
+                Some(DatumOption::Data(CborWrap(data)))
+            }
+            // re-encoded from JSON, so only use this when the original bytes aren't there
+            (None, Some(datum), _) => Some(DatumOption::Data(CborWrap(
+                plutus_data::from_json(&datum).context("could not read inline datum")?,
+            ))),
+            (None, None, Some(hash)) => Some(DatumOption::Hash(hex::decode(hash)?[..].into())),
+            (None, None, None) => None,
+        };
+
+        let mut coin = 0;
+        let mut assets = vec![];
+        for (unit, amount) in utxo.value {
+            if unit == "lovelace" {
+                coin = amount
+                    .as_u64()
+                    .ok_or_else(|| anyhow!("invalid lovelace amount {}", amount))?;
+                continue;
+            }
+            let policy: Hash<28> = hex::decode(&unit)?[..].into();
+            let Some(tokens) = amount.as_object() else {
+                bail!("invalid assets for policy {}", unit);
+            };
+            for (asset_name, quantity) in tokens {
+                let asset_name: Bytes = hex::decode(asset_name)?.into();
+                let quantity = quantity
+                    .as_u64()
+                    .ok_or_else(|| anyhow!("invalid quantity {} for policy {}", quantity, unit))?;
+                assets.push((policy, asset_name, quantity));
+            }
+        }
+        let value = build_value(coin, assets)?;
+
+        let script_ref = match utxo.reference_script {
+            Some(CliReferenceScript { script }) => {
+                let language = match script.script_type.as_str() {
+                    "SimpleScript" | "SimpleScriptV2" => ScriptLanguage::Native,
+                    "PlutusScriptV1" => ScriptLanguage::PlutusV1,
+                    "PlutusScriptV2" => ScriptLanguage::PlutusV2,
+                    "PlutusScriptV3" => ScriptLanguage::PlutusV3,
+                    other => {
+                        return Err(ChainQueryError::UnsupportedScript(other.to_string()).into());
+                    }
+                };
+                let envelope = hex::decode(&script.cbor_hex)?;
+                let bytes = match language {
+                    ScriptLanguage::Native => envelope,
+                    // The envelope wraps plutus scripts in one more CBOR bytestring
+                    _ => minicbor::decode::<ByteVec>(&envelope)?.to_vec(),
+                };
+                Some(CborWrap(build_script_ref(language, bytes)?))
+            }
+            None => None,
+        };
+
+        Ok(TransactionOutput::PostAlonzo(PostAlonzoTransactionOutput {
+            address: Address::from_bech32(&utxo.address)?.to_vec().into(),
+            datum_option,
+            script_ref,
+            value,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use pallas::ledger::primitives::conway::Value;
+
+    use super::*;
+    use crate::{config::Config, transaction};
+
+    fn test_data(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../test_data")
+            .join(name)
+    }
+
+    #[tokio::test]
+    async fn resolves_the_inputs_of_a_transaction() {
+        let tx_bytes = fs::read(test_data("sample.tx")).unwrap();
+        let tx = transaction::decode(&tx_bytes).unwrap();
+        let body = &tx.transaction_body;
+        let mut inputs: Vec<_> = body.inputs.iter().cloned().collect();
+        inputs.extend(
+            body.reference_inputs
+                .iter()
+                .flat_map(|inputs| inputs.to_vec()),
+        );
+
+        let utxos = UtxoFile::load(&test_data("sample.utxos.json"), Network::Mainnet).unwrap();
+        let resolved = utxos.get_utxos(inputs.clone()).await.unwrap();
+
+        assert_eq!(resolved.len(), 6);
+        for (input, resolved) in inputs.iter().zip(&resolved) {
+            assert_eq!(&resolved.input, input);
+        }
+        let TransactionOutput::PostAlonzo(wallet) = &resolved[0].output else {
+            panic!("expected a post-alonzo output");
+        };
+        assert_eq!(
+            Address::from_bytes(&wallet.address)
+                .unwrap()
+                .to_bech32()
+                .unwrap(),
+            "addr1vqp4mmnx647vyutfwugav0yvxhl6pdkyg69x4xqzfl4vwwcdlws2f"
+        );
+        assert!(matches!(wallet.value, Value::Coin(14612877400)));
+        assert!(wallet.datum_option.is_none());
+
+        let TransactionOutput::PostAlonzo(order) = &resolved[2].output else {
+            panic!("expected a post-alonzo output");
+        };
+        assert!(matches!(order.value, Value::Multiasset(4500000, _)));
+        assert!(matches!(order.datum_option, Some(DatumOption::Data(_))));
+
+        // the reference script is the one the transaction also carries in its witness set
+        let reference_script = resolved
+            .iter()
+            .find(|resolved| resolved.input.transaction_id == inputs[3].transaction_id)
+            .unwrap();
+        let TransactionOutput::PostAlonzo(reference_script) = &reference_script.output else {
+            panic!("expected a post-alonzo output");
+        };
+        assert!(reference_script.script_ref.is_some());
+    }
+
+    #[tokio::test]
+    async fn reports_inputs_missing_from_the_file() {
+        let utxos = UtxoFile::load(&test_data("sample.utxos.json"), Network::Mainnet).unwrap();
+        let input = TransactionInput {
+            transaction_id: Hash::new([0; 32]),
+            index: 0,
+        };
+        let error = utxos.get_utxos(vec![input]).await.unwrap_err();
+        assert!(
+            matches!(error, ChainQueryError::NotFound { .. }),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn needs_a_network() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "utxosFile": test_data("sample.utxos.json"),
+        }))
+        .unwrap();
+        let Err(error) = crate::chain_query::from_config(&config) else {
+            panic!("expected the missing network to be an error");
+        };
+        assert!(error.to_string().contains("needs a network"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn refuses_transaction_ids_up_front() {
+        let utxos = UtxoFile::load(&test_data("sample.utxos.json"), Network::Mainnet).unwrap();
+        let tx_id = Hash::<32>::new([0; 32]).to_string();
+        let Err(error) = crate::uplc::load_programs_from_file(
+            Path::new(&tx_id),
+            &utxos,
+            crate::uplc::LoadOptions::default(),
+        )
+        .await
+        else {
+            panic!("expected loading by id to be an error");
+        };
+        assert!(
+            matches!(
+                error.downcast_ref::<ChainQueryError>(),
+                Some(ChainQueryError::NoTxLookup(_))
+            ),
+            "{}",
+            error
+        );
+    }
+}
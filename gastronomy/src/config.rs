@@ -8,17 +8,66 @@ use serde::Deserialize;
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
+    pub network: Option<String>,
+    pub custom_network: Option<CustomNetworkConfig>,
     pub blockfrost: Option<BlockfrostConfig>,
+    pub koios: Option<KoiosConfig>,
+    pub ogmios: Option<OgmiosConfig>,
+    pub kupo: Option<KupoConfig>,
+    pub utxos_file: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    pub offline: Option<bool>,
+    /// How many requests to send a provider at once while resolving inputs
+    pub max_concurrent_requests: Option<usize>,
+    pub protocol_parameters_file: Option<PathBuf>,
+    /// The budget to start programs with: max, declared, or STEPS,MEM
+    pub budget: Option<String>,
     pub blueprint_file: Option<PathBuf>,
     pub script_overrides: Option<Vec<ScriptOverrideConfig>>,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomNetworkConfig {
+    /// The unix time (in milliseconds) of the network's first slot
+    pub zero_time: u64,
+    pub zero_slot: u64,
+    /// The length of a slot, in milliseconds
+    pub slot_length: u32,
+    /// The provider's API endpoint for this network,
+    /// e.g. http://localhost:8080/api/v1 for a yaci-devkit Blockfrost-compatible API
+    pub url: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BlockfrostConfig {
     pub key: String,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KoiosConfig {
+    /// Overrides the Koios endpoint, e.g. for a self-hosted instance
+    pub url: Option<String>,
+    /// Optional bearer token for higher rate limits
+    pub token: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OgmiosConfig {
+    /// The HTTP endpoint of an Ogmios server, e.g. http://localhost:1337
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KupoConfig {
+    /// The HTTP endpoint of a Kupo server, e.g. http://localhost:1442
+    pub url: String,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ScriptOverrideConfig {
@@ -1,19 +1,21 @@
 use std::{
-    collections::{BTreeMap, BTreeSet, HashMap},
-    fs,
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, iter,
     path::Path,
 };
 
 use anyhow::Result;
-use pallas::ledger::addresses::ScriptHash;
 use serde::Serialize;
 use uplc::{
     ast::NamedDeBruijn,
     machine::{Context, MachineState, indexed_term::IndexedTerm, value::Env},
-    tx::script_context::PlutusScript,
 };
 
-use crate::{chain_query::ChainQuery, uplc::LoadedProgram};
+use crate::{
+    chain_query::ChainQuery,
+    source_map::SourceLocation,
+    uplc::{LoadOptions, LoadedProgram},
+};
 
 pub type Value = String;
 
@@ -25,8 +27,10 @@ pub struct Frame {
     pub env: Vec<EnvVar>,
     pub term: Value,
     pub ret_value: Option<Value>,
-    pub location: Option<String>,
+    pub location: Option<SourceLocation>,
     pub budget: ExBudget,
+    /// What the program traced while stepping into this frame
+    pub logs: Vec<String>,
 }
 
 #[derive(Clone, Serialize)]
@@ -45,28 +49,128 @@ pub struct ExBudget {
     pub mem_diff: i64,
 }
 
+/// How running a program ended, and what it logged along the way
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionOutcome {
+    /// The term the program evaluated to, if it got that far
+    pub result: Option<Value>,
+    pub error: Option<ExecutionError>,
+    /// Sent separately, since programs can trace a lot
+    #[serde(skip)]
+    pub logs: Vec<TraceLog>,
+    pub starting_budget: ExUnits,
+    /// What the program spent, which is past its starting budget if it ran out
+    pub spent: ExUnits,
+}
+
+/// A message from the `trace` builtin, and the step which emitted it
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceLog {
+    pub step: usize,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExUnits {
+    pub steps: i64,
+    pub mem: i64,
+}
+
+impl ExUnits {
+    /// How much of this budget is left after spending `spent`, as percentages of it.
+    /// They're negative when it was overspent, and missing when there was no budget at all.
+    pub fn headroom(&self, spent: ExUnits) -> (Option<f64>, Option<f64>) {
+        let percent_left = |budget: i64, spent: i64| {
+            (budget != 0).then(|| (budget - spent) as f64 * 100.0 / budget as f64)
+        };
+        (
+            percent_left(self.steps, spent.steps),
+            percent_left(self.mem, spent.mem),
+        )
+    }
+}
+
+impl From<uplc::machine::cost_model::ExBudget> for ExUnits {
+    fn from(budget: uplc::machine::cost_model::ExBudget) -> Self {
+        Self {
+            steps: budget.cpu,
+            mem: budget.mem,
+        }
+    }
+}
+
+impl ExecutionOutcome {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionError {
+    pub kind: ExecutionErrorKind,
+    pub message: String,
+    /// The index of the last state the machine reached, which it couldn't step past
+    pub step: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExecutionErrorKind {
+    /// The program evaluated `error`, like a failed `expect` does
+    ExplicitError,
+    BudgetExceeded,
+    /// A builtin was given an argument of the wrong type
+    TypeMismatch,
+    /// A PlutusV3 validator finished, but returned something other than unit
+    NotUnit,
+    Other,
+}
+
+impl From<&uplc::machine::Error> for ExecutionErrorKind {
+    fn from(error: &uplc::machine::Error) -> Self {
+        use uplc::machine::Error;
+        match error {
+            Error::EvaluationFailure => Self::ExplicitError,
+            Error::OutOfExError(_) => Self::BudgetExceeded,
+            Error::TypeMismatch(..)
+            | Error::ListTypeMismatch(_)
+            | Error::PairTypeMismatch(_)
+            | Error::NotAConstant(_) => Self::TypeMismatch,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl fmt::Display for ExecutionErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ExplicitError => "Explicit error",
+            Self::BudgetExceeded => "Budget exceeded",
+            Self::TypeMismatch => "Type mismatch",
+            Self::NotUnit => "Didn't return unit",
+            Self::Other => "Machine error",
+        })
+    }
+}
+
 pub async fn load_file(
     filename: &Path,
     parameters: &[String],
-    query: ChainQuery,
-    script_overrides: HashMap<ScriptHash, PlutusScript>,
+    query: &dyn ChainQuery,
+    options: LoadOptions,
 ) -> Result<Vec<LoadedProgram>> {
-    println!("from file");
-    let raw_programs =
-        crate::uplc::load_programs_from_file(filename, query, script_overrides).await?;
+    let raw_programs = crate::uplc::load_programs_from_file(filename, query, options).await?;
     let mut programs = vec![];
 
-    println!("{} program(s)", raw_programs.len());
     for raw_program in raw_programs {
-        let arguments = parameters
-            .iter()
-            .enumerate()
-            .map(|(index, param)| crate::uplc::parse_parameter(index, param.clone()))
-            .collect::<Result<Vec<_>>>()?;
+        let arguments = crate::uplc::parse_parameters(&raw_program, parameters)?;
         let applied_program = crate::uplc::apply_parameters(raw_program, arguments)?;
         programs.push(applied_program);
     }
-    println!("Done");
     Ok(programs)
 }
 
@@ -76,20 +180,21 @@ pub struct RawFrame<'a> {
     pub env: Env,
     pub term: &'a IndexedTerm<NamedDeBruijn>,
     pub ret_value: Option<&'a uplc::machine::value::Value>,
-    pub location: Option<&'a String>,
+    pub location: Option<&'a SourceLocation>,
     pub budget: ExBudget,
+    pub logs: Vec<String>,
 }
 
-const MAX_CPU: i64 = 10000000000;
-const MAX_MEM: i64 = 14000000;
-
 pub fn parse_raw_frames<'a>(
     states: &'a [(MachineState, uplc::machine::cost_model::ExBudget)],
-    source_map: &'a BTreeMap<u64, String>,
+    source_map: &'a BTreeMap<u64, SourceLocation>,
+    initial_budget: uplc::machine::cost_model::ExBudget,
+    logs: &[TraceLog],
 ) -> Vec<RawFrame<'a>> {
     let mut frames = vec![];
     let mut prev_steps = 0;
     let mut prev_mem = 0;
+    let mut logs = logs.iter().peekable();
     for (state, budget) in states {
         let (label, context, env, term, location, ret_value) = match state {
             MachineState::Compute(context, env, term) => {
@@ -132,8 +237,13 @@ pub fn parse_raw_frames<'a>(
                 )
             }
         };
-        let steps = MAX_CPU - budget.cpu;
-        let mem = MAX_MEM - budget.mem;
+        let steps = initial_budget.cpu - budget.cpu;
+        let mem = initial_budget.mem - budget.mem;
+        // logs come in step order, and there's a frame for every step
+        let step = frames.len();
+        let frame_logs = iter::from_fn(|| logs.next_if(|log| log.step == step))
+            .map(|log| log.message.clone())
+            .collect();
         frames.push(RawFrame {
             label,
             context,
@@ -147,6 +257,7 @@ pub fn parse_raw_frames<'a>(
                 steps_diff: steps - prev_steps,
                 mem_diff: mem - prev_mem,
             },
+            logs: frame_logs,
         });
         prev_steps = steps;
         prev_mem = mem;
@@ -196,8 +307,7 @@ pub fn read_source_files(source_root: &Path, frames: &[RawFrame<'_>]) -> BTreeMa
     let filenames: BTreeSet<&str> = frames
         .iter()
         .filter_map(|f| f.location)
-        .filter_map(|loc| loc.split_once(":"))
-        .map(|(file, _)| file)
+        .map(|loc| loc.file.as_str())
         .collect();
 
     let mut roots = vec![source_root.join("validators"), source_root.join("lib")];
@@ -213,6 +323,9 @@ pub fn read_source_files(source_root: &Path, frames: &[RawFrame<'_>]) -> BTreeMa
         }
     }
 
+    // source maps from other toolchains are relative to the project root
+    roots.push(source_root.to_path_buf());
+
     let mut files = BTreeMap::new();
 
     for filename in filenames {
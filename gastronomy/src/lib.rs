@@ -1,6 +1,12 @@
+pub mod aiken;
+pub mod blueprint;
 pub mod chain_query;
 pub mod config;
 pub mod execution_trace;
+pub mod plutus_data;
+pub mod protocol_parameters;
+pub mod source_map;
+pub mod transaction;
 pub mod uplc;
 
 use std::{collections::HashMap, fs::File, io::BufReader, path::PathBuf, str::FromStr};
@@ -10,7 +16,7 @@ use anyhow::{Context, Result, anyhow};
 //REXPORTS
 use ::uplc::tx::script_context::PlutusScript;
 use aiken_project::blueprint::Blueprint;
-pub use execution_trace::Frame;
+pub use execution_trace::{ExecutionOutcome, Frame};
 pub use hex;
 pub use pallas::ledger::primitives::ScriptHash;
 
@@ -0,0 +1,548 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, anyhow, bail};
+use minicbor::{Encoder, data::Tag};
+use num_bigint::{BigInt, Sign};
+use serde_json::Value;
+use uplc::PlutusData;
+
+use crate::blueprint::ParameterSchema;
+
+/// Plutus data before it's encoded, built by the parsers below
+pub(crate) enum Data {
+    Int(BigInt),
+    Bytes(Vec<u8>),
+    List(Vec<Data>),
+    Map(Vec<(Data, Data)>),
+    Constr(u64, Vec<Data>),
+}
+
+/// Parses plutus data written in any of the formats we accept on the command line:
+/// - hex-encoded CBOR
+/// - the detailed-schema JSON used by cardano-cli, e.g. `{"constructor": 0, "fields": []}`
+/// - CBOR diagnostic notation, e.g. `121([42, h'cafe'])`
+/// - `@path/to/file`, holding any of the above (or raw CBOR)
+pub fn parse(input: &str) -> Result<PlutusData> {
+    let input = input.trim();
+    if let Some(path) = input.strip_prefix('@') {
+        return parse_file(Path::new(path));
+    }
+    // plain integers are valid hex too, but mean the number they look like
+    if is_integer(input) {
+        return DiagnosticParser::new(input).parse()?.to_plutus_data();
+    }
+    if let Ok(bytes) = hex::decode(input)
+        && let Ok(data) = decode(&bytes)
+    {
+        return Ok(data);
+    }
+    if input.starts_with('{')
+        && let Ok(json) = serde_json::from_str::<Value>(input)
+        && is_detailed_schema(&json)
+    {
+        return from_json(&json);
+    }
+    DiagnosticParser::new(input).parse()?.to_plutus_data()
+}
+
+/// Parses a value a blueprint describes. On top of the formats [`parse`] accepts,
+/// it can be JSON laid out like the schema, e.g. `{"owner": "cafe", "amount": 42}`
+pub fn parse_with_schema(input: &str, schema: &ParameterSchema) -> Result<PlutusData> {
+    let input = input.trim();
+    let text = match input.strip_prefix('@') {
+        Some(path) => {
+            fs::read_to_string(path).with_context(|| format!("could not read {}", path))?
+        }
+        None => input.to_string(),
+    };
+    let Ok(json) = serde_json::from_str::<Value>(&text) else {
+        return parse(input);
+    };
+    // numbers are valid hex too, so fall back to the other formats before giving up
+    schema
+        .encode(&json)
+        .or_else(|error| parse(input).map_err(|_| error))
+}
+
+fn parse_file(path: &Path) -> Result<PlutusData> {
+    let bytes = fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+    let result = match std::str::from_utf8(&bytes) {
+        Ok(text) if !text.trim_start().starts_with('@') => parse(text),
+        Ok(_) => Err(anyhow!("files can't refer to other files")),
+        Err(_) => decode(&bytes),
+    };
+    result.with_context(|| format!("in {}", path.display()))
+}
+
+/// Decodes CBOR plutus data, which has to use up every byte
+fn decode(bytes: &[u8]) -> Result<PlutusData> {
+    let mut decoder = minicbor::Decoder::new(bytes);
+    let data = decoder
+        .decode()
+        .map_err(|e| anyhow!("could not decode plutus data: {}", e))?;
+    if decoder.position() != bytes.len() {
+        bail!(
+            "could not decode plutus data: {} trailing bytes",
+            bytes.len() - decoder.position()
+        );
+    }
+    Ok(data)
+}
+
+fn is_integer(input: &str) -> bool {
+    let digits = input.strip_prefix('-').unwrap_or(input);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_detailed_schema(json: &Value) -> bool {
+    ["constructor", "int", "bytes", "list", "map"]
+        .iter()
+        .any(|key| json.get(key).is_some())
+}
+
+/// Converts the detailed-schema JSON written by cardano-cli into plutus data
+pub fn from_json(json: &Value) -> Result<PlutusData> {
+    json_to_data(json, "")?.to_plutus_data()
+}
+
+pub(crate) fn json_to_data(json: &Value, path: &str) -> Result<Data> {
+    let at = if path.is_empty() {
+        String::new()
+    } else {
+        format!(" at {}", path)
+    };
+    let Some(object) = json.as_object() else {
+        bail!("expected an object{}, found {}", at, json);
+    };
+    if let Some(index) = object.get("constructor") {
+        let Some(index) = index.as_u64() else {
+            bail!("invalid constructor index{}: {}", at, index);
+        };
+        let Some(fields) = object.get("fields").and_then(Value::as_array) else {
+            bail!("constructor{} is missing its fields", at);
+        };
+        let fields = fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| json_to_data(field, &format!("{}.fields[{}]", path, i)))
+            .collect::<Result<_>>()?;
+        return Ok(Data::Constr(index, fields));
+    }
+    if let Some(int) = object.get("int") {
+        // large integers don't fit in a JSON number, so allow them as strings too
+        let int = match int {
+            Value::Number(number) => number.to_string(),
+            Value::String(string) => string.clone(),
+            other => bail!("invalid int{}: {}", at, other),
+        };
+        let int = int
+            .parse()
+            .map_err(|_| anyhow!("invalid int{}: {}", at, int))?;
+        return Ok(Data::Int(int));
+    }
+    if let Some(bytes) = object.get("bytes") {
+        let bytes = bytes
+            .as_str()
+            .and_then(|bytes| hex::decode(bytes).ok())
+            .ok_or_else(|| anyhow!("invalid bytes{}, expected a hex string", at))?;
+        return Ok(Data::Bytes(bytes));
+    }
+    if let Some(list) = object.get("list") {
+        let Some(list) = list.as_array() else {
+            bail!("invalid list{}, expected an array", at);
+        };
+        let items = list
+            .iter()
+            .enumerate()
+            .map(|(i, item)| json_to_data(item, &format!("{}.list[{}]", path, i)))
+            .collect::<Result<_>>()?;
+        return Ok(Data::List(items));
+    }
+    if let Some(map) = object.get("map") {
+        let Some(map) = map.as_array() else {
+            bail!(
+                "invalid map{}, expected an array of {{\"k\", \"v\"}} pairs",
+                at
+            );
+        };
+        let entries = map
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let (Some(key), Some(value)) = (entry.get("k"), entry.get("v")) else {
+                    bail!(
+                        "map entry at {}.map[{}] needs both \"k\" and \"v\"",
+                        path,
+                        i
+                    );
+                };
+                Ok((
+                    json_to_data(key, &format!("{}.map[{}].k", path, i))?,
+                    json_to_data(value, &format!("{}.map[{}].v", path, i))?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        return Ok(Data::Map(entries));
+    }
+    bail!(
+        "expected one of constructor, int, bytes, list or map{}, found {}",
+        at,
+        json
+    )
+}
+
+/// A parser for the subset of CBOR diagnostic notation (RFC 8949, section 8)
+/// which can describe plutus data: integers, `h'..'` bytes, arrays, maps and tags.
+struct DiagnosticParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> DiagnosticParser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, position: 0 }
+    }
+
+    fn parse(mut self) -> Result<Data> {
+        let data = self.item()?;
+        self.skip_whitespace();
+        if self.position < self.text.len() {
+            return Err(self.error("unexpected trailing characters"));
+        }
+        Ok(data)
+    }
+
+    fn item(&mut self) -> Result<Data> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('[') => {
+                self.position += 1;
+                Ok(Data::List(self.sequence(']', Self::item)?))
+            }
+            Some('{') => {
+                self.position += 1;
+                Ok(Data::Map(self.sequence('}', Self::entry)?))
+            }
+            Some('h') => self.bytes(),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let number = self.number()?;
+                self.skip_whitespace();
+                if self.peek() == Some('(') {
+                    self.tagged(number)
+                } else {
+                    Ok(Data::Int(number))
+                }
+            }
+            Some(c) => Err(self.error(&format!("unexpected character '{}'", c))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn entry(&mut self) -> Result<(Data, Data)> {
+        let key = self.item()?;
+        self.skip_whitespace();
+        self.expect(':')?;
+        let value = self.item()?;
+        Ok((key, value))
+    }
+
+    /// Parses comma-separated items up to a closing bracket.
+    /// Indefinite-length markers (`[_ ...]`) are accepted, but the encoding is our own choice.
+    fn sequence<T>(&mut self, close: char, item: fn(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('_') {
+            self.position += 1;
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(close) {
+                self.position += 1;
+                return Ok(items);
+            }
+            if !items.is_empty() {
+                self.expect(',')?;
+            }
+            items.push(item(self)?);
+        }
+    }
+
+    fn bytes(&mut self) -> Result<Data> {
+        let start = self.position;
+        self.expect('h')?;
+        self.expect('\'')?;
+        let Some(length) = self.text[self.position..].find('\'') else {
+            return Err(self.error("unterminated byte string"));
+        };
+        let hex: String = self.text[self.position..self.position + length]
+            .split_whitespace()
+            .collect();
+        let bytes = hex::decode(hex)
+            .map_err(|e| self.error_at(start, &format!("invalid byte string: {}", e)))?;
+        self.position += length + 1;
+        Ok(Data::Bytes(bytes))
+    }
+
+    fn number(&mut self) -> Result<BigInt> {
+        let start = self.position;
+        if self.peek() == Some('-') {
+            self.position += 1;
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        self.text[start..self.position]
+            .parse()
+            .map_err(|_| self.error_at(start, "invalid number"))
+    }
+
+    fn tagged(&mut self, tag: BigInt) -> Result<Data> {
+        let start = self.position;
+        self.expect('(')?;
+        let item = self.item()?;
+        self.skip_whitespace();
+        self.expect(')')?;
+        let tag = u64::try_from(&tag).map_err(|_| self.error_at(start, "invalid tag"))?;
+        match (tag, item) {
+            (121..=127, Data::List(fields)) => Ok(Data::Constr(tag - 121, fields)),
+            (1280..=1400, Data::List(fields)) => Ok(Data::Constr(tag - 1280 + 7, fields)),
+            (102, Data::List(mut items)) if items.len() == 2 => {
+                let (Data::List(fields), Data::Int(index)) = (items.remove(1), items.remove(0))
+                else {
+                    return Err(self.error_at(start, "expected [index, [fields]] in tag 102"));
+                };
+                let index = u64::try_from(&index)
+                    .map_err(|_| self.error_at(start, "invalid constructor index"))?;
+                Ok(Data::Constr(index, fields))
+            }
+            (2, Data::Bytes(bytes)) => Ok(Data::Int(BigInt::from_bytes_be(Sign::Plus, &bytes))),
+            (3, Data::Bytes(bytes)) => {
+                Ok(Data::Int(-BigInt::from_bytes_be(Sign::Plus, &bytes) - 1))
+            }
+            (121..=127 | 1280..=1400 | 102, _) => {
+                Err(self.error_at(start, "constructor fields must be an array"))
+            }
+            (2 | 3, _) => Err(self.error_at(start, "bignums must be byte strings")),
+            (tag, _) => Err(self.error_at(start, &format!("unsupported tag {}", tag))),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{}'", expected)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        self.error_at(self.position, message)
+    }
+
+    fn error_at(&self, position: usize, message: &str) -> anyhow::Error {
+        let before = &self.text[..position];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rfind('\n').map_or(position, |i| position - i - 1) + 1;
+        anyhow!("{} at line {}, column {}", message, line, column)
+    }
+}
+
+impl Data {
+    pub(crate) fn to_plutus_data(&self) -> Result<PlutusData> {
+        let mut encoder = Encoder::new(vec![]);
+        self.encode(&mut encoder)?;
+        decode(&encoder.into_writer())
+    }
+
+    /// Encodes the data the same way the ledger and cardano-cli do:
+    /// non-empty lists are indefinite, and long byte strings are split into 64 byte chunks.
+    fn encode(&self, encoder: &mut Encoder<Vec<u8>>) -> Result<()> {
+        match self {
+            Data::Int(int) => encode_int(int, encoder)?,
+            Data::Bytes(bytes) => encode_bytes(bytes, encoder)?,
+            Data::List(items) => encode_list(items, encoder)?,
+            Data::Map(entries) => {
+                encoder.map(entries.len() as u64)?;
+                for (key, value) in entries {
+                    key.encode(encoder)?;
+                    value.encode(encoder)?;
+                }
+            }
+            Data::Constr(index, fields) => match index {
+                0..=6 => {
+                    encoder.tag(Tag::new(121 + index))?;
+                    encode_list(fields, encoder)?;
+                }
+                7..=127 => {
+                    encoder.tag(Tag::new(1280 + index - 7))?;
+                    encode_list(fields, encoder)?;
+                }
+                _ => {
+                    encoder.tag(Tag::new(102))?.array(2)?.u64(*index)?;
+                    encode_list(fields, encoder)?;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+fn encode_list(items: &[Data], encoder: &mut Encoder<Vec<u8>>) -> Result<()> {
+    if items.is_empty() {
+        encoder.array(0)?;
+        return Ok(());
+    }
+    encoder.begin_array()?;
+    for item in items {
+        item.encode(encoder)?;
+    }
+    encoder.end()?;
+    Ok(())
+}
+
+fn encode_bytes(bytes: &[u8], encoder: &mut Encoder<Vec<u8>>) -> Result<()> {
+    if bytes.len() <= 64 {
+        encoder.bytes(bytes)?;
+        return Ok(());
+    }
+    encoder.begin_bytes()?;
+    for chunk in bytes.chunks(64) {
+        encoder.bytes(chunk)?;
+    }
+    encoder.end()?;
+    Ok(())
+}
+
+fn encode_int(int: &BigInt, encoder: &mut Encoder<Vec<u8>>) -> Result<()> {
+    if let Ok(int) = i128::try_from(int)
+        && let Ok(int) = minicbor::data::Int::try_from(int)
+    {
+        encoder.int(int)?;
+        return Ok(());
+    }
+    // too big for a CBOR integer, so use a bignum
+    let (tag, magnitude) = match int.sign() {
+        Sign::Minus => (3, -int - 1),
+        _ => (2, int.clone()),
+    };
+    encoder.tag(Tag::new(tag))?;
+    encode_bytes(&magnitude.to_bytes_be().1, encoder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the input, and hex-encodes the CBOR it comes out as
+    fn cbor(input: &str) -> String {
+        let data = parse(input).unwrap_or_else(|e| panic!("{}: {:#}", input, e));
+        hex::encode(minicbor::to_vec(&data).unwrap())
+    }
+
+    fn error(input: &str) -> String {
+        match parse(input) {
+            Ok(_) => panic!("expected {} not to parse", input),
+            Err(error) => format!("{:#}", error),
+        }
+    }
+
+    #[test]
+    fn reads_plain_integers_as_numbers() {
+        assert_eq!(cbor("10"), "0a");
+        assert_eq!(cbor("42"), "182a");
+        assert_eq!(cbor("1864"), "190748");
+        assert_eq!(cbor("-1"), "20");
+        assert_eq!(cbor("-1000"), "3903e7");
+        assert_eq!(cbor("18446744073709551616"), "c249010000000000000000");
+    }
+
+    #[test]
+    fn reads_hex_as_cbor() {
+        assert_eq!(cbor("d87980"), "d87980");
+        assert_eq!(cbor("0a"), "0a");
+        assert_eq!(cbor("  182a\n"), "182a");
+    }
+
+    #[test]
+    fn reads_diagnostic_notation() {
+        assert_eq!(cbor("h'cafe'"), "42cafe");
+        assert_eq!(cbor("h''"), "40");
+        assert_eq!(cbor("[]"), "80");
+        assert_eq!(cbor("[1, [_ 2]]"), "9f019f02ffff");
+        assert_eq!(cbor("{1: h'', 2: [3]}"), "a20140029f03ff");
+        assert_eq!(cbor("121([])"), "d87980");
+        assert_eq!(cbor("122([1])"), "d87a9f01ff");
+        assert_eq!(cbor("127([])"), "d87f80");
+        assert_eq!(cbor("1280([])"), "d9050080");
+        assert_eq!(cbor("102([200, [1]])"), "d8668218c89f01ff");
+        assert_eq!(cbor("2(h'010000000000000000')"), "c249010000000000000000");
+    }
+
+    #[test]
+    fn reads_detailed_schema_json() {
+        assert_eq!(
+            cbor(r#"{"constructor": 0, "fields": [{"int": 1}, {"bytes": "ab"}]}"#),
+            "d8799f0141abff"
+        );
+        assert_eq!(
+            cbor(r#"{"map": [{"k": {"int": 1}, "v": {"list": []}}]}"#),
+            "a10180"
+        );
+        assert_eq!(
+            cbor(r#"{"int": "-18446744073709551617"}"#),
+            "c349010000000000000000"
+        );
+    }
+
+    #[test]
+    fn reads_files() {
+        let path = std::env::temp_dir().join(format!("gastronomy-datum-{}", std::process::id()));
+        fs::write(&path, "121([42])\n").unwrap();
+        assert_eq!(cbor(&format!("@{}", path.display())), "d8799f182aff");
+        fs::write(&path, [0xd8, 0x79, 0x80]).unwrap();
+        assert_eq!(cbor(&format!("@{}", path.display())), "d87980");
+        fs::remove_file(&path).unwrap();
+
+        assert!(error(&format!("@{}", path.display())).contains("could not read"));
+    }
+
+    #[test]
+    fn reports_malformed_input() {
+        assert!(error("121([1,").contains("unexpected end of input"));
+        assert!(error("h'abc'").contains("invalid byte string"));
+        assert!(error("7(1)").contains("unsupported tag 7"));
+        assert!(error("[1 2]").contains("expected ','"));
+        assert!(error("121(1)").contains("constructor fields must be an array"));
+        assert!(error(r#"{"constructor": 0}"#).contains("missing its fields"));
+        // hex, but not a complete CBOR item, and not diagnostic notation either
+        assert!(error("ab").contains("unexpected character 'a'"));
+        assert!(error("d8799f").contains("unexpected character 'd'"));
+    }
+
+    #[test]
+    fn reports_missing_files_for_schemas() {
+        let blueprint: crate::blueprint::Blueprint = serde_json::from_value(serde_json::json!({
+            "preamble": {},
+            "validators": [{
+                "title": "pool.spend",
+                "compiledCode": "",
+                "parameters": [{ "schema": { "dataType": "integer" } }],
+            }],
+        }))
+        .unwrap();
+        let (_, schemas) = blueprint.select(None).unwrap();
+        let Err(error) = parse_with_schema("@/no/such/datum.json", &schemas[0]) else {
+            panic!("expected a missing file to be an error");
+        };
+        assert!(error.to_string().contains("could not read"), "{}", error);
+    }
+}
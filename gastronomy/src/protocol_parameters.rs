@@ -0,0 +1,517 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, anyhow, bail};
+use pallas::ledger::primitives::conway::Language;
+use serde_json::{Value, json};
+use uplc::machine::cost_model::{CostModel, ExBudget, initialize_cost_model};
+
+/// The protocol parameters which affect script evaluation.
+/// Anything left unset falls back to the defaults built into the machine.
+#[derive(Clone, Debug, Default)]
+pub struct ProtocolParameters {
+    pub plutus_v1_cost_model: Option<Vec<i64>>,
+    pub plutus_v2_cost_model: Option<Vec<i64>>,
+    pub plutus_v3_cost_model: Option<Vec<i64>>,
+    pub max_tx_ex_units: Option<ExBudget>,
+}
+
+impl ProtocolParameters {
+    /// Reads the JSON written by `cardano-cli query protocol-parameters`
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("could not read protocol parameters {}", path.display()))?;
+        let value: Value = serde_json::from_slice(&bytes)?;
+        Self::from_cli_json(&value)
+            .with_context(|| format!("invalid protocol parameters {}", path.display()))
+    }
+
+    pub fn from_cli_json(value: &Value) -> Result<Self> {
+        let cost_models = &value["costModels"];
+        let max_tx_ex_units = match &value["maxTxExecutionUnits"] {
+            Value::Null => None,
+            units => Some(ExBudget {
+                mem: parse_i64(&units["memory"]).context("maxTxExecutionUnits.memory")?,
+                cpu: parse_i64(&units["steps"]).context("maxTxExecutionUnits.steps")?,
+            }),
+        };
+        Ok(Self {
+            plutus_v1_cost_model: parse_cost_model(Language::PlutusV1, &cost_models["PlutusV1"])
+                .context("costModels.PlutusV1")?,
+            plutus_v2_cost_model: parse_cost_model(Language::PlutusV2, &cost_models["PlutusV2"])
+                .context("costModels.PlutusV2")?,
+            plutus_v3_cost_model: parse_cost_model(Language::PlutusV3, &cost_models["PlutusV3"])
+                .context("costModels.PlutusV3")?,
+            max_tx_ex_units,
+        })
+    }
+
+    pub fn to_cli_json(&self) -> Value {
+        let mut value = json!({
+            "costModels": {
+                "PlutusV1": self.plutus_v1_cost_model,
+                "PlutusV2": self.plutus_v2_cost_model,
+                "PlutusV3": self.plutus_v3_cost_model,
+            },
+        });
+        if let Some(units) = self.max_tx_ex_units {
+            value["maxTxExecutionUnits"] = json!({ "memory": units.mem, "steps": units.cpu });
+        }
+        value
+    }
+
+    pub fn cost_model(&self, language: Language) -> CostModel {
+        let costs = match language {
+            Language::PlutusV1 => &self.plutus_v1_cost_model,
+            Language::PlutusV2 => &self.plutus_v2_cost_model,
+            Language::PlutusV3 => &self.plutus_v3_cost_model,
+        };
+        costs
+            .as_ref()
+            .map(|costs| initialize_cost_model(&language, costs))
+            .unwrap_or_default()
+    }
+
+    pub fn max_tx_budget(&self) -> ExBudget {
+        self.max_tx_ex_units.unwrap_or_default()
+    }
+}
+
+/// Cost models come either as a list of parameters in canonical order,
+/// or (from older tools) as a map of named parameters.
+/// PlutusV1 and V2 list their parameters by name, so a map sorts into the same order,
+/// but PlutusV3 appends the parameters of each newer builtin to the end of its list.
+pub(crate) fn parse_cost_model(language: Language, value: &Value) -> Result<Option<Vec<i64>>> {
+    let costs: Vec<&Value> = match value {
+        Value::Null => return Ok(None),
+        Value::Array(costs) => costs.iter().collect(),
+        Value::Object(costs) if language == Language::PlutusV3 => {
+            if let Some(name) = costs
+                .keys()
+                .find(|name| !PLUTUS_V3_PARAMETERS.contains(&name.as_str()))
+            {
+                bail!("unknown PlutusV3 cost model parameter {}", name);
+            }
+            // Cost models from before a hard fork lack the newest builtins' parameters
+            let count = PLUTUS_V3_PARAMETERS
+                .iter()
+                .position(|name| !costs.contains_key(*name))
+                .unwrap_or(PLUTUS_V3_PARAMETERS.len());
+            if let Some(name) = PLUTUS_V3_PARAMETERS[count..]
+                .iter()
+                .find(|name| costs.contains_key(**name))
+            {
+                bail!(
+                    "cost model has {} but is missing {}",
+                    name,
+                    PLUTUS_V3_PARAMETERS[count]
+                );
+            }
+            PLUTUS_V3_PARAMETERS[..count]
+                .iter()
+                .map(|name| &costs[*name])
+                .collect()
+        }
+        Value::Object(costs) => {
+            let mut costs: Vec<(&String, &Value)> = costs.iter().collect();
+            costs.sort_by_key(|(name, _)| *name);
+            costs.into_iter().map(|(_, cost)| cost).collect()
+        }
+        other => bail!("expected a cost model, found {}", other),
+    };
+    costs
+        .into_iter()
+        .map(parse_i64)
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+/// The names of PlutusV3's cost model parameters, in canonical order
+const PLUTUS_V3_PARAMETERS: [&str; 297] = [
+    "addInteger-cpu-arguments-intercept",
+    "addInteger-cpu-arguments-slope",
+    "addInteger-memory-arguments-intercept",
+    "addInteger-memory-arguments-slope",
+    "appendByteString-cpu-arguments-intercept",
+    "appendByteString-cpu-arguments-slope",
+    "appendByteString-memory-arguments-intercept",
+    "appendByteString-memory-arguments-slope",
+    "appendString-cpu-arguments-intercept",
+    "appendString-cpu-arguments-slope",
+    "appendString-memory-arguments-intercept",
+    "appendString-memory-arguments-slope",
+    "bData-cpu-arguments",
+    "bData-memory-arguments",
+    "blake2b_256-cpu-arguments-intercept",
+    "blake2b_256-cpu-arguments-slope",
+    "blake2b_256-memory-arguments",
+    "cekApplyCost-exBudgetCPU",
+    "cekApplyCost-exBudgetMemory",
+    "cekBuiltinCost-exBudgetCPU",
+    "cekBuiltinCost-exBudgetMemory",
+    "cekConstCost-exBudgetCPU",
+    "cekConstCost-exBudgetMemory",
+    "cekDelayCost-exBudgetCPU",
+    "cekDelayCost-exBudgetMemory",
+    "cekForceCost-exBudgetCPU",
+    "cekForceCost-exBudgetMemory",
+    "cekLamCost-exBudgetCPU",
+    "cekLamCost-exBudgetMemory",
+    "cekStartupCost-exBudgetCPU",
+    "cekStartupCost-exBudgetMemory",
+    "cekVarCost-exBudgetCPU",
+    "cekVarCost-exBudgetMemory",
+    "chooseData-cpu-arguments",
+    "chooseData-memory-arguments",
+    "chooseList-cpu-arguments",
+    "chooseList-memory-arguments",
+    "chooseUnit-cpu-arguments",
+    "chooseUnit-memory-arguments",
+    "consByteString-cpu-arguments-intercept",
+    "consByteString-cpu-arguments-slope",
+    "consByteString-memory-arguments-intercept",
+    "consByteString-memory-arguments-slope",
+    "constrData-cpu-arguments",
+    "constrData-memory-arguments",
+    "decodeUtf8-cpu-arguments-intercept",
+    "decodeUtf8-cpu-arguments-slope",
+    "decodeUtf8-memory-arguments-intercept",
+    "decodeUtf8-memory-arguments-slope",
+    "divideInteger-cpu-arguments-constant",
+    "divideInteger-cpu-arguments-model-arguments-c00",
+    "divideInteger-cpu-arguments-model-arguments-c01",
+    "divideInteger-cpu-arguments-model-arguments-c02",
+    "divideInteger-cpu-arguments-model-arguments-c10",
+    "divideInteger-cpu-arguments-model-arguments-c11",
+    "divideInteger-cpu-arguments-model-arguments-c20",
+    "divideInteger-cpu-arguments-model-arguments-minimum",
+    "divideInteger-memory-arguments-intercept",
+    "divideInteger-memory-arguments-minimum",
+    "divideInteger-memory-arguments-slope",
+    "encodeUtf8-cpu-arguments-intercept",
+    "encodeUtf8-cpu-arguments-slope",
+    "encodeUtf8-memory-arguments-intercept",
+    "encodeUtf8-memory-arguments-slope",
+    "equalsByteString-cpu-arguments-constant",
+    "equalsByteString-cpu-arguments-intercept",
+    "equalsByteString-cpu-arguments-slope",
+    "equalsByteString-memory-arguments",
+    "equalsData-cpu-arguments-intercept",
+    "equalsData-cpu-arguments-slope",
+    "equalsData-memory-arguments",
+    "equalsInteger-cpu-arguments-intercept",
+    "equalsInteger-cpu-arguments-slope",
+    "equalsInteger-memory-arguments",
+    "equalsString-cpu-arguments-constant",
+    "equalsString-cpu-arguments-intercept",
+    "equalsString-cpu-arguments-slope",
+    "equalsString-memory-arguments",
+    "fstPair-cpu-arguments",
+    "fstPair-memory-arguments",
+    "headList-cpu-arguments",
+    "headList-memory-arguments",
+    "iData-cpu-arguments",
+    "iData-memory-arguments",
+    "ifThenElse-cpu-arguments",
+    "ifThenElse-memory-arguments",
+    "indexByteString-cpu-arguments",
+    "indexByteString-memory-arguments",
+    "lengthOfByteString-cpu-arguments",
+    "lengthOfByteString-memory-arguments",
+    "lessThanByteString-cpu-arguments-intercept",
+    "lessThanByteString-cpu-arguments-slope",
+    "lessThanByteString-memory-arguments",
+    "lessThanEqualsByteString-cpu-arguments-intercept",
+    "lessThanEqualsByteString-cpu-arguments-slope",
+    "lessThanEqualsByteString-memory-arguments",
+    "lessThanEqualsInteger-cpu-arguments-intercept",
+    "lessThanEqualsInteger-cpu-arguments-slope",
+    "lessThanEqualsInteger-memory-arguments",
+    "lessThanInteger-cpu-arguments-intercept",
+    "lessThanInteger-cpu-arguments-slope",
+    "lessThanInteger-memory-arguments",
+    "listData-cpu-arguments",
+    "listData-memory-arguments",
+    "mapData-cpu-arguments",
+    "mapData-memory-arguments",
+    "mkCons-cpu-arguments",
+    "mkCons-memory-arguments",
+    "mkNilData-cpu-arguments",
+    "mkNilData-memory-arguments",
+    "mkNilPairData-cpu-arguments",
+    "mkNilPairData-memory-arguments",
+    "mkPairData-cpu-arguments",
+    "mkPairData-memory-arguments",
+    "modInteger-cpu-arguments-constant",
+    "modInteger-cpu-arguments-model-arguments-c00",
+    "modInteger-cpu-arguments-model-arguments-c01",
+    "modInteger-cpu-arguments-model-arguments-c02",
+    "modInteger-cpu-arguments-model-arguments-c10",
+    "modInteger-cpu-arguments-model-arguments-c11",
+    "modInteger-cpu-arguments-model-arguments-c20",
+    "modInteger-cpu-arguments-model-arguments-minimum",
+    "modInteger-memory-arguments-intercept",
+    "modInteger-memory-arguments-slope",
+    "multiplyInteger-cpu-arguments-intercept",
+    "multiplyInteger-cpu-arguments-slope",
+    "multiplyInteger-memory-arguments-intercept",
+    "multiplyInteger-memory-arguments-slope",
+    "nullList-cpu-arguments",
+    "nullList-memory-arguments",
+    "quotientInteger-cpu-arguments-constant",
+    "quotientInteger-cpu-arguments-model-arguments-c00",
+    "quotientInteger-cpu-arguments-model-arguments-c01",
+    "quotientInteger-cpu-arguments-model-arguments-c02",
+    "quotientInteger-cpu-arguments-model-arguments-c10",
+    "quotientInteger-cpu-arguments-model-arguments-c11",
+    "quotientInteger-cpu-arguments-model-arguments-c20",
+    "quotientInteger-cpu-arguments-model-arguments-minimum",
+    "quotientInteger-memory-arguments-intercept",
+    "quotientInteger-memory-arguments-minimum",
+    "quotientInteger-memory-arguments-slope",
+    "remainderInteger-cpu-arguments-constant",
+    "remainderInteger-cpu-arguments-model-arguments-c00",
+    "remainderInteger-cpu-arguments-model-arguments-c01",
+    "remainderInteger-cpu-arguments-model-arguments-c02",
+    "remainderInteger-cpu-arguments-model-arguments-c10",
+    "remainderInteger-cpu-arguments-model-arguments-c11",
+    "remainderInteger-cpu-arguments-model-arguments-c20",
+    "remainderInteger-cpu-arguments-model-arguments-minimum",
+    "remainderInteger-memory-arguments-intercept",
+    "remainderInteger-memory-arguments-slope",
+    "serialiseData-cpu-arguments-intercept",
+    "serialiseData-cpu-arguments-slope",
+    "serialiseData-memory-arguments-intercept",
+    "serialiseData-memory-arguments-slope",
+    "sha2_256-cpu-arguments-intercept",
+    "sha2_256-cpu-arguments-slope",
+    "sha2_256-memory-arguments",
+    "sha3_256-cpu-arguments-intercept",
+    "sha3_256-cpu-arguments-slope",
+    "sha3_256-memory-arguments",
+    "sliceByteString-cpu-arguments-intercept",
+    "sliceByteString-cpu-arguments-slope",
+    "sliceByteString-memory-arguments-intercept",
+    "sliceByteString-memory-arguments-slope",
+    "sndPair-cpu-arguments",
+    "sndPair-memory-arguments",
+    "subtractInteger-cpu-arguments-intercept",
+    "subtractInteger-cpu-arguments-slope",
+    "subtractInteger-memory-arguments-intercept",
+    "subtractInteger-memory-arguments-slope",
+    "tailList-cpu-arguments",
+    "tailList-memory-arguments",
+    "trace-cpu-arguments",
+    "trace-memory-arguments",
+    "unBData-cpu-arguments",
+    "unBData-memory-arguments",
+    "unConstrData-cpu-arguments",
+    "unConstrData-memory-arguments",
+    "unIData-cpu-arguments",
+    "unIData-memory-arguments",
+    "unListData-cpu-arguments",
+    "unListData-memory-arguments",
+    "unMapData-cpu-arguments",
+    "unMapData-memory-arguments",
+    "verifyEcdsaSecp256k1Signature-cpu-arguments",
+    "verifyEcdsaSecp256k1Signature-memory-arguments",
+    "verifyEd25519Signature-cpu-arguments-intercept",
+    "verifyEd25519Signature-cpu-arguments-slope",
+    "verifyEd25519Signature-memory-arguments",
+    "verifySchnorrSecp256k1Signature-cpu-arguments-intercept",
+    "verifySchnorrSecp256k1Signature-cpu-arguments-slope",
+    "verifySchnorrSecp256k1Signature-memory-arguments",
+    "cekConstrCost-exBudgetCPU",
+    "cekConstrCost-exBudgetMemory",
+    "cekCaseCost-exBudgetCPU",
+    "cekCaseCost-exBudgetMemory",
+    "bls12_381_G1_add-cpu-arguments",
+    "bls12_381_G1_add-memory-arguments",
+    "bls12_381_G1_compress-cpu-arguments",
+    "bls12_381_G1_compress-memory-arguments",
+    "bls12_381_G1_equal-cpu-arguments",
+    "bls12_381_G1_equal-memory-arguments",
+    "bls12_381_G1_hashToGroup-cpu-arguments-intercept",
+    "bls12_381_G1_hashToGroup-cpu-arguments-slope",
+    "bls12_381_G1_hashToGroup-memory-arguments",
+    "bls12_381_G1_neg-cpu-arguments",
+    "bls12_381_G1_neg-memory-arguments",
+    "bls12_381_G1_scalarMul-cpu-arguments-intercept",
+    "bls12_381_G1_scalarMul-cpu-arguments-slope",
+    "bls12_381_G1_scalarMul-memory-arguments",
+    "bls12_381_G1_uncompress-cpu-arguments",
+    "bls12_381_G1_uncompress-memory-arguments",
+    "bls12_381_G2_add-cpu-arguments",
+    "bls12_381_G2_add-memory-arguments",
+    "bls12_381_G2_compress-cpu-arguments",
+    "bls12_381_G2_compress-memory-arguments",
+    "bls12_381_G2_equal-cpu-arguments",
+    "bls12_381_G2_equal-memory-arguments",
+    "bls12_381_G2_hashToGroup-cpu-arguments-intercept",
+    "bls12_381_G2_hashToGroup-cpu-arguments-slope",
+    "bls12_381_G2_hashToGroup-memory-arguments",
+    "bls12_381_G2_neg-cpu-arguments",
+    "bls12_381_G2_neg-memory-arguments",
+    "bls12_381_G2_scalarMul-cpu-arguments-intercept",
+    "bls12_381_G2_scalarMul-cpu-arguments-slope",
+    "bls12_381_G2_scalarMul-memory-arguments",
+    "bls12_381_G2_uncompress-cpu-arguments",
+    "bls12_381_G2_uncompress-memory-arguments",
+    "bls12_381_finalVerify-cpu-arguments",
+    "bls12_381_finalVerify-memory-arguments",
+    "bls12_381_millerLoop-cpu-arguments",
+    "bls12_381_millerLoop-memory-arguments",
+    "bls12_381_mulMlResult-cpu-arguments",
+    "bls12_381_mulMlResult-memory-arguments",
+    "keccak_256-cpu-arguments-intercept",
+    "keccak_256-cpu-arguments-slope",
+    "keccak_256-memory-arguments",
+    "blake2b_224-cpu-arguments-intercept",
+    "blake2b_224-cpu-arguments-slope",
+    "blake2b_224-memory-arguments",
+    "integerToByteString-cpu-arguments-c0",
+    "integerToByteString-cpu-arguments-c1",
+    "integerToByteString-cpu-arguments-c2",
+    "integerToByteString-memory-arguments-intercept",
+    "integerToByteString-memory-arguments-slope",
+    "byteStringToInteger-cpu-arguments-c0",
+    "byteStringToInteger-cpu-arguments-c1",
+    "byteStringToInteger-cpu-arguments-c2",
+    "byteStringToInteger-memory-arguments-intercept",
+    "byteStringToInteger-memory-arguments-slope",
+    "andByteString-cpu-arguments-intercept",
+    "andByteString-cpu-arguments-slope1",
+    "andByteString-cpu-arguments-slope2",
+    "andByteString-memory-arguments-intercept",
+    "andByteString-memory-arguments-slope",
+    "orByteString-cpu-arguments-intercept",
+    "orByteString-cpu-arguments-slope1",
+    "orByteString-cpu-arguments-slope2",
+    "orByteString-memory-arguments-intercept",
+    "orByteString-memory-arguments-slope",
+    "xorByteString-cpu-arguments-intercept",
+    "xorByteString-cpu-arguments-slope1",
+    "xorByteString-cpu-arguments-slope2",
+    "xorByteString-memory-arguments-intercept",
+    "xorByteString-memory-arguments-slope",
+    "complementByteString-cpu-arguments-intercept",
+    "complementByteString-cpu-arguments-slope",
+    "complementByteString-memory-arguments-intercept",
+    "complementByteString-memory-arguments-slope",
+    "readBit-cpu-arguments",
+    "readBit-memory-arguments",
+    "writeBits-cpu-arguments-intercept",
+    "writeBits-cpu-arguments-slope",
+    "writeBits-memory-arguments-intercept",
+    "writeBits-memory-arguments-slope",
+    "replicateByte-cpu-arguments-intercept",
+    "replicateByte-cpu-arguments-slope",
+    "replicateByte-memory-arguments-intercept",
+    "replicateByte-memory-arguments-slope",
+    "shiftByteString-cpu-arguments-intercept",
+    "shiftByteString-cpu-arguments-slope",
+    "shiftByteString-memory-arguments-intercept",
+    "shiftByteString-memory-arguments-slope",
+    "rotateByteString-cpu-arguments-intercept",
+    "rotateByteString-cpu-arguments-slope",
+    "rotateByteString-memory-arguments-intercept",
+    "rotateByteString-memory-arguments-slope",
+    "countSetBits-cpu-arguments-intercept",
+    "countSetBits-cpu-arguments-slope",
+    "countSetBits-memory-arguments",
+    "findFirstSetBit-cpu-arguments-intercept",
+    "findFirstSetBit-cpu-arguments-slope",
+    "findFirstSetBit-memory-arguments",
+    "ripemd_160-cpu-arguments-intercept",
+    "ripemd_160-cpu-arguments-slope",
+    "ripemd_160-memory-arguments",
+];
+
+/// Some providers send large numbers as strings
+pub(crate) fn parse_i64(value: &Value) -> Result<i64> {
+    match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| anyhow!("expected an integer, found {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Map;
+
+    use super::*;
+
+    fn test_data(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../test_data")
+            .join(name)
+    }
+
+    fn named_v3_cost_model(count: usize) -> Value {
+        let costs: Map<String, Value> = PLUTUS_V3_PARAMETERS[..count]
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.to_string(), json!(index)))
+            .collect();
+        Value::Object(costs)
+    }
+
+    #[test]
+    fn maps_named_plutus_v3_parameters_into_canonical_order() {
+        // Each parameter's value is its index in the canonical list
+        let parameters =
+            ProtocolParameters::load(&test_data("cardano-cli/protocol-parameters.json")).unwrap();
+
+        let costs = parameters.plutus_v3_cost_model.unwrap();
+        assert_eq!(costs, (0..251).collect::<Vec<i64>>());
+        assert_eq!(parameters.plutus_v1_cost_model, None);
+        let budget = parameters.max_tx_budget();
+        assert_eq!((budget.mem, budget.cpu), (14_000_000, 10_000_000_000));
+    }
+
+    #[test]
+    fn reads_plutus_v3_cost_models_from_after_plomin() {
+        let costs = parse_cost_model(Language::PlutusV3, &named_v3_cost_model(297)).unwrap();
+        assert_eq!(costs, Some((0..297).collect()));
+
+        let costs: Vec<i64> = (0..297).collect();
+        let parsed = parse_cost_model(Language::PlutusV3, &json!(costs)).unwrap();
+        assert_eq!(parsed, Some(costs));
+    }
+
+    #[test]
+    fn sorts_named_plutus_v1_and_v2_parameters() {
+        let costs = json!({
+            "addInteger-cpu-arguments-slope": 2,
+            "addInteger-cpu-arguments-intercept": 1,
+            "bData-cpu-arguments": "3",
+        });
+        for language in [Language::PlutusV1, Language::PlutusV2] {
+            let parsed = parse_cost_model(language, &costs).unwrap();
+            assert_eq!(parsed, Some(vec![1, 2, 3]));
+        }
+    }
+
+    #[test]
+    fn rejects_incomplete_plutus_v3_cost_models() {
+        let mut costs = named_v3_cost_model(251);
+        costs["andByteString-cpu-arguments-slope9"] = json!(1);
+        let error = parse_cost_model(Language::PlutusV3, &costs).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown PlutusV3 cost model parameter andByteString-cpu-arguments-slope9"
+        );
+
+        let mut costs = named_v3_cost_model(251);
+        costs
+            .as_object_mut()
+            .unwrap()
+            .remove("cekCaseCost-exBudgetCPU");
+        let error = parse_cost_model(Language::PlutusV3, &costs).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "cost model has cekCaseCost-exBudgetMemory but is missing cekCaseCost-exBudgetCPU"
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path, str::FromStr};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const VERSION: u64 = 1;
+
+/// Where in the source a term came from: a span (or just its start) and the enclosing function.
+/// Lines and columns start at 1, and the span's end is inclusive.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceLocation {
+    pub file: String,
+    #[serde(alias = "startLine")]
+    pub line: usize,
+    #[serde(alias = "startColumn")]
+    pub column: usize,
+    pub end_line: Option<usize>,
+    pub end_column: Option<usize>,
+    #[serde(alias = "name")]
+    pub function: Option<String>,
+}
+
+impl SourceLocation {
+    /// The span's end, which is its start when the source map only gave a point
+    pub fn end(&self) -> (usize, usize) {
+        match (self.end_line, self.end_column) {
+            (Some(line), Some(column)) => (line, column),
+            _ => (self.line, self.column),
+        }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)?;
+        let end = self.end();
+        if end != (self.line, self.column) {
+            write!(f, "-{}:{}", end.0, end.1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the `file:line:column` locations in Aiken's source maps,
+/// optionally followed by the span's end as `-line:column`
+impl FromStr for SourceLocation {
+    type Err = anyhow::Error;
+
+    fn from_str(location: &str) -> Result<Self> {
+        let invalid = || anyhow!("invalid source location {}", location);
+        let (start, end) = match location.rsplit_once('-') {
+            Some((start, end)) if parse_position(end).is_some() => (start, Some(end)),
+            _ => (location, None),
+        };
+        // files can have colons in their names, so split from the right
+        let mut pieces = start.rsplitn(3, ':');
+        let column = pieces
+            .next()
+            .and_then(|c| c.parse().ok())
+            .ok_or_else(invalid)?;
+        let line = pieces
+            .next()
+            .and_then(|l| l.parse().ok())
+            .ok_or_else(invalid)?;
+        let file = pieces.next().ok_or_else(invalid)?.to_string();
+        let end = end.and_then(parse_position);
+        Ok(Self {
+            file,
+            line,
+            column,
+            end_line: end.map(|(line, _)| line),
+            end_column: end.map(|(_, column)| column),
+            function: None,
+        })
+    }
+}
+
+fn parse_position(position: &str) -> Option<(usize, usize)> {
+    let (line, column) = position.split_once(':')?;
+    Some((line.parse().ok()?, column.parse().ok()?))
+}
+
+/// Parses the source map of an Aiken export or blueprint
+pub fn from_aiken(source_map: BTreeMap<u64, String>) -> Result<BTreeMap<u64, SourceLocation>> {
+    source_map
+        .into_iter()
+        .map(|(index, location)| Ok((index, location.parse()?)))
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct SidecarEntry {
+    index: u64,
+    #[serde(flatten)]
+    location: SourceLocation,
+}
+
+/// Reads a source map for a program from another toolchain than Aiken:
+/// the toolchain-neutral format described in the README,
+/// or a Plutarch or Scalus style list of locations
+pub fn load(path: &Path) -> Result<BTreeMap<u64, SourceLocation>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+    let json: Value = serde_json::from_str(&text)
+        .with_context(|| format!("invalid source map {}", path.display()))?;
+    from_json(json).with_context(|| format!("invalid source map {}", path.display()))
+}
+
+/// Reads a source map already parsed as JSON
+pub fn from_json(json: Value) -> Result<BTreeMap<u64, SourceLocation>> {
+    let locations = match json {
+        Value::Array(_) => serde_json::from_value::<Vec<SidecarEntry>>(json)?
+            .into_iter()
+            .map(|entry| (entry.index, entry.location))
+            .collect(),
+        Value::Object(mut object) if object.contains_key("locations") => {
+            if let Some(version) = object.get("version").and_then(Value::as_u64)
+                && version != VERSION
+            {
+                bail!("unsupported source map version {}", version);
+            }
+            serde_json::from_value(object.remove("locations").unwrap_or_default())?
+        }
+        json => serde_json::from_value(json)?,
+    };
+    Ok(locations)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn location(file: &str, line: usize, column: usize) -> SourceLocation {
+        SourceLocation {
+            file: file.to_string(),
+            line,
+            column,
+            end_line: None,
+            end_column: None,
+            function: None,
+        }
+    }
+
+    fn span(file: &str, start: (usize, usize), end: (usize, usize)) -> SourceLocation {
+        SourceLocation {
+            end_line: Some(end.0),
+            end_column: Some(end.1),
+            ..location(file, start.0, start.1)
+        }
+    }
+
+    #[test]
+    fn parses_aiken_locations() -> Result<()> {
+        let parsed: SourceLocation = "validators/pool.ak:12:5".parse()?;
+        assert_eq!(parsed, location("validators/pool.ak", 12, 5));
+        assert_eq!(parsed.end(), (12, 5));
+
+        let parsed: SourceLocation = "validators/pool.ak:12:5-14:2".parse()?;
+        assert_eq!(parsed, span("validators/pool.ak", (12, 5), (14, 2)));
+        assert_eq!(parsed.end(), (14, 2));
+
+        // dashes and colons in the file name aren't mistaken for the span's end
+        let parsed: SourceLocation = "my-lib/pool.ak:3:1".parse()?;
+        assert_eq!(parsed, location("my-lib/pool.ak", 3, 1));
+        let parsed: SourceLocation = "my-lib/pool.ak:3:1-4:2".parse()?;
+        assert_eq!(parsed, span("my-lib/pool.ak", (3, 1), (4, 2)));
+        let parsed: SourceLocation = r"C:\my-lib\pool.ak:3:1".parse()?;
+        assert_eq!(parsed, location(r"C:\my-lib\pool.ak", 3, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_locations() {
+        for invalid in [
+            "pool.ak",
+            "pool.ak:3",
+            "pool.ak:x:1",
+            "pool.ak:3:1-4:x",
+            "pool.ak:3:1-4",
+            "3:1",
+        ] {
+            let error = invalid
+                .parse::<SourceLocation>()
+                .err()
+                .map(|e| e.to_string());
+            assert_eq!(
+                error,
+                Some(format!("invalid source location {}", invalid)),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_locations() -> Result<()> {
+        for location in [
+            location("validators/pool.ak", 12, 5),
+            span("my-lib/pool.ak", (3, 1), (4, 2)),
+        ] {
+            assert_eq!(location.to_string().parse::<SourceLocation>()?, location);
+            let json = serde_json::to_value(&location)?;
+            assert_eq!(serde_json::from_value::<SourceLocation>(json)?, location);
+        }
+        // a span which ends where it starts is just a point
+        let point = span("pool.ak", (3, 1), (3, 1));
+        assert_eq!(point.to_string(), "pool.ak:3:1");
+        Ok(())
+    }
+
+    #[test]
+    fn reads_each_source_map_shape() -> Result<()> {
+        let expected = BTreeMap::from([
+            (
+                12,
+                SourceLocation {
+                    function: Some("validateSwap".to_string()),
+                    ..span("src/pool.ts", (40, 5), (40, 31))
+                },
+            ),
+            (13, location("src/pool.ts", 41, 3)),
+        ]);
+
+        let versioned = json!({
+            "version": 1,
+            "locations": {
+                "12": {
+                    "file": "src/pool.ts", "line": 40, "column": 5,
+                    "endLine": 40, "endColumn": 31, "function": "validateSwap",
+                },
+                "13": { "file": "src/pool.ts", "line": 41, "column": 3 },
+            },
+        });
+        assert_eq!(from_json(versioned.clone())?, expected);
+
+        let bare = versioned["locations"].clone();
+        assert_eq!(from_json(bare)?, expected);
+
+        // Plutarch and Scalus name the span's start and the function differently
+        let sidecar = json!([
+            {
+                "index": 12, "file": "src/pool.ts", "startLine": 40, "startColumn": 5,
+                "endLine": 40, "endColumn": 31, "name": "validateSwap",
+            },
+            { "index": 13, "file": "src/pool.ts", "line": 41, "column": 3 },
+        ]);
+        assert_eq!(from_json(sidecar)?, expected);
+
+        let aiken = BTreeMap::from([
+            (12, "src/pool.ak:40:5-40:31".to_string()),
+            (13, "src/pool.ak:41:3".to_string()),
+        ]);
+        let parsed = from_aiken(aiken)?;
+        assert_eq!(parsed[&12], span("src/pool.ak", (40, 5), (40, 31)));
+        assert_eq!(parsed[&13], location("src/pool.ak", 41, 3));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_source_maps() {
+        let newer = json!({ "version": 2, "locations": {} });
+        let error = from_json(newer).err().map(|e| e.to_string());
+        assert_eq!(error.as_deref(), Some("unsupported source map version 2"));
+
+        let invalid = [
+            json!({ "12": { "file": "src/pool.ts", "line": "40", "column": 5 } }),
+            json!({ "x": { "file": "src/pool.ts", "line": 40, "column": 5 } }),
+            json!([{ "file": "src/pool.ts", "line": 40, "column": 5 }]),
+            json!({ "version": 1, "locations": [] }),
+            json!("src/pool.ts:40:5"),
+        ];
+        for source_map in invalid {
+            assert!(from_json(source_map.clone()).is_err(), "{}", source_map);
+        }
+        let aiken = BTreeMap::from([(12, "src/pool.ak:40".to_string())]);
+        assert!(from_aiken(aiken).is_err());
+    }
+
+    #[test]
+    fn loads_source_map_files() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("gastronomy-{}.map.json", std::process::id()));
+        let source_map = json!({
+            "version": 1,
+            "locations": { "0": { "file": "src/pool.ts", "line": 1, "column": 1 } },
+        });
+        fs::write(&path, source_map.to_string())?;
+        let loaded = load(&path);
+        fs::remove_file(&path)?;
+        assert_eq!(
+            loaded?,
+            BTreeMap::from([(0, location("src/pool.ts", 1, 1))])
+        );
+
+        let error = load(&path).err().map(|e| e.to_string());
+        assert_eq!(error, Some(format!("could not read {}", path.display())));
+        Ok(())
+    }
+}
@@ -0,0 +1,1312 @@
+use std::{collections::HashSet, fs, ops::Range, path::Path};
+
+use anyhow::{Context, Result, anyhow, bail};
+use minicbor::{
+    Decoder, Encoder,
+    data::{Tag, Type},
+};
+use pallas::{
+    codec::utils::{Bytes, NonEmptyKeyValuePairs, NonEmptySet, Nullable, Set},
+    crypto::hash::Hasher,
+    ledger::{
+        addresses::{Address, ScriptHash, ShelleyPaymentPart, StakePayload},
+        primitives::conway::{
+            AuxiliaryData, BootstrapWitness, Certificate, Coin, DatumOption, GovAction, Language,
+            Mint, MintedTransactionOutput, MintedTx, NativeScript, NetworkId, PlutusData,
+            PlutusScript, PositiveCoin, ProposalProcedure, PseudoScript, Redeemer, RedeemerTag,
+            Redeemers, RequiredSigners, RewardAccount, TransactionInput, TransactionOutput,
+            VKeyWitness, Voter, VotingProcedures,
+        },
+        traverse::{Era, MultiEraTx},
+    },
+};
+use serde::Deserialize;
+use serde_json::Value;
+use uplc::{Hash, tx::ResolvedInput};
+
+use crate::{chain_query::ChainQuery, plutus_data};
+
+const BODY_OUTPUTS: u64 = 1;
+const BODY_CERTIFICATES: u64 = 4;
+const WITNESS_PLUTUS_DATA: u64 = 4;
+const WITNESS_REDEEMERS: u64 = 5;
+const SET_TAG: u64 = 258;
+
+/// Fills in what a draft transaction leaves out, so it decodes like a complete one:
+/// a bare transaction body gets an empty witness set, no auxiliary data, and is marked valid.
+/// Transactions from before Alonzo are a body, a witness set and auxiliary data,
+/// so those get the validity flag in between.
+/// The body is copied byte for byte, so the transaction id doesn't change.
+pub fn complete(tx_bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = Decoder::new(tx_bytes);
+    let items: Vec<&[u8]> = match decoder.datatype()? {
+        Type::Map | Type::MapIndef => vec![tx_bytes],
+        Type::Array => match decoder.array()? {
+            Some(len @ 1..4) => (0..len)
+                .map(|_| skip(&mut decoder).map(|item| &tx_bytes[item]))
+                .collect::<Result<_>>()?,
+            _ => return Ok(tx_bytes.to_vec()),
+        },
+        _ => return Ok(tx_bytes.to_vec()),
+    };
+    let empty_witness_set: &[u8] = &[0xa0];
+    let valid: &[u8] = &[0xf5];
+    let no_auxiliary_data: &[u8] = &[0xf6];
+    let completed = match items.as_slice() {
+        [body] => [*body, empty_witness_set, valid, no_auxiliary_data],
+        [body, witness_set] => [*body, *witness_set, valid, no_auxiliary_data],
+        [body, witness_set, auxiliary_data] => [*body, *witness_set, valid, *auxiliary_data],
+        _ => unreachable!("only transactions with 1 to 3 items are completed"),
+    };
+
+    let mut encoder = Encoder::new(vec![]);
+    encoder.array(4)?;
+    for item in completed {
+        encoder.writer_mut().extend_from_slice(item);
+    }
+    Ok(encoder.into_writer())
+}
+
+/// Works out which era a transaction is from, for when nothing says so.
+/// Only Conway transactions tag their sets, key their redeemers by purpose or have its
+/// certificates, and only Babbage on write outputs as maps. Past that, a transaction could
+/// be from any era which knows all its fields, since the later ones encode them the same way,
+/// so this guesses the earliest of those which decodes it.
+pub fn detect_era(tx_bytes: &[u8]) -> Result<Era> {
+    let (body, witness_set) = fields(tx_bytes).unwrap_or_default();
+    let earliest = earliest_era(&body, &witness_set);
+    let era = [Era::Alonzo, Era::Babbage, Era::Conway]
+        .into_iter()
+        .filter(|&era| era >= earliest)
+        .find(|&era| {
+            let (known_body_keys, known_witness_keys) = known_keys(era);
+            body.iter().all(|(key, _)| known_body_keys.contains(key))
+                && witness_set
+                    .iter()
+                    .all(|(key, _)| known_witness_keys.contains(key))
+                && MultiEraTx::decode_for_era(era, tx_bytes).is_ok()
+        });
+    if let Some(era) = era {
+        return Ok(era);
+    }
+    match MultiEraTx::decode(tx_bytes) {
+        Ok(tx) if tx.era() < Era::Alonzo => bail!(
+            "the transaction is from the {:?} era, which predates plutus scripts",
+            tx.era()
+        ),
+        // leave it to the conway decoder to explain what's wrong
+        _ => Ok(Era::Conway),
+    }
+}
+
+type Fields<'a> = Vec<(u64, &'a [u8])>;
+
+/// The keys and raw values of the transaction body's and witness set's fields
+fn fields(tx_bytes: &[u8]) -> Option<(Fields<'_>, Fields<'_>)> {
+    let mut decoder = Decoder::new(tx_bytes);
+    decoder.array().ok()?;
+    let body = skip(&mut decoder).ok()?;
+    let witness_set = skip(&mut decoder).ok()?;
+    Some((
+        map_entries(&tx_bytes[body])?,
+        map_entries(&tx_bytes[witness_set])?,
+    ))
+}
+
+fn map_entries(bytes: &[u8]) -> Option<Fields<'_>> {
+    let mut entries = vec![];
+    let mut decoder = Decoder::new(bytes);
+    let len = decoder.map().ok()?;
+    while len.is_none_or(|len| entries.len() < len as usize) {
+        if len.is_none() && decoder.datatype().ok()? == Type::Break {
+            break;
+        }
+        let key = decoder.u64().ok()?;
+        let value = skip(&mut decoder).ok()?;
+        entries.push((key, &bytes[value]));
+    }
+    Some(entries)
+}
+
+/// The earliest era which lays out fields the way this transaction does
+fn earliest_era(body: &[(u64, &[u8])], witness_set: &[(u64, &[u8])]) -> Era {
+    let datatype = |bytes: &[u8]| Decoder::new(bytes).datatype().ok();
+    // no field was tagged before Conway's sets could be
+    let tagged = body
+        .iter()
+        .chain(witness_set)
+        .any(|(_, value)| datatype(value) == Some(Type::Tag));
+    let keyed_redeemers = witness_set.iter().any(|&(key, value)| {
+        key == WITNESS_REDEEMERS && matches!(datatype(value), Some(Type::Map | Type::MapIndef))
+    });
+    let certificates = body
+        .iter()
+        .any(|&(key, value)| key == BODY_CERTIFICATES && has_conway_certificates(value));
+    if tagged || keyed_redeemers || certificates {
+        return Era::Conway;
+    }
+    let babbage = body.iter().any(|&(key, value)| {
+        key == BODY_OUTPUTS
+            && minicbor::decode::<Vec<MintedTransactionOutput>>(value).is_ok_and(|outputs| {
+                outputs
+                    .iter()
+                    .any(|output| matches!(output, MintedTransactionOutput::PostAlonzo(_)))
+            })
+    });
+    if babbage { Era::Babbage } else { Era::Alonzo }
+}
+
+/// Conway replaced the certificates for genesis delegation and MIRs,
+/// and numbers the ones it added (for DReps, the committee and vote delegation) from 7
+fn has_conway_certificates(bytes: &[u8]) -> bool {
+    set_items(bytes).is_ok_and(|(certificates, _)| {
+        certificates.iter().any(|certificate| {
+            let mut decoder = Decoder::new(certificate);
+            decoder.array().is_ok() && decoder.u64().is_ok_and(|kind| kind >= 7)
+        })
+    })
+}
+
+/// The transaction body and witness set keys each era introduced
+fn known_keys(era: Era) -> (&'static [u64], &'static [u64]) {
+    match era {
+        Era::Alonzo => (
+            &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 11, 13, 14, 15],
+            &[0, 1, 2, 3, 4, 5],
+        ),
+        Era::Babbage => (
+            &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 11, 13, 14, 15, 16, 17, 18],
+            &[0, 1, 2, 3, 4, 5, 6],
+        ),
+        _ => (
+            &[
+                0, 1, 2, 3, 4, 5, 7, 8, 9, 11, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22,
+            ],
+            &[0, 1, 2, 3, 4, 5, 6, 7],
+        ),
+    }
+}
+
+/// Whether scripts in this language could run in transactions from this era
+pub fn check_language(era: Era, language: Language) -> Result<()> {
+    let supported = match era {
+        Era::Alonzo => matches!(language, Language::PlutusV1),
+        Era::Babbage => matches!(language, Language::PlutusV1 | Language::PlutusV2),
+        _ => true,
+    };
+    if !supported {
+        bail!(
+            "the transaction is from the {:?} era, which doesn't support {:?} scripts",
+            era,
+            language
+        );
+    }
+    Ok(())
+}
+
+/// Outputs from before Babbage are plain arrays of an address, a value and a datum hash.
+/// Chain providers describe every output the same way, so this turns them back into that form.
+pub fn legacy_output(output: &TransactionOutput) -> Result<TransactionOutput> {
+    let TransactionOutput::PostAlonzo(output) = output else {
+        return Ok(output.clone());
+    };
+    let datum_hash = match &output.datum_option {
+        None => None,
+        Some(DatumOption::Hash(hash)) => Some(*hash),
+        Some(DatumOption::Data(_)) => bail!("outputs couldn't hold inline datums before Babbage"),
+    };
+    if output.script_ref.is_some() {
+        bail!("outputs couldn't hold reference scripts before Babbage");
+    }
+    // values are encoded the same way in both forms
+    let mut encoder = Encoder::new(vec![]);
+    encoder.array(if datum_hash.is_some() { 3 } else { 2 })?;
+    encoder.bytes(&output.address)?;
+    encoder
+        .writer_mut()
+        .extend_from_slice(&minicbor::to_vec(&output.value)?);
+    if let Some(hash) = datum_hash {
+        encoder.bytes(hash.as_ref())?;
+    }
+    minicbor::decode(&encoder.into_writer())
+        .map_err(|e| anyhow!("could not build a legacy output: {}", e))
+}
+
+/// Decodes a transaction, naming the field at fault when it doesn't decode
+pub fn decode(tx_bytes: &[u8]) -> Result<MintedTx<'_>> {
+    MintedTx::decode_fragment(tx_bytes).map_err(|error| match find_bad_field(tx_bytes) {
+        Some(field) => anyhow!("could not decode the transaction: invalid {}", field),
+        None => anyhow!("could not decode the transaction: {}", error),
+    })
+}
+
+fn find_bad_field(tx_bytes: &[u8]) -> Option<String> {
+    let mut decoder = Decoder::new(tx_bytes);
+    if !matches!(decoder.array(), Ok(Some(4) | None)) {
+        return Some(
+            "transaction, expected an array of its body, witness set, validity and metadata"
+                .to_string(),
+        );
+    }
+    let body = skip(&mut decoder).ok()?;
+    let witness_set = skip(&mut decoder).ok()?;
+    if let Some(field) = find_bad_entry(&tx_bytes[body], "transaction body", body_field) {
+        return Some(field);
+    }
+    if let Some(field) = find_bad_entry(&tx_bytes[witness_set], "witness set", witness_field) {
+        return Some(field);
+    }
+    if decoder.bool().is_err() {
+        return Some("validity flag, expected a boolean".to_string());
+    }
+    let metadata = skip(&mut decoder).ok()?;
+    decode_as::<Nullable<AuxiliaryData>>(&tx_bytes[metadata])
+        .err()
+        .map(|reason| format!("auxiliary data: {}", reason))
+}
+
+/// Decodes each entry of a CBOR map on its own, to find the first which doesn't decode
+fn find_bad_entry(
+    bytes: &[u8],
+    name: &str,
+    field: fn(u64, &[u8]) -> (&'static str, Result<(), String>),
+) -> Option<String> {
+    let mut decoder = Decoder::new(bytes);
+    let Ok(len) = decoder.map() else {
+        return Some(format!("{}, expected a map", name));
+    };
+    let mut count = 0;
+    while len.is_none_or(|len| count < len) {
+        if len.is_none() && decoder.datatype().ok()? == Type::Break {
+            break;
+        }
+        let Ok(key) = decoder.u64() else {
+            return Some(format!("{}, expected integer keys", name));
+        };
+        let value = skip(&mut decoder).ok()?;
+        if let (field, Err(reason)) = field(key, &bytes[value]) {
+            return Some(format!(
+                "{} in the {} (key {}): {}",
+                field, name, key, reason
+            ));
+        }
+        count += 1;
+    }
+    None
+}
+
+fn body_field(key: u64, bytes: &[u8]) -> (&'static str, Result<(), String>) {
+    match key {
+        0 => ("inputs", decode_as::<Set<TransactionInput>>(bytes)),
+        1 => ("outputs", decode_as::<Vec<MintedTransactionOutput>>(bytes)),
+        2 => ("fee", decode_as::<u64>(bytes)),
+        3 => ("ttl", decode_as::<u64>(bytes)),
+        4 => ("certificates", decode_as::<NonEmptySet<Certificate>>(bytes)),
+        5 => (
+            "withdrawals",
+            decode_as::<NonEmptyKeyValuePairs<RewardAccount, Coin>>(bytes),
+        ),
+        7 => ("auxiliary data hash", decode_as::<Hash<32>>(bytes)),
+        8 => ("validity interval start", decode_as::<u64>(bytes)),
+        9 => ("mint", decode_as::<Mint>(bytes)),
+        11 => ("script data hash", decode_as::<Hash<32>>(bytes)),
+        13 => (
+            "collateral",
+            decode_as::<NonEmptySet<TransactionInput>>(bytes),
+        ),
+        14 => ("required signers", decode_as::<RequiredSigners>(bytes)),
+        15 => ("network id", decode_as::<NetworkId>(bytes)),
+        16 => (
+            "collateral return",
+            decode_as::<MintedTransactionOutput>(bytes),
+        ),
+        17 => ("total collateral", decode_as::<u64>(bytes)),
+        18 => (
+            "reference inputs",
+            decode_as::<NonEmptySet<TransactionInput>>(bytes),
+        ),
+        19 => ("voting procedures", decode_as::<VotingProcedures>(bytes)),
+        20 => (
+            "proposal procedures",
+            decode_as::<NonEmptySet<ProposalProcedure>>(bytes),
+        ),
+        21 => ("treasury value", decode_as::<u64>(bytes)),
+        22 => ("donation", decode_as::<PositiveCoin>(bytes)),
+        _ => ("field", Err("unknown key".to_string())),
+    }
+}
+
+fn witness_field(key: u64, bytes: &[u8]) -> (&'static str, Result<(), String>) {
+    match key {
+        0 => (
+            "vkey witnesses",
+            decode_as::<NonEmptySet<VKeyWitness>>(bytes),
+        ),
+        1 => (
+            "native scripts",
+            decode_as::<NonEmptySet<NativeScript>>(bytes),
+        ),
+        2 => (
+            "bootstrap witnesses",
+            decode_as::<NonEmptySet<BootstrapWitness>>(bytes),
+        ),
+        3 => (
+            "plutus v1 scripts",
+            decode_as::<NonEmptySet<PlutusScript<1>>>(bytes),
+        ),
+        4 => ("plutus data", decode_as::<NonEmptySet<PlutusData>>(bytes)),
+        5 => ("redeemers", decode_as::<Redeemers>(bytes)),
+        6 => (
+            "plutus v2 scripts",
+            decode_as::<NonEmptySet<PlutusScript<2>>>(bytes),
+        ),
+        7 => (
+            "plutus v3 scripts",
+            decode_as::<NonEmptySet<PlutusScript<3>>>(bytes),
+        ),
+        _ => ("field", Err("unknown key".to_string())),
+    }
+}
+
+fn decode_as<'b, T: minicbor::Decode<'b, ()>>(bytes: &'b [u8]) -> Result<(), String> {
+    minicbor::decode::<T>(bytes)
+        .map(|_| ())
+        .map_err(|error| error.to_string())
+}
+
+/// The name of the purpose a redeemer's tag stands for
+pub fn purpose_name(tag: RedeemerTag) -> &'static str {
+    match tag {
+        RedeemerTag::Spend => "spend",
+        RedeemerTag::Mint => "mint",
+        RedeemerTag::Cert => "publish",
+        RedeemerTag::Reward => "withdraw",
+        RedeemerTag::Vote => "vote",
+        RedeemerTag::Propose => "propose",
+    }
+}
+
+/// Describes what a redeemer is for (the input it spends, the policy it mints, ...),
+/// along with the hash of the script it runs, where the transaction tells us
+pub fn redeemer_target(
+    tx: &MintedTx,
+    resolved_inputs: &[ResolvedInput],
+    redeemer: &Redeemer,
+) -> (Option<String>, Option<ScriptHash>) {
+    let body = &tx.transaction_body;
+    let index = redeemer.index as usize;
+    // the ledger writes maps in key order, which is the order redeemers index them in
+    match redeemer.tag {
+        RedeemerTag::Spend => {
+            let mut inputs: Vec<_> = body.inputs.iter().collect();
+            inputs.sort_by_key(|input| (input.transaction_id, input.index));
+            let Some(input) = inputs.get(index) else {
+                return (None, None);
+            };
+            let script_hash = resolved_inputs
+                .iter()
+                .find(|resolved| resolved.input == **input)
+                .and_then(|resolved| payment_script(&resolved.output));
+            let target = format!("{}#{}", input.transaction_id, input.index);
+            (Some(target), script_hash)
+        }
+        RedeemerTag::Mint => {
+            let mut policies: Vec<_> = body
+                .mint
+                .iter()
+                .flat_map(|mint| mint.iter().map(|(policy, _)| *policy))
+                .collect();
+            policies.sort();
+            let policy = policies.get(index).copied();
+            (policy.map(|policy| policy.to_string()), policy)
+        }
+        RedeemerTag::Cert => {
+            let certificate = body
+                .certificates
+                .iter()
+                .flat_map(|certificates| certificates.iter())
+                .nth(index);
+            (
+                certificate.map(|_| format!("certificate {}", index)),
+                certificate.and_then(certificate_script),
+            )
+        }
+        RedeemerTag::Reward => {
+            let mut accounts: Vec<_> = body
+                .withdrawals
+                .iter()
+                .flat_map(|withdrawals| withdrawals.iter().map(|(account, _)| account))
+                .collect();
+            accounts.sort_by_key(|account| reward_account_order(*account));
+            let account = accounts.get(index).copied();
+            (
+                account.map(|account| hex::encode(account.as_slice())),
+                account.and_then(|account| match Address::from_bytes(account) {
+                    Ok(Address::Stake(address)) => match address.payload() {
+                        StakePayload::Script(hash) => Some(*hash),
+                        StakePayload::Stake(_) => None,
+                    },
+                    _ => None,
+                }),
+            )
+        }
+        RedeemerTag::Vote => {
+            let mut voters: Vec<_> = body
+                .voting_procedures
+                .iter()
+                .flat_map(|procedures| procedures.iter().map(|(voter, _)| voter))
+                .collect();
+            voters.sort_by_key(|voter| voter_order(*voter));
+            let Some(voter) = voters.get(index).copied() else {
+                return (None, None);
+            };
+            match voter {
+                Voter::ConstitutionalCommitteeKey(hash) => {
+                    (Some(format!("committee member {}", hash)), None)
+                }
+                Voter::ConstitutionalCommitteeScript(hash) => {
+                    (Some(format!("committee member {}", hash)), Some(*hash))
+                }
+                Voter::DRepKey(hash) => (Some(format!("drep {}", hash)), None),
+                Voter::DRepScript(hash) => (Some(format!("drep {}", hash)), Some(*hash)),
+                Voter::StakePoolKey(hash) => (Some(format!("stake pool {}", hash)), None),
+            }
+        }
+        RedeemerTag::Propose => {
+            let proposal = body
+                .proposal_procedures
+                .iter()
+                .flat_map(|proposals| proposals.iter())
+                .nth(index);
+            let Some(proposal) = proposal else {
+                return (None, None);
+            };
+            // only these actions are checked by the constitution's guardrail script
+            let script_hash = match &proposal.gov_action {
+                GovAction::ParameterChange(_, _, Nullable::Some(hash))
+                | GovAction::TreasuryWithdrawals(_, Nullable::Some(hash)) => Some(*hash),
+                _ => None,
+            };
+            (Some(format!("proposal {}", index)), script_hash)
+        }
+    }
+}
+
+/// The ledger orders reward accounts by network, then script credentials before key ones
+fn reward_account_order(account: &RewardAccount) -> (u8, bool, &[u8]) {
+    let header = account.first().copied().unwrap_or_default();
+    let credential = account.get(1..).unwrap_or_default();
+    (header & 0x0f, header & 0x10 == 0, credential)
+}
+
+/// The ledger orders voters by role (committee members, dreps, then stake pools),
+/// then script credentials before key ones
+fn voter_order(voter: &Voter) -> (u8, bool, &[u8]) {
+    match voter {
+        Voter::ConstitutionalCommitteeScript(hash) => (0, false, &hash[..]),
+        Voter::ConstitutionalCommitteeKey(hash) => (0, true, &hash[..]),
+        Voter::DRepScript(hash) => (1, false, &hash[..]),
+        Voter::DRepKey(hash) => (1, true, &hash[..]),
+        Voter::StakePoolKey(hash) => (2, true, &hash[..]),
+    }
+}
+
+fn payment_script(output: &TransactionOutput) -> Option<ScriptHash> {
+    let address = match output {
+        TransactionOutput::Legacy(output) => &output.address,
+        TransactionOutput::PostAlonzo(output) => &output.address,
+    };
+    match Address::from_bytes(address) {
+        Ok(Address::Shelley(address)) => match address.payment() {
+            ShelleyPaymentPart::Script(hash) => Some(*hash),
+            ShelleyPaymentPart::Key(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// The script credential of a certificate, if it has one. Every kind of certificate which
+/// takes a credential names it right after its kind, so read it from the encoding.
+fn certificate_script(certificate: &Certificate) -> Option<ScriptHash> {
+    let bytes = minicbor::to_vec(certificate).ok()?;
+    let mut decoder = Decoder::new(&bytes);
+    decoder.array().ok()?;
+    decoder.u64().ok()?;
+    decoder.array().ok()?;
+    if decoder.u8().ok()? != 1 {
+        return None;
+    }
+    let hash = decoder.bytes().ok()?;
+    (hash.len() == 28).then(|| ScriptHash::from(hash))
+}
+
+/// The plutus version of the script with this hash, which the transaction either
+/// carries in its witness set or refers to from one of its inputs
+pub fn script_language(
+    tx: &MintedTx,
+    resolved_inputs: &[ResolvedInput],
+    script_hash: &ScriptHash,
+) -> Option<Language> {
+    let witness_set = &tx.transaction_witness_set;
+    let witnessed = witness_set
+        .plutus_v1_script
+        .iter()
+        .flat_map(|scripts| scripts.iter())
+        .map(|script| (Language::PlutusV1, plutus_script_hash(script)))
+        .chain(
+            witness_set
+                .plutus_v2_script
+                .iter()
+                .flat_map(|scripts| scripts.iter())
+                .map(|script| (Language::PlutusV2, plutus_script_hash(script))),
+        )
+        .chain(
+            witness_set
+                .plutus_v3_script
+                .iter()
+                .flat_map(|scripts| scripts.iter())
+                .map(|script| (Language::PlutusV3, plutus_script_hash(script))),
+        );
+    let referenced = resolved_inputs
+        .iter()
+        .filter_map(|resolved| match &resolved.output {
+            TransactionOutput::PostAlonzo(output) => output.script_ref.as_ref(),
+            TransactionOutput::Legacy(_) => None,
+        })
+        .filter_map(|script_ref| match &script_ref.0 {
+            PseudoScript::NativeScript(_) => None,
+            PseudoScript::PlutusV1Script(script) => {
+                Some((Language::PlutusV1, plutus_script_hash(script)))
+            }
+            PseudoScript::PlutusV2Script(script) => {
+                Some((Language::PlutusV2, plutus_script_hash(script)))
+            }
+            PseudoScript::PlutusV3Script(script) => {
+                Some((Language::PlutusV3, plutus_script_hash(script)))
+            }
+        });
+    witnessed
+        .chain(referenced)
+        .find(|(_, hash)| hash == script_hash)
+        .map(|(language, _)| language)
+}
+
+/// Script hashes are taken over the script, prefixed with its plutus version
+fn plutus_script_hash<const VERSION: usize>(script: &PlutusScript<VERSION>) -> ScriptHash {
+    Hasher::<224>::hash_tagged(&script.0, VERSION as u8)
+}
+
+/// Reads redeemers to evaluate a transaction with, from either
+/// - a JSON list of `{"purpose": "spend", "index": 0, "data": ..., "exUnits": {...}}`,
+///   where the data is in any of the formats parameters can be written in
+/// - the CBOR of a witness set's redeemers, as hex or raw bytes
+///
+/// Returns them encoded, ready to go in a witness set.
+pub fn read_redeemers(path: &Path) -> Result<Vec<u8>> {
+    let bytes = match read_witness_file(path)? {
+        WitnessFile::Json(json) => {
+            let redeemers: Vec<RedeemerJson> = serde_json::from_value(json)
+                .with_context(|| format!("invalid redeemers in {}", path.display()))?;
+            let mut encoder = Encoder::new(vec![]);
+            encoder.array(redeemers.len() as u64)?;
+            for (index, redeemer) in redeemers.iter().enumerate() {
+                let context = || format!("invalid redeemer {} in {}", index, path.display());
+                let tag = redeemer_tag(&redeemer.purpose).with_context(context)?;
+                let data = json_to_plutus_data(&redeemer.data).with_context(context)?;
+                encoder
+                    .array(4)?
+                    .u8(tag)?
+                    .u32(redeemer.index)?
+                    .writer_mut()
+                    .extend_from_slice(&minicbor::to_vec(&data)?);
+                encoder
+                    .array(2)?
+                    .u64(redeemer.ex_units.mem)?
+                    .u64(redeemer.ex_units.steps)?;
+            }
+            encoder.into_writer()
+        }
+        WitnessFile::Cbor(bytes) => bytes,
+    };
+    decode_as::<Redeemers>(&bytes)
+        .map_err(|e| anyhow!("invalid redeemers in {}: {}", path.display(), e))?;
+    Ok(bytes)
+}
+
+/// Reads datums to add to a transaction's witness set, from either
+/// a JSON list of datums (in any of the formats parameters can be written in),
+/// or the CBOR of a list of datums, as hex or raw bytes
+pub fn read_datums(path: &Path) -> Result<Vec<Bytes>> {
+    match read_witness_file(path)? {
+        WitnessFile::Json(json) => {
+            let Value::Array(datums) = json else {
+                bail!("invalid datums in {}, expected a list", path.display());
+            };
+            datums
+                .iter()
+                .enumerate()
+                .map(|(index, datum)| {
+                    let datum = json_to_plutus_data(datum).with_context(|| {
+                        format!("invalid datum {} in {}", index, path.display())
+                    })?;
+                    Ok(minicbor::to_vec(&datum)?.into())
+                })
+                .collect()
+        }
+        WitnessFile::Cbor(bytes) => {
+            let (datums, _) = set_items(&bytes)
+                .with_context(|| format!("invalid datums in {}", path.display()))?;
+            datums
+                .into_iter()
+                .enumerate()
+                .map(|(index, datum)| {
+                    decode_as::<PlutusData>(datum).map_err(|e| {
+                        anyhow!("invalid datum {} in {}: {}", index, path.display(), e)
+                    })?;
+                    Ok(datum.to_vec().into())
+                })
+                .collect()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RedeemerJson {
+    purpose: String,
+    index: u32,
+    data: Value,
+    /// Only checked against the actual cost, so they can be left out
+    #[serde(default)]
+    ex_units: ExUnitsJson,
+}
+
+#[derive(Deserialize, Default)]
+struct ExUnitsJson {
+    mem: u64,
+    steps: u64,
+}
+
+fn redeemer_tag(purpose: &str) -> Result<u8> {
+    Ok(match purpose {
+        "spend" => 0,
+        "mint" => 1,
+        "cert" | "publish" => 2,
+        "reward" | "withdraw" => 3,
+        "vote" => 4,
+        "propose" => 5,
+        other => bail!(
+            "unknown purpose {}, expected spend, mint, cert, reward, vote or propose",
+            other
+        ),
+    })
+}
+
+fn json_to_plutus_data(json: &Value) -> Result<PlutusData> {
+    match json {
+        Value::String(data) => plutus_data::parse(data),
+        json => plutus_data::from_json(json),
+    }
+}
+
+enum WitnessFile {
+    Json(Value),
+    Cbor(Vec<u8>),
+}
+
+fn read_witness_file(path: &Path) -> Result<WitnessFile> {
+    let bytes = fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+    let Ok(text) = std::str::from_utf8(&bytes).map(str::trim) else {
+        return Ok(WitnessFile::Cbor(bytes));
+    };
+    if text.starts_with('[') || text.starts_with('{') {
+        let json = serde_json::from_str(text)
+            .with_context(|| format!("invalid JSON in {}", path.display()))?;
+        return Ok(WitnessFile::Json(json));
+    }
+    // binary files are sometimes valid UTF-8 by chance
+    Ok(WitnessFile::Cbor(hex::decode(text).unwrap_or(bytes)))
+}
+
+/// Finds the datums needed to spend script-locked inputs which only carry a datum hash,
+/// and which aren't already in the transaction's witness set.
+/// Returns the original CBOR of each datum, so their hashes are preserved.
+pub async fn find_missing_datums(
+    tx: &MintedTx<'_>,
+    resolved_inputs: &[ResolvedInput],
+    query: &dyn ChainQuery,
+) -> Result<Vec<Bytes>> {
+    let mut known: HashSet<Hash<32>> = tx
+        .transaction_witness_set
+        .plutus_data
+        .iter()
+        .flat_map(|datums| datums.iter())
+        .map(|datum| Hasher::<256>::hash(datum.raw_cbor()))
+        .collect();
+
+    let mut datums = vec![];
+    for input in tx.transaction_body.inputs.iter() {
+        let Some(resolved) = resolved_inputs.iter().find(|r| &r.input == input) else {
+            continue;
+        };
+        let Some(datum_hash) = script_datum_hash(&resolved.output) else {
+            continue;
+        };
+        if known.contains(&datum_hash) {
+            continue;
+        }
+        let Some(datum) = query.get_datum(datum_hash).await? else {
+            bail!(
+                "could not find datum {} for input {}#{}, \
+                it's neither in the transaction's witnesses nor known to the chain provider",
+                datum_hash,
+                input.transaction_id,
+                input.index
+            );
+        };
+        if Hasher::<256>::hash(&datum) != datum_hash {
+            bail!(
+                "the chain provider returned the wrong datum for hash {} (input {}#{})",
+                datum_hash,
+                input.transaction_id,
+                input.index
+            );
+        }
+        known.insert(datum_hash);
+        datums.push(datum);
+    }
+    Ok(datums)
+}
+
+/// The datum hash of an output locked by a script, if its datum isn't inline
+fn script_datum_hash(output: &TransactionOutput) -> Option<Hash<32>> {
+    let (address, datum_hash) = match output {
+        TransactionOutput::Legacy(output) => (&output.address, output.datum_hash?),
+        TransactionOutput::PostAlonzo(output) => match &output.datum_option {
+            Some(DatumOption::Hash(hash)) => (&output.address, *hash),
+            _ => return None,
+        },
+    };
+    match Address::from_bytes(address) {
+        Ok(Address::Shelley(address)) if address.payment().is_script() => Some(datum_hash),
+        _ => None,
+    }
+}
+
+/// Returns a copy of the transaction with extra datums in its witness set.
+/// Everything else is copied byte for byte, so the transaction id and the hashes
+/// of the existing witnesses stay the same.
+pub fn add_datums(tx_bytes: &[u8], datums: &[Bytes]) -> Result<Vec<u8>> {
+    edit_witness_set(tx_bytes, |entries| {
+        match entries
+            .iter_mut()
+            .find(|(key, _)| *key == WITNESS_PLUTUS_DATA)
+        {
+            Some((_, value)) => *value = append_to_set(value, datums)?,
+            None => entries.push((WITNESS_PLUTUS_DATA, append_to_set(&[], datums)?)),
+        }
+        Ok(())
+    })
+}
+
+/// Returns a copy of the transaction with its redeemers replaced by these (already encoded) ones
+pub fn set_redeemers(tx_bytes: &[u8], redeemers: &[u8]) -> Result<Vec<u8>> {
+    edit_witness_set(tx_bytes, |entries| {
+        entries.retain(|(key, _)| *key != WITNESS_REDEEMERS);
+        entries.push((WITNESS_REDEEMERS, redeemers.to_vec()));
+        Ok(())
+    })
+}
+
+/// Rewrites the witness set's entries, copying the rest of the transaction as is
+fn edit_witness_set(
+    tx_bytes: &[u8],
+    edit: impl FnOnce(&mut Vec<(u64, Vec<u8>)>) -> Result<()>,
+) -> Result<Vec<u8>> {
+    let mut decoder = Decoder::new(tx_bytes);
+    decoder.array()?;
+    skip(&mut decoder)?;
+    let witness_set = skip(&mut decoder)?;
+
+    let witness_bytes = &tx_bytes[witness_set.clone()];
+    let mut entries = vec![];
+    let mut witness_decoder = Decoder::new(witness_bytes);
+    let len = witness_decoder.map()?;
+    while len.is_none_or(|len| entries.len() < len as usize) {
+        if len.is_none() && witness_decoder.datatype()? == Type::Break {
+            break;
+        }
+        let key = witness_decoder.u64()?;
+        let value = skip(&mut witness_decoder)?;
+        entries.push((key, witness_bytes[value].to_vec()));
+    }
+
+    edit(&mut entries)?;
+    entries.sort_by_key(|(key, _)| *key);
+
+    let mut encoder = Encoder::new(vec![]);
+    encoder.map(entries.len() as u64)?;
+    for (key, value) in entries {
+        encoder.u64(key)?;
+        encoder.writer_mut().extend_from_slice(&value);
+    }
+
+    let mut patched = tx_bytes[..witness_set.start].to_vec();
+    patched.extend_from_slice(&encoder.into_writer());
+    patched.extend_from_slice(&tx_bytes[witness_set.end..]);
+    Ok(patched)
+}
+
+/// Appends raw items to an encoded set (or plain array) of witnesses
+fn append_to_set(set: &[u8], items: &[Bytes]) -> Result<Vec<u8>> {
+    let (existing, tagged) = if set.is_empty() {
+        (vec![], true)
+    } else {
+        set_items(set)?
+    };
+
+    let mut encoder = Encoder::new(vec![]);
+    if tagged {
+        encoder.tag(Tag::new(SET_TAG))?;
+    }
+    encoder.array((existing.len() + items.len()) as u64)?;
+    for item in existing {
+        encoder.writer_mut().extend_from_slice(item);
+    }
+    for item in items {
+        encoder.writer_mut().extend_from_slice(item);
+    }
+    Ok(encoder.into_writer())
+}
+
+/// The raw items of an encoded set (or plain array), and whether it was tagged as a set
+fn set_items(set: &[u8]) -> Result<(Vec<&[u8]>, bool)> {
+    let mut items = vec![];
+    let mut decoder = Decoder::new(set);
+    let tagged = decoder.datatype()? == Type::Tag;
+    if tagged {
+        decoder.tag()?;
+    }
+    let len = decoder.array()?;
+    while len.is_none_or(|len| items.len() < len as usize) {
+        if len.is_none() && decoder.datatype()? == Type::Break {
+            break;
+        }
+        let item = skip(&mut decoder)?;
+        items.push(&set[item]);
+    }
+    Ok((items, tagged))
+}
+
+/// Skips the next CBOR item, returning where it was
+fn skip(decoder: &mut Decoder) -> Result<Range<usize>> {
+    let start = decoder.position();
+    decoder.skip()?;
+    Ok(start..decoder.position())
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::ledger::primitives::conway::ExUnits;
+
+    use super::*;
+    use crate::chain_query::MockChainQuery;
+
+    const KEY_HASH: [u8; 28] = [1; 28];
+    const SCRIPT_HASH: [u8; 28] = [2; 28];
+
+    /// A transaction which withdraws from and votes with a key and a script,
+    /// listing the key's entries first, as hash order would
+    fn governance_tx() -> Result<Vec<u8>> {
+        let mut encoder = Encoder::new(vec![]);
+        encoder.array(4)?;
+        encoder.map(5)?;
+        encoder.u8(0)?.array(0)?;
+        encoder.u8(1)?.array(0)?;
+        encoder.u8(2)?.u64(200_000)?;
+        encoder.u8(5)?.map(2)?;
+        for (header, hash) in [(0xe1, KEY_HASH), (0xf1, SCRIPT_HASH)] {
+            encoder.bytes(&[&[header][..], &hash[..]].concat())?.u8(0)?;
+        }
+        encoder.u8(19)?.map(2)?;
+        for (kind, hash) in [(2, KEY_HASH), (1, SCRIPT_HASH)] {
+            encoder.array(2)?.u8(kind)?.bytes(&hash)?;
+            encoder.map(1)?.array(2)?.bytes(&[3; 32])?.u8(0)?;
+            encoder.array(2)?.u8(1)?.null()?;
+        }
+        encoder.map(0)?.bool(true)?.null()?;
+        Ok(encoder.into_writer())
+    }
+
+    fn redeemer(tag: RedeemerTag, index: u32) -> Result<Redeemer> {
+        Ok(Redeemer {
+            tag,
+            index,
+            data: minicbor::decode(&[0xd8, 0x79, 0x80])?,
+            ex_units: ExUnits { mem: 0, steps: 0 },
+        })
+    }
+
+    /// A transaction body which spends and pays nothing
+    fn body(fee: u64) -> Result<Vec<u8>> {
+        let mut encoder = Encoder::new(vec![]);
+        encoder.map(3)?;
+        encoder.u8(0)?.array(0)?;
+        encoder.u8(1)?.array(0)?;
+        encoder.u8(2)?.u64(fee)?;
+        Ok(encoder.into_writer())
+    }
+
+    fn array(items: &[&[u8]]) -> Result<Vec<u8>> {
+        let mut encoder = Encoder::new(vec![]);
+        encoder.array(items.len() as u64)?;
+        for item in items {
+            encoder.writer_mut().extend_from_slice(item);
+        }
+        Ok(encoder.into_writer())
+    }
+
+    fn witness_set(entries: &[(u64, &[u8])]) -> Result<Vec<u8>> {
+        let mut encoder = Encoder::new(vec![]);
+        encoder.map(entries.len() as u64)?;
+        for (key, value) in entries {
+            encoder.u64(*key)?.writer_mut().extend_from_slice(value);
+        }
+        Ok(encoder.into_writer())
+    }
+
+    fn tagged_set(items: &[&[u8]]) -> Result<Vec<u8>> {
+        Ok([&[0xd9, 0x01, 0x02][..], &array(items)?].concat())
+    }
+
+    fn error(tx_bytes: &[u8]) -> String {
+        decode(tx_bytes)
+            .err()
+            .map(|e| e.to_string())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn completes_drafts() -> Result<()> {
+        let body = body(200_000)?;
+        let datums = witness_set(&[(WITNESS_PLUTUS_DATA, &tagged_set(&[&[0x01]])?)])?;
+        let complete_tx = array(&[&body, &[0xa0], &[0xf5], &[0xf6]])?;
+
+        // a bare body, and one without a witness set
+        assert_eq!(complete(&body)?, complete_tx);
+        assert_eq!(complete(&array(&[&body])?)?, complete_tx);
+        assert_eq!(
+            complete(&array(&[&body, &datums])?)?,
+            array(&[&body, &datums, &[0xf5], &[0xf6]])?
+        );
+        // from before Alonzo, without the validity flag
+        assert_eq!(
+            complete(&array(&[&body, &datums, &[0xf6]])?)?,
+            array(&[&body, &datums, &[0xf5], &[0xf6]])?
+        );
+        assert_eq!(complete(&complete_tx)?, complete_tx);
+
+        let completed = complete(&body)?;
+        let tx = decode(&completed)?;
+        assert_eq!(tx.transaction_body.fee, 200_000);
+        assert!(tx.success);
+        Ok(())
+    }
+
+    #[test]
+    fn merges_redeemers_and_datums_into_the_witness_set() -> Result<()> {
+        let body = body(200_000)?;
+        let datum: &[u8] = &[0xd8, 0x79, 0x80];
+        let redeemers = |tag: u8, data: &[u8]| -> Result<Vec<u8>> {
+            let mut encoder = Encoder::new(vec![]);
+            encoder.array(1)?.array(4)?.u8(tag)?.u8(0)?;
+            encoder.writer_mut().extend_from_slice(data);
+            encoder.array(2)?.u8(1)?.u8(1)?;
+            Ok(encoder.into_writer())
+        };
+        let old_redeemers = redeemers(0, datum)?;
+        let new_redeemers = redeemers(1, &[0x01])?;
+        // listed out of key order, which the patched witness set puts right
+        let vkey_witnesses = tagged_set(&[&array(&[&[0x41, 0x01], &[0x41, 0x02]])?])?;
+        let witnesses = witness_set(&[
+            (WITNESS_REDEEMERS, &old_redeemers),
+            (WITNESS_PLUTUS_DATA, &tagged_set(&[datum])?),
+            (0, &vkey_witnesses),
+        ])?;
+        let tx = array(&[&body, &witnesses, &[0xf5], &[0xf6]])?;
+
+        let patched = add_datums(&tx, &[Bytes::from(vec![0x01])])?;
+        let patched = set_redeemers(&patched, &new_redeemers)?;
+        let expected = witness_set(&[
+            (0, &vkey_witnesses),
+            (WITNESS_PLUTUS_DATA, &tagged_set(&[datum, &[0x01]])?),
+            (WITNESS_REDEEMERS, &new_redeemers),
+        ])?;
+        assert_eq!(patched, array(&[&body, &expected, &[0xf5], &[0xf6]])?);
+
+        // a draft without a witness set gets one
+        let patched = add_datums(&complete(&body)?, &[Bytes::from(vec![0x01])])?;
+        let patched = set_redeemers(&patched, &new_redeemers)?;
+        let expected = witness_set(&[
+            (WITNESS_PLUTUS_DATA, &tagged_set(&[&[0x01]])?),
+            (WITNESS_REDEEMERS, &new_redeemers),
+        ])?;
+        assert_eq!(patched, array(&[&body, &expected, &[0xf5], &[0xf6]])?);
+        decode(&patched)?;
+        Ok(())
+    }
+
+    #[test]
+    fn names_the_field_which_doesnt_decode() -> Result<()> {
+        let mut encoder = Encoder::new(vec![]);
+        encoder.map(3)?;
+        encoder.u8(0)?.array(0)?;
+        encoder.u8(1)?.array(0)?;
+        encoder.u8(2)?.str("lots")?;
+        let bad_fee = encoder.into_writer();
+        assert!(error(&complete(&bad_fee)?).starts_with(
+            "could not decode the transaction: invalid fee in the transaction body (key 2): "
+        ));
+
+        let bad_redeemers = witness_set(&[(WITNESS_REDEEMERS, &[0x61, 0x78])])?;
+        let tx = array(&[&body(200_000)?, &bad_redeemers, &[0xf5], &[0xf6]])?;
+        assert!(error(&tx).starts_with(
+            "could not decode the transaction: invalid redeemers in the witness set (key 5): "
+        ));
+
+        let tx = array(&[&body(200_000)?, &[0xa0], &[0x01], &[0xf6]])?;
+        assert_eq!(
+            error(&tx),
+            "could not decode the transaction: invalid validity flag, expected a boolean"
+        );
+        Ok(())
+    }
+
+    /// A mainnet enterprise address paying to `SCRIPT_HASH`
+    fn script_address() -> Vec<u8> {
+        [&[0x71][..], &SCRIPT_HASH[..]].concat()
+    }
+
+    /// An output in the array form every era before Babbage wrote
+    fn legacy_output_bytes(datum_hash: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = Encoder::new(vec![]);
+        encoder.array(3)?.bytes(&script_address())?.u64(2_000_000)?;
+        encoder.bytes(datum_hash)?;
+        Ok(encoder.into_writer())
+    }
+
+    /// A transaction paying to these outputs, with these extra body fields and witnesses
+    fn era_tx(
+        outputs: &[&[u8]],
+        body_fields: &[(u64, &[u8])],
+        witnesses: &[(u64, &[u8])],
+    ) -> Result<Vec<u8>> {
+        let outputs = array(outputs)?;
+        let fee = [0x1a, 0x00, 0x03, 0x0d, 0x40];
+        let mut fields: Vec<(u64, &[u8])> =
+            vec![(0, &[0x80][..]), (1, &outputs[..]), (2, &fee[..])];
+        fields.extend_from_slice(body_fields);
+        array(&[
+            &witness_set(&fields)?,
+            &witness_set(witnesses)?,
+            &[0xf5],
+            &[0xf6],
+        ])
+    }
+
+    #[test]
+    fn detects_eras() -> Result<()> {
+        let script: &[u8] = &array(&[&[0x41, 0x01]])?;
+        let output = legacy_output_bytes(&[4; 32])?;
+
+        // a legacy output and a PlutusV1 script were all Alonzo had
+        let alonzo = era_tx(&[&output], &[], &[(3, script)])?;
+        assert_eq!(detect_era(&alonzo)?, Era::Alonzo);
+
+        // outputs written as maps, with inline datums
+        let mut encoder = Encoder::new(vec![]);
+        encoder
+            .map(3)?
+            .u8(0)?
+            .bytes(&script_address())?
+            .u8(1)?
+            .u64(2_000_000)?;
+        encoder.u8(2)?.array(2)?.u8(1)?.tag(Tag::new(24))?;
+        encoder.bytes(&[0xd8, 0x79, 0x80])?;
+        let babbage_output = encoder.into_writer();
+        let babbage = era_tx(&[&babbage_output], &[], &[(6, script)])?;
+        assert_eq!(detect_era(&babbage)?, Era::Babbage);
+
+        // reference inputs came with Babbage too, whatever the outputs look like
+        let mut encoder = Encoder::new(vec![]);
+        encoder.array(1)?.array(2)?.bytes(&[5; 32])?.u8(0)?;
+        let reference_inputs = encoder.into_writer();
+        let babbage = era_tx(&[&output], &[(18, &reference_inputs)], &[(3, script)])?;
+        assert_eq!(detect_era(&babbage)?, Era::Babbage);
+
+        // sets tagged as such only appeared in Conway
+        let tagged_script = tagged_set(&[&[0x41, 0x01]])?;
+        let conway = era_tx(&[&output], &[], &[(3, &tagged_script)])?;
+        assert_eq!(detect_era(&conway)?, Era::Conway);
+        assert_eq!(detect_era(&governance_tx()?)?, Era::Conway);
+        Ok(())
+    }
+
+    #[test]
+    fn takes_transactions_which_only_use_older_features_for_the_oldest_era() -> Result<()> {
+        // a Babbage transaction which only uses what Alonzo had is encoded just like an
+        // Alonzo one, so it can't be told apart, and is evaluated as one. That only matters
+        // for the script context of PlutusV2 scripts, which Alonzo couldn't run anyway.
+        let script: &[u8] = &array(&[&[0x41, 0x01]])?;
+        let output = legacy_output_bytes(&[4; 32])?;
+        let babbage = era_tx(&[&output], &[], &[(3, script)])?;
+        assert_eq!(detect_era(&babbage)?, Era::Alonzo);
+        assert!(check_language(Era::Alonzo, Language::PlutusV1).is_ok());
+        assert!(check_language(Era::Alonzo, Language::PlutusV2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn turns_outputs_back_into_their_legacy_form() -> Result<()> {
+        let datum_hash = [4; 32];
+        let legacy_bytes = legacy_output_bytes(&datum_hash)?;
+
+        // chain providers describe it like any other output
+        let mut encoder = Encoder::new(vec![]);
+        encoder
+            .map(3)?
+            .u8(0)?
+            .bytes(&script_address())?
+            .u8(1)?
+            .u64(2_000_000)?;
+        encoder.u8(2)?.array(2)?.u8(0)?.bytes(&datum_hash)?;
+        let described: TransactionOutput = minicbor::decode(&encoder.into_writer())?;
+
+        let output = legacy_output(&described)?;
+        let TransactionOutput::Legacy(legacy) = &output else {
+            panic!("expected a legacy output, got {:?}", output);
+        };
+        assert_eq!(legacy.address.to_vec(), script_address());
+        assert_eq!(legacy.datum_hash, Some(Hash::new(datum_hash)));
+        assert_eq!(minicbor::to_vec(&output)?, legacy_bytes);
+        // and outputs already in that form are left alone
+        assert_eq!(minicbor::to_vec(legacy_output(&output)?)?, legacy_bytes);
+
+        let mut encoder = Encoder::new(vec![]);
+        encoder
+            .map(3)?
+            .u8(0)?
+            .bytes(&script_address())?
+            .u8(1)?
+            .u64(2_000_000)?;
+        encoder.u8(2)?.array(2)?.u8(1)?.tag(Tag::new(24))?;
+        encoder.bytes(&[0xd8, 0x79, 0x80])?;
+        let inline: TransactionOutput = minicbor::decode(&encoder.into_writer())?;
+        let error = legacy_output(&inline).err().map(|e| e.to_string());
+        assert_eq!(
+            error.as_deref(),
+            Some("outputs couldn't hold inline datums before Babbage")
+        );
+        Ok(())
+    }
+
+    /// An output to this address, with a datum hash (kind 0) or an inline datum (kind 1)
+    fn output_with_datum(address: &[u8], kind: u8, datum: &[u8]) -> Result<TransactionOutput> {
+        let mut encoder = Encoder::new(vec![]);
+        encoder
+            .map(3)?
+            .u8(0)?
+            .bytes(address)?
+            .u8(1)?
+            .u64(2_000_000)?;
+        encoder.u8(2)?.array(2)?.u8(kind)?;
+        if kind == 1 {
+            encoder.tag(Tag::new(24))?;
+        }
+        encoder.bytes(datum)?;
+        Ok(minicbor::decode(&encoder.into_writer())?)
+    }
+
+    /// A transaction spending inputs 0 to 3 of one transaction, which witnesses this datum
+    fn spending_tx(datum: &[u8]) -> Result<(Vec<u8>, Vec<TransactionInput>)> {
+        let inputs: Vec<_> = (0..4)
+            .map(|index| TransactionInput {
+                transaction_id: Hash::new([9; 32]),
+                index,
+            })
+            .collect();
+        let mut fields = Encoder::new(vec![]);
+        fields.map(3)?;
+        fields.u8(0)?.encode(&inputs)?;
+        fields.u8(1)?.array(0)?;
+        fields.u8(2)?.u64(200_000)?;
+        let witnesses = witness_set(&[(WITNESS_PLUTUS_DATA, &tagged_set(&[datum])?)])?;
+        let tx = array(&[&fields.into_writer(), &witnesses, &[0xf5], &[0xf6]])?;
+        Ok((tx, inputs))
+    }
+
+    #[tokio::test]
+    async fn finds_the_datums_missing_from_the_witness_set() -> Result<()> {
+        let witnessed: &[u8] = &[0xd8, 0x79, 0x80];
+        let fetched: &[u8] = &[0xd8, 0x7a, 0x80];
+        let (tx_bytes, inputs) = spending_tx(witnessed)?;
+        let tx = decode(&tx_bytes)?;
+        let key_address = [&[0x61][..], &KEY_HASH[..]].concat();
+        let outputs = [
+            // its datum is in the witness set already
+            output_with_datum(&script_address(), 0, &Hasher::<256>::hash(witnessed)[..])?,
+            // inline datums don't need witnessing
+            output_with_datum(&script_address(), 1, &[0x01])?,
+            // this one has to come from the chain provider
+            output_with_datum(&script_address(), 0, &Hasher::<256>::hash(fetched)[..])?,
+            // and spending from a key needs no datum at all
+            output_with_datum(&key_address, 0, &[4; 32])?,
+        ];
+        let resolved_inputs: Vec<_> = inputs
+            .iter()
+            .zip(outputs)
+            .map(|(input, output)| ResolvedInput {
+                input: input.clone(),
+                output,
+            })
+            .collect();
+
+        let query = MockChainQuery::new().with_datum(fetched.to_vec());
+        let datums = find_missing_datums(&tx, &resolved_inputs, &query).await?;
+        assert_eq!(datums, vec![Bytes::from(fetched.to_vec())]);
+
+        let error = find_missing_datums(&tx, &resolved_inputs, &MockChainQuery::new())
+            .await
+            .err()
+            .map(|e| e.to_string());
+        let expected = format!(
+            "could not find datum {} for input {}#2, \
+            it's neither in the transaction's witnesses nor known to the chain provider",
+            Hasher::<256>::hash(fetched),
+            Hash::<32>::new([9; 32])
+        );
+        assert_eq!(error, Some(expected));
+        Ok(())
+    }
+
+    #[test]
+    fn orders_withdrawals_and_voters_like_the_ledger() -> Result<()> {
+        let tx_bytes = governance_tx()?;
+        let tx = decode(&tx_bytes)?;
+        let script_hash = Hash::new(SCRIPT_HASH);
+
+        let (target, hash) = redeemer_target(&tx, &[], &redeemer(RedeemerTag::Reward, 0)?);
+        let account = [&[0xf1][..], &SCRIPT_HASH[..]].concat();
+        assert_eq!(target, Some(hex::encode(account)));
+        assert_eq!(hash, Some(script_hash));
+
+        let (target, hash) = redeemer_target(&tx, &[], &redeemer(RedeemerTag::Vote, 0)?);
+        assert_eq!(target, Some(format!("committee member {}", script_hash)));
+        assert_eq!(hash, Some(script_hash));
+
+        let (target, hash) = redeemer_target(&tx, &[], &redeemer(RedeemerTag::Vote, 1)?);
+        assert_eq!(target, Some(format!("drep {}", Hash::<28>::new(KEY_HASH))));
+        assert_eq!(hash, None);
+        Ok(())
+    }
+}
@@ -1,60 +1,323 @@
 use std::{
     collections::{BTreeMap, HashMap},
-    ffi::OsStr,
-    fs,
-    path::Path,
+    fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
-use anyhow::{Context, Result, anyhow};
-use minicbor::bytes::ByteVec;
-use pallas::ledger::{
-    addresses::ScriptHash,
-    primitives::conway::{Language, MintedTx},
-};
-use serde::Deserialize;
+use anyhow::{Context, Result, anyhow, bail};
+use minicbor::{bytes::ByteVec, data::Type};
+pub use pallas::ledger::primitives::conway::Language;
+use pallas::ledger::{addresses::ScriptHash, traverse::Era};
+use serde::{Deserialize, Serialize};
 pub use uplc::ast::Program;
+pub use uplc::machine::cost_model::{CostModel, ExBudget};
 use uplc::{
     Fragment, PlutusData,
-    ast::{DeBruijn, FakeNamedDeBruijn, Name, NamedDeBruijn},
-    machine::{
-        Machine, MachineState,
-        cost_model::{CostModel, ExBudget},
-        indexed_term::IndexedTerm,
-    },
+    ast::{Constant, DeBruijn, FakeNamedDeBruijn, Name, NamedDeBruijn, Term},
+    machine::{Machine, MachineState},
     parser,
     tx::{script_context::PlutusScript, tx_to_programs},
 };
 
-use crate::chain_query::ChainQuery;
+use crate::{
+    aiken,
+    blueprint::{Blueprint, ParameterSchema, Validator},
+    chain_query::ChainQuery,
+    execution_trace::{ExUnits, ExecutionError, ExecutionErrorKind, ExecutionOutcome, TraceLog},
+    plutus_data,
+    protocol_parameters::ProtocolParameters,
+    source_map::{self, SourceLocation},
+    transaction,
+};
 
 pub struct LoadedProgram {
     pub filename: String,
     pub program: Program<NamedDeBruijn>,
-    pub source_map: BTreeMap<u64, String>,
+    pub source_map: BTreeMap<u64, SourceLocation>,
+    /// The plutus version the program runs as, which decides its builtins and costs
+    pub language: Language,
+    /// Whether the program is a validator, which from PlutusV3 on also has to return unit
+    pub is_validator: bool,
+    pub cost_model: CostModel,
+    pub budget: ExBudget,
+    /// The parameters the program still expects, when a blueprint describes them
+    pub parameters: Vec<ParameterSchema>,
+    /// Where the program's sources are, when it was compiled from an aiken project
+    pub source_root: Option<PathBuf>,
+    /// The redeemer the program runs, when it comes from a transaction
+    pub redeemer: Option<RedeemerInfo>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedeemerInfo {
+    /// The redeemer's tag, named for its purpose: spend, mint, publish, withdraw, vote or propose
+    pub purpose: String,
+    pub index: u32,
+    /// What the redeemer is for, e.g. the input it spends or the policy it mints
+    pub target: Option<String>,
+    pub script_hash: Option<String>,
+    /// The ex-units the transaction set aside for the redeemer
+    pub declared: ExUnits,
+}
+
+impl fmt::Display for RedeemerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} #{}", self.purpose, self.index)?;
+        if let Some(target) = &self.target {
+            write!(f, " ({})", target)?;
+        }
+        Ok(())
+    }
+}
+
+/// Settings which change how the programs in a file are loaded
+#[derive(Default)]
+pub struct LoadOptions {
+    /// Scripts to run in place of the ones a transaction refers to
+    pub script_overrides: HashMap<ScriptHash, PlutusScript>,
+    /// Evaluate transactions with these, instead of the provider's current ones
+    pub protocol_parameters: Option<ProtocolParameters>,
+    /// The title of the validator to load from a blueprint
+    pub validator: Option<String>,
+    /// A file of redeemers to evaluate transactions with, in place of their own
+    pub redeemers: Option<PathBuf>,
+    /// A file of datums to add to transactions' witness sets
+    pub datums: Option<PathBuf>,
+    /// Read the file as this format, instead of working it out from its contents
+    pub format: Option<FileFormat>,
+    /// A source map for the script, for toolchains which don't embed one
+    pub source_map: Option<PathBuf>,
+    /// The budget to start programs with
+    pub budget: StartingBudget,
+}
+
+/// The budget a program starts running with, and fails once it has spent
+#[derive(Clone, Copy, Debug, Default)]
+pub enum StartingBudget {
+    /// The protocol's per-transaction maximum, or the machine's default for lone scripts
+    #[default]
+    Max,
+    /// The ex-units each redeemer declares, so programs run out of budget where the ledger's would
+    Declared,
+    Custom(ExBudget),
+}
+
+/// Parses `max`, `declared`, or a custom budget written as `STEPS,MEM`
+impl FromStr for StartingBudget {
+    type Err = String;
+
+    fn from_str(budget: &str) -> Result<Self, Self::Err> {
+        match budget {
+            "max" => Ok(Self::Max),
+            "declared" => Ok(Self::Declared),
+            custom => {
+                let invalid = || {
+                    format!(
+                        "invalid budget {}, expected max, declared or STEPS,MEM",
+                        custom
+                    )
+                };
+                let (steps, mem) = custom.split_once(',').ok_or_else(invalid)?;
+                Ok(Self::Custom(ExBudget {
+                    cpu: steps.trim().parse().map_err(|_| invalid())?,
+                    mem: mem.trim().parse().map_err(|_| invalid())?,
+                }))
+            }
+        }
+    }
+}
+
+/// Scripts which don't say which plutus version they're for run as the latest one
+const DEFAULT_LANGUAGE: Language = Language::PlutusV3;
+
+/// What a file holds, worked out from its contents rather than its extension
+enum FileContents {
+    Uplc(String),
+    /// A flat-encoded program, with its plutus version if the file names it
+    Flat(Vec<u8>, Language),
+    AikenExport(AikenExport),
+    Blueprint(Blueprint),
+    AikenProject(PathBuf),
+    /// A transaction, with its era if the file names it
+    Transaction(Vec<u8>, Option<Era>),
+    TransactionId(Vec<u8>),
 }
 
-enum FileType {
+/// A file format to use instead of working it out from the file's contents
+#[derive(Clone, Copy, Debug)]
+pub enum FileFormat {
     Uplc,
     Flat,
-    Json,
     Transaction,
-    TransactionId,
+    /// A blueprint, an Aiken export or a cardano-cli text envelope
+    Json,
 }
 
-fn identify_file_type(file: &Path) -> Result<FileType> {
-    if let Some(path) = file.to_str() {
-        if path.len() == 64 && hex::decode(path).is_ok() {
-            return Ok(FileType::TransactionId);
+impl FromStr for FileFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "uplc" => Ok(Self::Uplc),
+            "flat" => Ok(Self::Flat),
+            "tx" => Ok(Self::Transaction),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown format {}, expected uplc, flat, tx or json",
+                other
+            )),
+        }
+    }
+}
+
+/// Reads a file, or stdin when the file is `-`
+fn read_file(file: &Path) -> Result<Vec<u8>> {
+    if file == Path::new("-") {
+        let mut bytes = vec![];
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .context("could not read stdin")?;
+        return Ok(bytes);
+    }
+    fs::read(file).with_context(|| format!("could not read {}", file.display()))
+}
+
+fn identify_file_contents(file: &Path, format: Option<FileFormat>) -> Result<FileContents> {
+    if let Some(path) = file.to_str()
+        && path.len() == 64
+        && !file.exists()
+        && let Ok(tx_id) = hex::decode(path)
+    {
+        return Ok(FileContents::TransactionId(tx_id));
+    }
+    if file.is_dir() {
+        if !aiken::is_project(file) {
+            bail!(
+                "{} is a directory, but not an aiken project",
+                file.display()
+            );
         }
+        return Ok(FileContents::AikenProject(file.to_path_buf()));
+    }
+    let bytes = read_file(file)?;
+    let text = std::str::from_utf8(&bytes).ok().map(str::trim);
+    if let Some(format) = format {
+        return read_as(format, &bytes, text);
+    }
+    let Some(text) = text else {
+        return identify_binary(bytes);
+    };
+    if text.starts_with('{') {
+        return identify_json(text);
+    }
+    if let Ok(bytes) = hex::decode(text) {
+        return identify_binary(bytes);
     }
-    let extension = file.extension().and_then(OsStr::to_str);
-    match extension {
-        Some("uplc") => Ok(FileType::Uplc),
-        Some("flat") => Ok(FileType::Flat),
-        Some("json") => Ok(FileType::Json),
-        Some("tx") => Ok(FileType::Transaction),
-        _ => Err(anyhow!("That extension is not supported.")),
+    if text.starts_with('(') {
+        return Ok(FileContents::Uplc(text.to_string()));
     }
+    // binary files are sometimes valid UTF-8 by chance
+    identify_binary(bytes)
+}
+
+fn read_as(format: FileFormat, bytes: &[u8], text: Option<&str>) -> Result<FileContents> {
+    // binary formats may be hex-encoded too
+    let binary = || match text.map(hex::decode) {
+        Some(Ok(decoded)) => decoded,
+        _ => bytes.to_vec(),
+    };
+    match format {
+        FileFormat::Uplc => {
+            let text = text.context("UPLC programs must be UTF-8 text")?;
+            Ok(FileContents::Uplc(text.to_string()))
+        }
+        FileFormat::Flat => Ok(FileContents::Flat(
+            unwrap_bytestrings(binary())?,
+            DEFAULT_LANGUAGE,
+        )),
+        FileFormat::Transaction => Ok(FileContents::Transaction(binary(), None)),
+        FileFormat::Json => identify_json(text.context("JSON files must be UTF-8 text")?),
+    }
+}
+
+fn identify_json(text: &str) -> Result<FileContents> {
+    let json: serde_json::Value = serde_json::from_str(text)?;
+    if Blueprint::is_blueprint(&json) {
+        return Ok(FileContents::Blueprint(serde_json::from_value(json)?));
+    }
+    if json.get("compiledCode").is_some() {
+        return Ok(FileContents::AikenExport(serde_json::from_value(json)?));
+    }
+    let envelope: TextEnvelope = serde_json::from_value(json).map_err(|_| {
+        anyhow!(
+            "unrecognized JSON file, \
+            expected a blueprint, an Aiken export or a cardano-cli text envelope"
+        )
+    })?;
+    let bytes = hex::decode(&envelope.cbor_hex)
+        .with_context(|| format!("invalid cborHex in {} envelope", envelope.envelope_type))?;
+    let envelope_type = envelope.envelope_type.as_str();
+    if let Some(version) = envelope_type.strip_prefix("PlutusScriptV") {
+        let language = match version {
+            "1" => Language::PlutusV1,
+            "2" => Language::PlutusV2,
+            "3" => Language::PlutusV3,
+            _ => bail!("unsupported plutus version in {} envelope", envelope_type),
+        };
+        Ok(FileContents::Flat(unwrap_bytestrings(bytes)?, language))
+    } else if envelope_type.starts_with("Tx ")
+        || envelope_type.starts_with("Unwitnessed Tx ")
+        || envelope_type.starts_with("Witnessed Tx ")
+        || envelope_type.starts_with("TxBody")
+    {
+        // e.g. `Tx ConwayEra` or `TxBodyBabbage`
+        let era = [
+            ("Alonzo", Era::Alonzo),
+            ("Babbage", Era::Babbage),
+            ("Conway", Era::Conway),
+        ]
+        .into_iter()
+        .find(|(name, _)| envelope_type.contains(name))
+        .map(|(_, era)| era);
+        Ok(FileContents::Transaction(bytes, era))
+    } else {
+        Err(anyhow!(
+            "unsupported text envelope type \"{}\", expected a plutus script or a transaction",
+            envelope_type
+        ))
+    }
+}
+
+fn identify_binary(bytes: Vec<u8>) -> Result<FileContents> {
+    match minicbor::Decoder::new(&bytes).datatype() {
+        // transactions are CBOR arrays (or maps, for bare transaction bodies),
+        // scripts are flat-encoded programs wrapped in bytestrings
+        Ok(Type::Array | Type::ArrayIndef | Type::Map | Type::MapIndef) => {
+            Ok(FileContents::Transaction(bytes, None))
+        }
+        Ok(Type::Bytes | Type::BytesIndef) => Ok(FileContents::Flat(
+            unwrap_bytestrings(bytes)?,
+            DEFAULT_LANGUAGE,
+        )),
+        _ => Ok(FileContents::Flat(bytes, DEFAULT_LANGUAGE)),
+    }
+}
+
+/// Scripts are often wrapped in one or more CBOR bytestrings (cardano-cli wraps them twice),
+/// while flat-encoded programs never start with a bytestring header.
+fn unwrap_bytestrings(mut bytes: Vec<u8>) -> Result<Vec<u8>> {
+    while matches!(
+        minicbor::Decoder::new(&bytes).datatype(),
+        Ok(Type::Bytes | Type::BytesIndef)
+    ) {
+        let inner: ByteVec =
+            minicbor::decode(&bytes).map_err(|e| anyhow!("invalid CBOR bytestring: {}", e))?;
+        bytes = inner.to_vec();
+    }
+    Ok(bytes)
 }
 
 fn fix_names(program: Program<NamedDeBruijn>) -> Result<Program<NamedDeBruijn>> {
@@ -69,66 +332,192 @@ fn load_flat(bytes: &[u8]) -> Result<Program<NamedDeBruijn>> {
     Ok(fake_named_de_bruijn.into())
 }
 
+/// A program which runs on its own, with the machine's default costs and budget
+fn standalone_program(
+    filename: String,
+    program: Program<NamedDeBruijn>,
+    source_map: BTreeMap<u64, SourceLocation>,
+    language: Language,
+) -> LoadedProgram {
+    LoadedProgram {
+        filename,
+        program,
+        source_map,
+        language,
+        is_validator: false,
+        cost_model: CostModel::default(),
+        budget: ExBudget::default(),
+        parameters: vec![],
+        source_root: None,
+        redeemer: None,
+    }
+}
+
 pub async fn load_programs_from_file(
     file: &Path,
-    query: ChainQuery,
-    script_overrides: HashMap<ScriptHash, PlutusScript>,
+    query: &dyn ChainQuery,
+    options: LoadOptions,
 ) -> Result<Vec<LoadedProgram>> {
-    let filename = file.display().to_string();
-    match identify_file_type(file)? {
-        FileType::Uplc => {
-            let code = fs::read_to_string(file)?;
-            let program = parser::program(&code).unwrap().try_into()?;
-            let source_map = BTreeMap::new();
-            Ok(vec![LoadedProgram {
+    let filename = if file == Path::new("-") {
+        "stdin".to_string()
+    } else {
+        file.display().to_string()
+    };
+    let source_map = options
+        .source_map
+        .as_deref()
+        .map(source_map::load)
+        .transpose()?;
+    let budget = options.budget;
+    let contents = identify_file_contents(file, options.format)?;
+    if matches!(budget, StartingBudget::Declared)
+        && !matches!(
+            contents,
+            FileContents::Transaction(..) | FileContents::TransactionId(_)
+        )
+    {
+        bail!("only transactions declare budgets, for their redeemers");
+    }
+    if let FileContents::TransactionId(tx_id) = &contents {
+        query.check_tx_lookup(tx_id[..].into())?;
+    }
+    let mut programs = match contents {
+        FileContents::Uplc(code) => {
+            let program = parser::program(&code)
+                .map_err(|e| anyhow!("could not parse {}: {}", filename, e))?
+                .try_into()?;
+            Ok(vec![standalone_program(
                 filename,
                 program,
-                source_map,
-            }])
+                BTreeMap::new(),
+                DEFAULT_LANGUAGE,
+            )])
         }
-        FileType::Flat => {
-            let bytes = std::fs::read(file)?;
+        FileContents::Flat(bytes, language) => {
             let program = fix_names(load_flat(&bytes)?)?;
-            let source_map = BTreeMap::new();
-            Ok(vec![LoadedProgram {
+            Ok(vec![standalone_program(
                 filename,
                 program,
-                source_map,
-            }])
+                BTreeMap::new(),
+                language,
+            )])
+        }
+        FileContents::AikenExport(export) => {
+            Ok(vec![load_export(filename, export, DEFAULT_LANGUAGE)?])
         }
-        FileType::Json => {
-            let export: AikenExport = serde_json::from_slice(&fs::read(file)?)?;
-            let bytes = hex::decode(&export.compiled_code)?;
-            let cbor: ByteVec = minicbor::decode(&bytes)?;
-            let program = fix_names(load_flat(&cbor)?)?;
-            let source_map = export.source_map.unwrap_or_default();
+        FileContents::Blueprint(blueprint) => {
+            let language = blueprint.language()?;
+            let (validator, parameters) = blueprint.select(options.validator.as_deref())?;
+            Ok(vec![load_validator(
+                filename, validator, parameters, language,
+            )?])
+        }
+        FileContents::AikenProject(dir) => {
+            let program = load_from_project(filename, &dir, options.validator.as_deref())?;
             Ok(vec![LoadedProgram {
-                filename,
-                program,
-                source_map,
+                source_root: Some(dir),
+                ..program
             }])
         }
-        FileType::TransactionId => {
-            let tx_id = hex::decode(file.to_str().unwrap())?;
+        FileContents::TransactionId(tx_id) => {
             let tx_bytes = query.get_tx_bytes(tx_id[..].into()).await?;
-            let multi_era_tx = MintedTx::decode_fragment(&tx_bytes).unwrap();
-            load_programs_from_tx(filename, multi_era_tx, query, script_overrides).await
+            load_programs_from_tx(filename, &tx_bytes, None, query, options).await
+        }
+        FileContents::Transaction(bytes, era) => {
+            load_programs_from_tx(filename, &bytes, era, query, options).await
         }
-        FileType::Transaction => {
-            let bytes = std::fs::read(file)?;
-            let multi_era_tx = MintedTx::decode_fragment(&bytes).unwrap();
-            load_programs_from_tx(filename, multi_era_tx, query, script_overrides).await
+    }?;
+    if let Some(source_map) = source_map {
+        for program in programs.iter_mut() {
+            program.source_map = source_map.clone();
         }
     }
+    if let StartingBudget::Custom(budget) = budget {
+        for program in programs.iter_mut() {
+            program.budget = budget;
+        }
+    }
+    Ok(programs)
+}
+
+fn load_export(filename: String, export: AikenExport, language: Language) -> Result<LoadedProgram> {
+    let bytes = hex::decode(&export.compiled_code)?;
+    let program = fix_names(load_flat(&unwrap_bytestrings(bytes)?)?)?;
+    let source_map = source_map::from_aiken(export.source_map.unwrap_or_default())?;
+    Ok(standalone_program(filename, program, source_map, language))
+}
+
+fn load_validator(
+    filename: String,
+    validator: Validator,
+    parameters: Vec<ParameterSchema>,
+    language: Language,
+) -> Result<LoadedProgram> {
+    let bytes = hex::decode(&validator.compiled_code)
+        .with_context(|| format!("invalid compiled code for {}", validator.title))?;
+    let program = fix_names(load_flat(&unwrap_bytestrings(bytes)?)?)?;
+    let source_map = source_map::from_aiken(validator.source_map.unwrap_or_default())?;
+    let filename = format!("{} ({})", filename, validator.title);
+    Ok(LoadedProgram {
+        parameters,
+        is_validator: true,
+        ..standalone_program(filename, program, source_map, language)
+    })
+}
+
+/// Compiles an aiken project, and loads one of its validators,
+/// or failing that a function named like `module.name`
+fn load_from_project(filename: String, dir: &Path, name: Option<&str>) -> Result<LoadedProgram> {
+    let (project, blueprint) = aiken::compile(dir)?;
+    let language = blueprint.language()?;
+    let validator = match blueprint.select(name) {
+        Ok((validator, parameters)) => {
+            return load_validator(filename, validator, parameters, language);
+        }
+        Err(error) => error,
+    };
+    let Some(name) = name else {
+        return Err(validator);
+    };
+    let export = project.export(name).map_err(|export| {
+        anyhow!(
+            "{} has no validator or function named {}\n{:#}\n{:#}",
+            filename,
+            name,
+            validator,
+            export
+        )
+    })?;
+    let export = serde_json::from_value(export)?;
+    load_export(format!("{} ({})", filename, name), export, language)
 }
 
 async fn load_programs_from_tx(
     filename: String,
-    tx: MintedTx<'_>,
-    query: ChainQuery,
-    script_overrides: HashMap<ScriptHash, PlutusScript>,
+    tx_bytes: &[u8],
+    era: Option<Era>,
+    query: &dyn ChainQuery,
+    options: LoadOptions,
 ) -> Result<Vec<LoadedProgram>> {
-    println!("loading programs from tx");
+    if options.source_map.is_some() {
+        bail!("source maps can only be given for scripts, not transactions");
+    }
+    let mut tx_bytes = transaction::complete(tx_bytes)?;
+    // transactions from before conway decode as conway ones, since the format only grew
+    let era = match era {
+        Some(era) => era,
+        None => transaction::detect_era(&tx_bytes)?,
+    };
+    if let Some(path) = &options.redeemers {
+        tx_bytes = transaction::set_redeemers(&tx_bytes, &transaction::read_redeemers(path)?)?;
+    }
+    if let Some(path) = &options.datums {
+        tx_bytes = transaction::add_datums(&tx_bytes, &transaction::read_datums(path)?)?;
+    }
+    let tx_bytes = tx_bytes.as_slice();
+    let patched_tx_bytes;
+    let tx = transaction::decode(tx_bytes)
+        .with_context(|| format!("in a transaction from the {:?} era", era))?;
     let mut inputs: Vec<_> = tx.transaction_body.inputs.iter().cloned().collect();
     if let Some(ref_inputs) = &tx.transaction_body.reference_inputs {
         for input in ref_inputs.iter() {
@@ -140,36 +529,105 @@ async fn load_programs_from_tx(
             inputs.push(input.clone());
         }
     }
-    let resolved_inputs = query.get_utxos(inputs).await?;
-    let slot_config = query.get_slot_config()?;
-    println!("resolved inputs");
+    let mut resolved_inputs = query.get_utxos(inputs).await?;
+    if era == Era::Alonzo {
+        for resolved in resolved_inputs.iter_mut() {
+            resolved.output = transaction::legacy_output(&resolved.output).with_context(|| {
+                format!(
+                    "invalid output for input {}#{}",
+                    resolved.input.transaction_id, resolved.input.index
+                )
+            })?;
+        }
+    }
+    let missing_datums = transaction::find_missing_datums(&tx, &resolved_inputs, query).await?;
+    let tx = if missing_datums.is_empty() {
+        tx
+    } else {
+        patched_tx_bytes = transaction::add_datums(tx_bytes, &missing_datums)?;
+        transaction::decode(&patched_tx_bytes).context("could not add datums to the transaction")?
+    };
+    let slot_config = query.get_slot_config().await?;
+    let protocol_parameters = match options.protocol_parameters {
+        Some(protocol_parameters) => protocol_parameters,
+        None => query.get_protocol_parameters().await?,
+    };
 
     let mut programs = vec![];
-    for (_, program, _) in
-        tx_to_programs(&tx, &resolved_inputs, &slot_config, script_overrides).unwrap()
-    {
+    let tx_programs = tx_to_programs(
+        &tx,
+        &resolved_inputs,
+        &slot_config,
+        options.script_overrides,
+    )
+    .map_err(|e| anyhow!("could not build the transaction's script contexts: {}", e))?;
+    for (redeemer, program, _) in tx_programs {
+        let program = fix_names(program)?;
+        let (target, script_hash) = transaction::redeemer_target(&tx, &resolved_inputs, &redeemer);
+        let language = script_hash
+            .and_then(|hash| transaction::script_language(&tx, &resolved_inputs, &hash))
+            .with_context(|| {
+                format!(
+                    "could not find the script {} #{} runs",
+                    transaction::purpose_name(redeemer.tag),
+                    redeemer.index
+                )
+            })?;
+        transaction::check_language(era, language)?;
+        let declared = ExBudget {
+            mem: redeemer.ex_units.mem as i64,
+            cpu: redeemer.ex_units.steps as i64,
+        };
+        let budget = match options.budget {
+            StartingBudget::Declared => declared,
+            _ => protocol_parameters.max_tx_budget(),
+        };
         programs.push(LoadedProgram {
             filename: filename.clone(),
-            program: fix_names(program)?,
+            language,
+            is_validator: true,
+            cost_model: protocol_parameters.cost_model(language),
+            budget,
+            program,
             source_map: BTreeMap::new(),
+            parameters: vec![],
+            source_root: None,
+            redeemer: Some(RedeemerInfo {
+                purpose: transaction::purpose_name(redeemer.tag).to_string(),
+                index: redeemer.index,
+                target,
+                script_hash: script_hash.map(|hash| hash.to_string()),
+                declared: declared.into(),
+            }),
         });
     }
+    if programs.is_empty() {
+        bail!(
+            "{} has no redeemers to evaluate, drafts can be given them in a separate file",
+            filename
+        );
+    }
     Ok(programs)
 }
 
+/// Parses a parameter given as hex-encoded CBOR, detailed-schema JSON,
+/// CBOR diagnostic notation, or an `@file` holding any of those
 pub fn parse_parameter(index: usize, parameter: String) -> Result<PlutusData> {
-    let data: PlutusData = {
-        let bytes =
-            hex::decode(parameter).context(format!("could not hex-decode parameter {}", index))?;
-        uplc::plutus_data(&bytes).map_err(|e| {
-            anyhow!(
-                "could not decode plutus data for parameter {}: {}",
-                index,
-                e
-            )
-        })?
-    };
-    Ok(data)
+    plutus_data::parse(&parameter).with_context(|| format!("could not parse parameter {}", index))
+}
+
+/// Parses the parameters to apply to a program.
+/// Those a blueprint describes can also be written as JSON laid out like their schema.
+pub fn parse_parameters(program: &LoadedProgram, parameters: &[String]) -> Result<Vec<PlutusData>> {
+    parameters
+        .iter()
+        .enumerate()
+        .map(|(index, parameter)| match program.parameters.get(index) {
+            Some(schema) => plutus_data::parse_with_schema(parameter, schema)
+                .with_context(|| format!("could not parse parameter {}", index)),
+            None => parse_parameter(index, parameter.clone()),
+        })
+        .collect()
 }
 
 pub fn apply_parameters(
@@ -177,9 +635,19 @@ pub fn apply_parameters(
         filename,
         program,
         source_map,
+        language,
+        is_validator,
+        cost_model,
+        budget,
+        parameters: schemas,
+        source_root,
+        redeemer,
     }: LoadedProgram,
     parameters: Vec<PlutusData>,
 ) -> Result<LoadedProgram> {
+    if !schemas.is_empty() {
+        check_parameters(&filename, &schemas, &parameters)?;
+    }
     let mut program = program;
     let mut source_map_offset = 0;
     for param in parameters {
@@ -196,32 +664,108 @@ pub fn apply_parameters(
         filename,
         program,
         source_map,
+        language,
+        is_validator,
+        cost_model,
+        budget,
+        parameters: vec![],
+        source_root,
+        redeemer,
     })
 }
 
-pub fn execute_program(program: Program<NamedDeBruijn>) -> Result<Vec<(MachineState, ExBudget)>> {
-    let mut machine = Machine::new(
-        program.plutus_version()?,
-        CostModel::default(),
-        ExBudget::default(),
-        1,
-    );
+fn check_parameters(
+    filename: &str,
+    schemas: &[ParameterSchema],
+    parameters: &[PlutusData],
+) -> Result<()> {
+    let name = |index: usize| match &schemas[index].title {
+        Some(title) => format!("{} ({})", index, title),
+        None => index.to_string(),
+    };
+    if schemas.len() != parameters.len() {
+        bail!(
+            "{} expects {} parameter(s): {}, but {} were given",
+            filename,
+            schemas.len(),
+            (0..schemas.len()).map(name).collect::<Vec<_>>().join(", "),
+            parameters.len()
+        );
+    }
+    for (index, (schema, parameter)) in schemas.iter().zip(parameters).enumerate() {
+        schema
+            .check(parameter)
+            .with_context(|| format!("parameter {} doesn't match the blueprint", name(index)))?;
+    }
+    Ok(())
+}
+
+/// Runs a program to completion, keeping every state the machine passed through.
+/// A machine error ends the trace at the last state reached, and is reported in the outcome,
+/// as is a PlutusV3 validator returning anything but unit, which would fail on chain.
+pub fn execute_program(
+    program: Program<NamedDeBruijn>,
+    language: Language,
+    is_validator: bool,
+    cost_model: CostModel,
+    budget: ExBudget,
+) -> Result<(Vec<(MachineState, ExBudget)>, ExecutionOutcome)> {
+    let mut machine = Machine::new(language, cost_model, budget, 1);
     let mut state = machine
         .get_initial_machine_state(program.term)
         .map_err(|err| anyhow!("could not get initial state: {}", err))?;
     let mut states = vec![(state.clone(), machine.ex_budget)];
-    while !matches!(state, MachineState::Done(_)) {
-        state = match machine.step(state) {
-            Ok(state) => state,
-            Err(err) => {
-                eprintln!("Machine Error: {}", err);
-                MachineState::Done(IndexedTerm::Error { index: None })
+    let mut outcome = ExecutionOutcome {
+        starting_budget: budget.into(),
+        ..Default::default()
+    };
+    loop {
+        if let MachineState::Done(term) = &state {
+            outcome.result = Some(term.to_string());
+            let returned_unit =
+                matches!(term, Term::Constant(constant) if **constant == Constant::Unit);
+            if is_validator && language == Language::PlutusV3 && !returned_unit {
+                outcome.error = Some(ExecutionError {
+                    kind: ExecutionErrorKind::NotUnit,
+                    message: format!(
+                        "PlutusV3 validators must return unit, this one returned {}",
+                        term
+                    ),
+                    step: states.len() - 1,
+                });
             }
+            break;
+        }
+        let result = machine.step(state);
+        // logs from a failed step belong to the last state reached, like the error does
+        let step = match result {
+            Ok(_) => states.len(),
+            Err(_) => states.len() - 1,
         };
+        for message in machine.logs.drain(..) {
+            outcome.logs.push(TraceLog { step, message });
+        }
+        match result {
+            Ok(next) => state = next,
+            Err(err) => {
+                outcome.error = Some(ExecutionError {
+                    kind: (&err).into(),
+                    message: err.to_string(),
+                    step: states.len() - 1,
+                });
+                break;
+            }
+        }
         states.push((state.clone(), machine.ex_budget));
     }
+    // the machine's budget goes negative when a step overspends it
+    outcome.spent = ExBudget {
+        cpu: budget.cpu - machine.ex_budget.cpu,
+        mem: budget.mem - machine.ex_budget.mem,
+    }
+    .into();
 
-    Ok(states)
+    Ok((states, outcome))
 }
 
 #[derive(Deserialize, Debug)]
@@ -231,20 +775,286 @@ struct AikenExport {
     source_map: Option<BTreeMap<u64, String>>,
 }
 
-/**
-UTILITY LOGIC
-*/
-pub trait HasPlutusVersion {
-    fn plutus_version(&self) -> Result<Language>;
+/// The JSON wrapper cardano-cli and cardano-serialization-lib use for scripts and transactions
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TextEnvelope {
+    #[serde(rename = "type")]
+    envelope_type: String,
+    cbor_hex: String,
 }
 
-impl<T> HasPlutusVersion for Program<T> {
-    fn plutus_version(&self) -> Result<Language> {
-        match self.version.0 {
-            1 => Ok(Language::PlutusV1),
-            2 => Ok(Language::PlutusV2),
-            3 => Ok(Language::PlutusV3),
-            _ => Err(anyhow!("invalid language version")),
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::chain_query::MockChainQuery;
+
+    fn run(source: &str, language: Language, is_validator: bool) -> ExecutionOutcome {
+        let program: Program<Name> = parser::program(source).unwrap();
+        let program: Program<NamedDeBruijn> = program.try_into().unwrap();
+        let (_, outcome) = execute_program(
+            program,
+            language,
+            is_validator,
+            CostModel::default(),
+            ExBudget::default(),
+        )
+        .unwrap();
+        outcome
+    }
+
+    #[test]
+    fn fails_plutus_v3_validators_which_dont_return_unit() {
+        let outcome = run("(program 1.1.0 (con integer 42))", Language::PlutusV3, true);
+        let error = outcome.error.expect("expected the validator to fail");
+        assert_eq!(error.kind, ExecutionErrorKind::NotUnit);
+        assert_eq!(outcome.result.as_deref(), Some("(con integer 42)"));
+
+        let outcome = run("(program 1.1.0 (con unit ()))", Language::PlutusV3, true);
+        assert!(outcome.succeeded());
+    }
+
+    #[test]
+    fn lets_other_programs_return_anything() {
+        // only PlutusV3 validators have to return unit
+        let source = "(program 1.0.0 (con integer 42))";
+        assert!(run(source, Language::PlutusV2, true).succeeded());
+        // functions exported from a project aren't validators at all
+        assert!(run(source, Language::PlutusV3, false).succeeded());
+    }
+
+    #[test]
+    fn numbers_terms_in_pre_order_from_before_parameters_are_applied() {
+        // 0 is the lambda, 1 its body
+        let program: Program<Name> = parser::program("(program 1.1.0 (lam p p))").unwrap();
+        let at = |line| SourceLocation {
+            file: "p.ts".to_string(),
+            line,
+            column: 1,
+            end_line: None,
+            end_column: None,
+            function: None,
+        };
+        let source_map = BTreeMap::from([(0, at(1)), (1, at(2))]);
+        let program = standalone_program(
+            "p".to_string(),
+            program.try_into().unwrap(),
+            source_map,
+            Language::PlutusV3,
+        );
+        let program = apply_parameters(program, vec![plutus_data::parse("7").unwrap()]).unwrap();
+        // applying the parameter wraps the program in another term, numbered 0
+        assert_eq!(program.source_map, BTreeMap::from([(1, at(1)), (2, at(2))]));
+
+        let (states, outcome) = execute_program(
+            program.program,
+            program.language,
+            false,
+            CostModel::default(),
+            ExBudget::default(),
+        )
+        .unwrap();
+        assert!(outcome.succeeded());
+        // the application, the lambda, its argument (numbered after the lambda's body),
+        // then the body
+        let computed: Vec<_> = states
+            .iter()
+            .filter_map(|(state, _)| match state {
+                MachineState::Compute(_, _, term) => term.index(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(computed, vec![0, 1, 3, 2]);
+    }
+
+    fn envelope_era(envelope_type: &str) -> Option<Era> {
+        let envelope = json!({ "type": envelope_type, "description": "", "cborHex": "84a0a0f5f6" });
+        match identify_json(&envelope.to_string()).unwrap() {
+            FileContents::Transaction(_, era) => era,
+            _ => panic!("expected {} to be read as a transaction", envelope_type),
         }
     }
+
+    #[test]
+    fn takes_the_era_from_text_envelopes() {
+        assert_eq!(envelope_era("Tx AlonzoEra"), Some(Era::Alonzo));
+        // the envelope settles what the encoding can't,
+        // like Babbage transactions which only use what Alonzo had
+        assert_eq!(envelope_era("Witnessed Tx BabbageEra"), Some(Era::Babbage));
+        assert_eq!(envelope_era("Unwitnessed Tx ConwayEra"), Some(Era::Conway));
+        assert_eq!(envelope_era("TxBodyBabbage"), Some(Era::Babbage));
+        assert_eq!(envelope_era("TxBody"), None);
+
+        let bytes = hex::decode("84a0a0f5f6").unwrap();
+        assert!(matches!(
+            identify_binary(bytes).unwrap(),
+            FileContents::Transaction(_, None)
+        ));
+    }
+
+    /// A blueprint with two spend validators and a mint validator,
+    /// where `pool.pool.spend` takes an owner
+    fn blueprint_file(name: &str) -> PathBuf {
+        let compiled = |source: &str| {
+            let program: Program<Name> = parser::program(source).unwrap();
+            let program: Program<DeBruijn> = program.try_into().unwrap();
+            hex::encode(program.to_cbor().unwrap())
+        };
+        let takes_an_owner = compiled("(program 1.1.0 (lam owner (lam context (con unit ()))))");
+        let always_succeeds = compiled("(program 1.1.0 (lam context (con unit ())))");
+        let blueprint = json!({
+            "preamble": { "title": "test/pool", "plutusVersion": "v3" },
+            "validators": [
+                {
+                    "title": "pool.pool.spend",
+                    "compiledCode": takes_an_owner,
+                    "parameters": [{ "title": "owner", "schema": { "dataType": "bytes" } }],
+                },
+                {
+                    "title": "oracle.oracle.spend",
+                    "compiledCode": always_succeeds,
+                },
+                {
+                    "title": "pool.pool.mint",
+                    "compiledCode": always_succeeds,
+                },
+            ],
+        });
+        let path =
+            std::env::temp_dir().join(format!("gastronomy-{}-{}.json", name, std::process::id()));
+        fs::write(&path, blueprint.to_string()).unwrap();
+        path
+    }
+
+    async fn load_validator_titled(name: &str, title: Option<&str>) -> Result<Vec<LoadedProgram>> {
+        let path = blueprint_file(name);
+        let options = LoadOptions {
+            validator: title.map(str::to_string),
+            ..Default::default()
+        };
+        let programs = load_programs_from_file(&path, &MockChainQuery::new(), options).await;
+        fs::remove_file(&path).unwrap();
+        programs
+    }
+
+    #[tokio::test]
+    async fn picks_validators_by_title() {
+        let programs = load_validator_titled("by-title", Some("oracle.spend"))
+            .await
+            .unwrap();
+        assert_eq!(programs.len(), 1);
+        assert!(programs[0].filename.ends_with("(oracle.oracle.spend)"));
+        assert!(programs[0].is_validator);
+        assert_eq!(programs[0].language, Language::PlutusV3);
+
+        let programs = load_validator_titled("full-title", Some("pool.pool.mint"))
+            .await
+            .unwrap();
+        assert!(programs[0].filename.ends_with("(pool.pool.mint)"));
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_and_ambiguous_titles() {
+        let Err(error) = load_validator_titled("unknown", Some("pool.withdraw")).await else {
+            panic!("expected an unknown title to be an error");
+        };
+        assert_eq!(
+            error.to_string(),
+            "no validator titled pool.withdraw in the blueprint, expected one of: \
+            pool.pool.spend, oracle.oracle.spend, pool.pool.mint"
+        );
+
+        let Err(error) = load_validator_titled("ambiguous", Some("spend")).await else {
+            panic!("expected an ambiguous title to be an error");
+        };
+        assert!(
+            error
+                .to_string()
+                .starts_with("several validators match spend"),
+            "{}",
+            error
+        );
+
+        let Err(error) = load_validator_titled("untitled", None).await else {
+            panic!("expected a missing title to be an error");
+        };
+        assert!(
+            error
+                .to_string()
+                .starts_with("the blueprint has several validators"),
+            "{}",
+            error
+        );
+    }
+
+    /// Copies the fixture Aiken project somewhere else, since building it writes to it
+    fn aiken_project(name: &str) -> PathBuf {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_data/aiken_project");
+        let dir = std::env::temp_dir().join(format!("gastronomy-{}-{}", name, std::process::id()));
+        for file in ["aiken.toml", "lib/pool/math.ak"] {
+            fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            fs::copy(fixture.join(file), dir.join(file)).unwrap();
+        }
+        dir
+    }
+
+    async fn load_from_aiken_project(dir: &Path, name: &str) -> Result<Vec<LoadedProgram>> {
+        let options = LoadOptions {
+            validator: Some(name.to_string()),
+            ..Default::default()
+        };
+        load_programs_from_file(dir, &MockChainQuery::new(), options).await
+    }
+
+    #[tokio::test]
+    async fn exports_functions_from_aiken_projects() {
+        let dir = aiken_project("aiken-project");
+        let programs = load_from_aiken_project(&dir, "pool/math.swap_amount").await;
+        let tests =
+            load_from_aiken_project(&dir, "pool/math.swap_pays_less_than_the_reserve").await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        let programs = programs.unwrap();
+        assert_eq!(programs.len(), 1);
+        let program = &programs[0];
+        assert!(program.filename.ends_with("(pool/math.swap_amount)"));
+        assert!(!program.is_validator);
+        assert_eq!(program.source_root.as_deref(), Some(dir.as_path()));
+        assert!(!program.source_map.is_empty());
+
+        // aiken can only export functions
+        let Err(error) = tests else {
+            panic!("expected exporting a test to be an error");
+        };
+        assert!(
+            error
+                .to_string()
+                .contains("has no validator or function named pool/math.swap_pays_less"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_missing_parameters() {
+        let mut programs = load_validator_titled("parameters", Some("pool.spend"))
+            .await
+            .unwrap();
+        let program = programs.remove(0);
+        assert_eq!(program.parameters.len(), 1);
+
+        let arguments = parse_parameters(&program, &[]).unwrap();
+        let Err(error) = apply_parameters(program, arguments) else {
+            panic!("expected the missing owner to be an error");
+        };
+        assert!(
+            error
+                .to_string()
+                .ends_with("expects 1 parameter(s): 0 (owner), but 0 were given"),
+            "{}",
+            error
+        );
+    }
 }
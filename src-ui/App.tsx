@@ -10,6 +10,7 @@ function App() {
   const [displayDebugger, setDisplayDebugger] = useState(false);
   const [displaySettings, setDisplaySettings] = useState(false);
   const [parameters, setParameters] = useState<string[]>([]);
+  const [validator, setValidator] = useState("");
   const [file, setFile] = useState("");
 
   const fileName = useMemo(() => {
@@ -25,6 +26,7 @@ function App() {
     setDisplayDebugger(false);
     setFile("");
     setParameters([]);
+    setValidator("");
   };
 
   return (
@@ -39,6 +41,7 @@ function App() {
           fileName={fileName}
           onQuit={onQuit}
           parameters={parameters}
+          validator={validator}
         />
       ) : (
         <div className="p-4 flex justify-center items-center h-full flex-col gap-11">
@@ -48,6 +51,19 @@ function App() {
           </h1>
           <div className="border border-lime-600 p-6 flex flex-col gap-6 w-[30rem]">
             <FilePicker setFile={setFile} fileName={fileName} />
+            <div className="flex flex-col gap-4">
+              <label htmlFor="validator" className="cursor-pointer">
+                Validator:
+              </label>
+              <input
+                className="p-2 w-full bg-slate-900 text-white border border-slate-800 rounded focus:outline-none"
+                id="validator"
+                name="validator"
+                onChange={(event) => setValidator(event.target.value)}
+                placeholder="The title of a validator, when opening a blueprint with several"
+                value={validator}
+              />
+            </div>
             <div className="flex flex-col gap-4">
               <label htmlFor="parameters" className="cursor-pointer">
                 Parameters:
@@ -57,7 +73,7 @@ function App() {
                 id="parameters"
                 name="parameters"
                 onChange={handleChange}
-                placeholder="Enter parameters, each on a new line"
+                placeholder="Enter parameters, each on a new line, as CBOR hex, JSON, CBOR diagnostic notation or @file"
                 value={parameters?.join("\n")}
               />
             </div>
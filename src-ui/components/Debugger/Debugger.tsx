@@ -3,11 +3,17 @@ import { invoke } from "@tauri-apps/api/core";
 import { message, open } from "@tauri-apps/plugin-dialog";
 import cx from "classnames";
 import {
+  IExecutionOutcome,
   IFrame,
   IFrameResponse,
+  ILogsResponse,
+  IRedeemerInfo,
+  ISourceLocation,
   ISourceResponse,
   ISummaryResponse,
+  ITraceLog,
   ITraceResponse,
+  TExecutionErrorKind,
 } from "../../types";
 import DisplayString from "../DisplayString";
 import Modal from "../Modal";
@@ -15,17 +21,46 @@ import DebuggerNavigation from "../DebuggerNavigation";
 import { TbFaceIdError } from "react-icons/tb";
 import { Triangle } from "react-loader-spinner";
 
+const formatLocation = (location: ISourceLocation) => {
+  const start = `${location.file}:${location.line}:${location.column}`;
+  const { endLine, endColumn } = location;
+  if (endLine === null || endColumn === null) return start;
+  if (endLine === location.line && endColumn === location.column) return start;
+  return `${start}-${endLine}:${endColumn}`;
+};
+
+const formatRedeemer = ({ purpose, index, target }: IRedeemerInfo) =>
+  target ? `${purpose} #${index} (${target})` : `${purpose} #${index}`;
+
+const formatHeadroom = (declared: number, spent: number) => {
+  if (declared === 0) return "";
+  const headroom = ((declared - spent) * 100) / declared;
+  return headroom >= 0
+    ? `(${headroom.toFixed(1)}% left)`
+    : `(${(-headroom).toFixed(1)}% over)`;
+};
+
+const errorKinds: Record<TExecutionErrorKind, string> = {
+  explicitError: "Explicit error",
+  budgetExceeded: "Budget exceeded",
+  typeMismatch: "Type mismatch",
+  notUnit: "Didn't return unit",
+  other: "Machine error",
+};
+
 interface IDebuggerProps {
   file: string;
   fileName: string;
   onQuit: () => void;
   parameters: string[];
+  validator: string;
 }
 
 const Debugger: FC<IDebuggerProps> = ({
   onQuit,
   file,
   parameters,
+  validator,
   fileName,
 }) => {
   const [identifiers, setIdentifiers] = useState<string[] | undefined>(
@@ -38,6 +73,11 @@ const Debugger: FC<IDebuggerProps> = ({
   const [viewSource, setViewSource] = useState(false);
   const [frameCount, setFrameCount] = useState<number>(0);
   const [sourceTokenIndices, setSourceTokenIndices] = useState<number[]>([]);
+  const [outcome, setOutcome] = useState<IExecutionOutcome | undefined>(
+    undefined,
+  );
+  const [redeemer, setRedeemer] = useState<IRedeemerInfo | null>(null);
+  const [logs, setLogs] = useState<ITraceLog[]>([]);
   const [sourceFiles, setSourceFiles] = useState<Record<string, string>>({});
   const [currentFrame, setCurrentFrame] = useState<IFrame | undefined>(
     undefined,
@@ -56,7 +96,7 @@ const Debugger: FC<IDebuggerProps> = ({
   }, [currentFrameIndex, identifier]);
 
   const displayLabel = (frameIndex: number) => {
-    if (frameIndex === frameCount - 1) return "Done";
+    if (frameIndex === frameCount - 1) return outcome?.error ? "Error" : "Done";
     if (frameIndex === frameCount) return "None";
     if (currentFrame?.retValue) return "Return";
     return "Compute";
@@ -95,6 +135,11 @@ const Debugger: FC<IDebuggerProps> = ({
     });
   }, [viewSource, sourceTokenIndices]);
 
+  const handleNextLog = useCallback(() => {
+    const next = logs.find(({ step }) => step > currentFrameIndex);
+    if (next) setCurrentFrameIndex(next.step);
+  }, [logs, currentFrameIndex]);
+
   const handleNextTrace = useCallback(() => {
     if (!identifiers) return;
     const currentIndex = identifiers.indexOf(identifier ?? "");
@@ -105,12 +150,13 @@ const Debugger: FC<IDebuggerProps> = ({
   const [sourceText, sourcePos] = useMemo(() => {
     const location = currentFrame?.location;
     if (!location) return [null, null];
-    const [file, line, column] = location.split(":");
     const sourcePos = {
-      line: Number(line),
-      column: Number(column),
+      line: location.line,
+      column: location.column,
+      endLine: location.endLine ?? location.line,
+      endColumn: location.endColumn ?? location.column,
     };
-    return [sourceFiles[file] || null, sourcePos];
+    return [sourceFiles[location.file] || null, sourcePos];
   }, [currentFrame, sourceFiles]);
 
   const handleViewSource = useCallback(async () => {
@@ -121,8 +167,7 @@ const Debugger: FC<IDebuggerProps> = ({
     }
     const location = currentFrame?.location;
     if (!location) return;
-    const [file] = location.split(":");
-    if (!sourceFiles[file]) {
+    if (!sourceFiles[location.file]) {
       await message("Please select the root directory of your app.");
       const sourceRoot = await open({
         title: "Open Aiken source root",
@@ -158,9 +203,18 @@ const Debugger: FC<IDebuggerProps> = ({
         handleNextTrace();
       } else if (event.key === "v") {
         handleViewSource();
+      } else if (event.key === "l") {
+        handleNextLog();
       }
     },
-    [handleNext, handlePrevious, handleQuit, handleNextTrace, handleViewSource],
+    [
+      handleNext,
+      handlePrevious,
+      handleQuit,
+      handleNextTrace,
+      handleViewSource,
+      handleNextLog,
+    ],
   );
 
   useEffect(() => {
@@ -171,11 +225,12 @@ const Debugger: FC<IDebuggerProps> = ({
   }, [handleKeyPress]);
 
   const fetchIdentifiers = useCallback(
-    async (file: string, parameters: string[]) => {
+    async (file: string, parameters: string[], validator: string) => {
       try {
         const { identifiers } = await invoke<ITraceResponse>("create_traces", {
           file,
           parameters,
+          validator: validator || null,
         });
         setIdentifiers(identifiers);
         const identifier = identifiers[0];
@@ -188,19 +243,21 @@ const Debugger: FC<IDebuggerProps> = ({
   );
 
   useEffect(() => {
-    fetchIdentifiers(file, parameters);
-  }, [fetchIdentifiers, file, parameters]);
+    fetchIdentifiers(file, parameters, validator);
+  }, [fetchIdentifiers, file, parameters, validator]);
 
   const fetchFrames = useCallback(async (identifier: string) => {
     try {
-      const { frameCount, sourceTokenIndices } = await invoke<ISummaryResponse>(
-        "get_trace_summary",
-        {
+      const { frameCount, sourceTokenIndices, outcome, redeemer } =
+        await invoke<ISummaryResponse>("get_trace_summary", {
           identifier,
-        },
-      );
+        });
       setFrameCount(frameCount);
       setSourceTokenIndices(sourceTokenIndices);
+      setOutcome(outcome);
+      setRedeemer(redeemer);
+      const { logs } = await invoke<ILogsResponse>("get_logs", { identifier });
+      setLogs(logs);
       setSourceFiles({});
       setCurrentFrameIndex(0);
       setIsModalOpen(false);
@@ -307,6 +364,55 @@ const Debugger: FC<IDebuggerProps> = ({
                 </span>
               </div>
             </div>
+            {outcome && (
+              <div
+                className={cx(
+                  "pt-2 text-center truncate",
+                  outcome.error ? "text-red-600" : "text-lime-600",
+                )}
+              >
+                {outcome.error
+                  ? `${errorKinds[outcome.error.kind]} at step ${outcome.error.step}: ${outcome.error.message}`
+                  : `Result: ${outcome.result}`}
+              </div>
+            )}
+            {redeemer && outcome && (
+              <div className="pt-2 flex justify-between">
+                <div className="truncate">
+                  Redeemer:{" "}
+                  <span className="text-blue-600">
+                    {formatRedeemer(redeemer)}
+                  </span>
+                  {redeemer.scriptHash && (
+                    <>
+                      {" "}
+                      Script:{" "}
+                      <span className="text-blue-600">
+                        {redeemer.scriptHash}
+                      </span>
+                    </>
+                  )}
+                </div>
+                <div className="flex gap-2">
+                  Declared:
+                  <div className="flex gap-2">
+                    <span className="text-blue-600">
+                      {redeemer.declared.steps} steps
+                    </span>
+                    {formatHeadroom(
+                      redeemer.declared.steps,
+                      outcome.spent.steps,
+                    )}
+                  </div>
+                  <div className="flex gap-2">
+                    <span className="text-blue-600">
+                      {redeemer.declared.mem} mem
+                    </span>
+                    {formatHeadroom(redeemer.declared.mem, outcome.spent.mem)}
+                  </div>
+                </div>
+              </div>
+            )}
           </div>
           <div className="grid grid-cols-2 h-full border-t border-lime-600">
             <div className="relative border-r border-lime-600">
@@ -332,7 +438,14 @@ const Debugger: FC<IDebuggerProps> = ({
                       Source Location
                     </h2>
                     <div className="p-4 overflow-auto relative inset-0">
-                      <DisplayString string={currentFrame.location} />
+                      {currentFrame.location.function && (
+                        <span className="text-blue-600">
+                          {currentFrame.location.function} ›{" "}
+                        </span>
+                      )}
+                      <DisplayString
+                        string={formatLocation(currentFrame.location)}
+                      />
                     </div>
                     <div className="left-2 -bottom-2 bg-slate-950 absolute px-2 z-10">
                       <button className="hover:underline">
@@ -348,7 +461,12 @@ const Debugger: FC<IDebuggerProps> = ({
               <h2 className="left-2 -top-3 bg-slate-950 absolute px-2 z-10">
                 Context
               </h2>
-              <div className="h-full grid grid-rows-2">
+              <div
+                className={cx(
+                  "h-full grid",
+                  logs.length ? "grid-rows-3" : "grid-rows-2",
+                )}
+              >
                 <div className="relative">
                   <div className="p-4 overflow-auto absolute inset-0">
                     {currentFrame?.context.map((c, i) => (
@@ -372,6 +490,30 @@ const Debugger: FC<IDebuggerProps> = ({
                     </div>
                   </div>
                 </div>
+                {logs.length > 0 && (
+                  <div className="p-3 border-t border-lime-600 relative">
+                    <h2 className="left-2 -top-3 bg-slate-950 absolute px-2 z-10">
+                      Logs <span className="text-blue-600">{"<L>"}</span>
+                    </h2>
+                    <div className="relative h-full">
+                      <div className="p-4 overflow-auto absolute inset-0">
+                        {logs.map(({ step, message }, i) => (
+                          <button
+                            key={i}
+                            className={cx(
+                              "block text-left hover:underline",
+                              step === currentFrameIndex && "text-yellow-500",
+                              step > currentFrameIndex && "text-slate-500",
+                            )}
+                            onClick={() => setCurrentFrameIndex(step)}
+                          >
+                            {step}: {message}
+                          </button>
+                        ))}
+                      </div>
+                    </div>
+                  </div>
+                )}
               </div>
             </div>
           </div>
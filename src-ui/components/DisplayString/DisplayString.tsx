@@ -1,19 +1,33 @@
 import { FC, Fragment } from "react";
 
+interface IHighlight {
+  line: number;
+  column: number;
+  endLine: number;
+  endColumn: number;
+}
+
 interface IDisplayStringProps {
   string?: string | null;
-  highlight?: { line: number; column: number } | null;
+  highlight?: IHighlight | null;
 }
 
 const DisplayString: FC<IDisplayStringProps> = ({ string, highlight }) => {
   if (!string) return null;
 
   const formattedCode = string.split("\n").map((line, index) => {
-    if (index + 1 === highlight?.line) {
-      const col = highlight.column - 1;
-      const before = line.substring(0, col);
-      const at = line[col];
-      const after = line.substring(col + 1);
+    const lineNumber = index + 1;
+    if (
+      highlight &&
+      lineNumber >= highlight.line &&
+      lineNumber <= highlight.endLine
+    ) {
+      const start = lineNumber === highlight.line ? highlight.column - 1 : 0;
+      const end =
+        lineNumber === highlight.endLine ? highlight.endColumn : line.length;
+      const before = line.substring(0, start);
+      const at = line.substring(start, Math.max(end, start + 1));
+      const after = line.substring(Math.max(end, start + 1));
       return (
         <div key={index} className="bg-slate-800">
           {before}
@@ -11,10 +11,25 @@ const FilePicker: FC<IFilePickerProps> = ({ setFile, fileName }) => {
     const selectedFile = await open({
       multiple: false,
       filters: [
-        { name: "Executable UPLC", extensions: ["uplc", "flat", "tx", "json"] },
+        {
+          name: "Executable UPLC",
+          extensions: [
+            "uplc",
+            "flat",
+            "tx",
+            "json",
+            "plutus",
+            "signed",
+            "raw",
+            "cbor",
+            "hex",
+          ],
+        },
         { name: "UPLC Files", extensions: ["uplc"] },
         { name: "Flat Files", extensions: ["flat"] },
-        { name: "Transaction Files", extensions: ["tx"] },
+        { name: "Transaction Files", extensions: ["tx", "signed", "raw"] },
+        { name: "Text Envelopes", extensions: ["plutus", "json"] },
+        { name: "CBOR Files", extensions: ["cbor", "hex"] },
         { name: "JSON Files", extensions: ["json"] },
       ],
     });
@@ -35,6 +35,22 @@ const Settings: FC<ISettingsProps> = ({ isOpen, onClose }) => {
           }
         }
 
+        if (updated === "network") {
+          if (value) {
+            newConfig.network = value;
+          } else {
+            delete newConfig.network;
+          }
+        }
+
+        if (updated === "budget") {
+          if (value) {
+            newConfig.budget = value;
+          } else {
+            delete newConfig.budget;
+          }
+        }
+
         return newConfig;
       });
     },
@@ -60,6 +76,37 @@ const Settings: FC<ISettingsProps> = ({ isOpen, onClose }) => {
     }
   };
 
+  const handleUtxosFileSelect = async () => {
+    try {
+      const selectedPath = await open({
+        multiple: false,
+        filters: [
+          { name: "UTxO Files", extensions: ["json", "cbor", "hex"] },
+        ],
+      });
+
+      if (selectedPath && typeof selectedPath === "string") {
+        setConfig((oldConfig) => {
+          if (!oldConfig) return null;
+          return { ...oldConfig, utxosFile: selectedPath };
+        });
+        saveSettings();
+      }
+    } catch (error) {
+      console.error("Error selecting UTxO file:", error);
+    }
+  };
+
+  const clearUtxosFile = () => {
+    setConfig((oldConfig) => {
+      if (!oldConfig) return null;
+      const newConfig = { ...oldConfig };
+      delete newConfig.utxosFile;
+      return newConfig;
+    });
+    saveSettings();
+  };
+
   const handleScriptOverrideChange = useCallback(
     (index: number, field: keyof IScriptOverride, value: string) => {
       setConfig((oldConfig) => {
@@ -179,6 +226,64 @@ const Settings: FC<ISettingsProps> = ({ isOpen, onClose }) => {
           />
         </div>
 
+        <div className="mb-6">
+          <label htmlFor="network">Network</label>
+          <input
+            id="network"
+            name="network"
+            type="text"
+            className="my-2 w-full"
+            placeholder="Inferred from the Blockfrost API Key"
+            value={config?.network || ""}
+            onChange={onSettingChanged}
+            onBlur={saveSettings}
+          />
+        </div>
+
+        <div className="mb-6">
+          <label htmlFor="budget">Budget</label>
+          <input
+            id="budget"
+            name="budget"
+            type="text"
+            className="my-2 w-full"
+            placeholder="max, declared, or STEPS,MEM"
+            value={config?.budget || ""}
+            onChange={onSettingChanged}
+            onBlur={saveSettings}
+          />
+        </div>
+
+        <div className="mb-6">
+          <label htmlFor="utxosFile">UTxO File</label>
+          <div className="flex items-center my-2">
+            <input
+              id="utxosFile"
+              type="text"
+              value={config?.utxosFile || ""}
+              readOnly
+              className="flex-grow mr-2 pl-1"
+              placeholder="Query the chain for transaction inputs"
+            />
+            {config?.utxosFile && (
+              <button
+                type="button"
+                onClick={clearUtxosFile}
+                className="mr-2 text-red-500 hover:text-red-400"
+              >
+                Clear
+              </button>
+            )}
+            <button
+              type="button"
+              onClick={handleUtxosFileSelect}
+              className="px-3 py-1 bg-blue-600 text-white rounded hover:bg-blue-700"
+            >
+              Select UTxO File
+            </button>
+          </div>
+        </div>
+
         <div className="mb-6">
           <h2 className="text-xl font-semibold mb-3">
             Blueprint Configuration
@@ -10,29 +10,83 @@ interface IEnv {
   value: string;
 }
 
+export interface ISourceLocation {
+  file: string;
+  line: number;
+  column: number;
+  endLine: number | null;
+  endColumn: number | null;
+  function: string | null;
+}
+
 export interface IFrame {
   budget: IBudget;
   context: string[];
   label: string;
   retValue: string | null;
   term: string;
-  location: string | null;
+  location: ISourceLocation | null;
   env: IEnv[];
+  logs: string[];
 }
 
 export interface ITraceResponse {
   identifiers: string[];
 }
 
+export type TExecutionErrorKind =
+  | "explicitError"
+  | "budgetExceeded"
+  | "typeMismatch"
+  | "notUnit"
+  | "other";
+
+export interface IExecutionError {
+  kind: TExecutionErrorKind;
+  message: string;
+  step: number;
+}
+
+export interface IExUnits {
+  steps: number;
+  mem: number;
+}
+
+export interface IExecutionOutcome {
+  result: string | null;
+  error: IExecutionError | null;
+  startingBudget: IExUnits;
+  spent: IExUnits;
+}
+
+export interface IRedeemerInfo {
+  purpose: string;
+  index: number;
+  target: string | null;
+  scriptHash: string | null;
+  declared: IExUnits;
+}
+
 export interface ISummaryResponse {
   frameCount: number;
   sourceTokenIndices: number[];
+  outcome: IExecutionOutcome;
+  redeemer: IRedeemerInfo | null;
 }
 
 export interface IFrameResponse {
   frame: IFrame;
 }
 
+export interface ITraceLog {
+  step: number;
+  message: string;
+}
+
+export interface ILogsResponse {
+  logs: ITraceLog[];
+}
+
 export interface ISourceResponse {
   files: Record<string, string>;
 }
@@ -47,7 +101,10 @@ export interface IScriptOverride {
 }
 
 export interface ISettings {
+  network?: string;
   blockfrost?: IBlockfrostSettings;
   blueprintFile?: string;
   scriptOverrides?: IScriptOverride[];
+  utxosFile?: string;
+  budget?: string;
 }